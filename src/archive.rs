@@ -1,16 +1,22 @@
-use std::alloc::Layout;
+use std::{alloc::Layout, collections::BTreeMap, ptr::NonNull};
 
 use bytemuck::{Pod, Zeroable};
 use camino::Utf8Path;
+use smash_hash::Hash40;
 
 use crate::{
-    containers::{BucketLookup, IndexLookup, Table, TableMut, TableRef, TableSliceRef},
+    containers::{
+        Bucket, BucketLookup, IndexLookup, LookupSnapshot, Table, TableMut, TableRef,
+        TableSliceMut, TableSliceRef,
+    },
     data::{
-        FileData, FileDescriptor, FileEntity, FileGroup, FileInfo, FilePackage, FilePackageChild,
-        FilePath, IntoHash, SearchFolder, SearchPath, SearchPathLink, StreamData, StreamEntity,
-        StreamFolder, StreamPath,
+        FileData, FileDescriptor, FileEntity, FileGroup, FileInfo, FileInfoFlags, FilePackage,
+        FilePackageChild, FilePath, HashWithData, IntoHash, SearchFolder, SearchPath,
+        SearchPathLink, StreamData, StreamEntity, StreamFileFlags, StreamFolder, StreamPath,
+        TryFilePathResult, MAX_SEARCH_PATH_CHAIN,
     },
-    HashDisplay,
+    hash_interner::HashMemorySlab,
+    packages, HashDisplay,
 };
 
 #[repr(C)]
@@ -40,6 +46,47 @@ fn read_compressed_at_offset(file_nx: &mut *mut FileNX, offset: usize) -> *mut u
 #[skyline::from_offset(0x37c6940)]
 fn read_into_ptr(file_nx: *mut FileNX, buffer: *mut u8, size: usize) -> usize;
 
+/// Owns one of the game's file handles
+///
+/// TODO: This needs a `Drop` impl that closes and frees the [`FileNX`] through the game's
+/// finalize routine, which hasn't been located yet. Until then the handle is leaked when this is
+/// dropped, including when opening or reading fails.
+struct FileNXHandle(*mut FileNX);
+
+impl FileNXHandle {
+    fn new() -> Self {
+        let mut file: *mut FileNX = std::ptr::null_mut();
+        // SAFETY: `init_file` only writes the newly created handle to `file`
+        unsafe { init_file(&mut file) };
+        Self(file)
+    }
+
+    /// Opens `path` on the romfs, returning `false` if the game failed to open it
+    fn open(&mut self, path: &str) -> bool {
+        // The game expects a fixed size path buffer with the open mode at the end
+        let mut buffer = [0u8; 0x108];
+        assert!(path.len() < 0x100, "Path {path} is too long to open");
+        buffer[0x100..0x108].copy_from_slice(bytemuck::bytes_of(&0xdu64));
+        buffer[..path.len()].copy_from_slice(path.as_bytes());
+        // SAFETY: The handle was initialized by `init_file` and the buffer is null terminated
+        unsafe { open_file(&mut self.0, buffer.as_ptr().cast()) }
+    }
+
+    /// Reads `buffer.len()` bytes from the current position, returning how many were read
+    fn read_into(&mut self, buffer: &mut [u8]) -> usize {
+        // SAFETY: The buffer is valid for writes of its length
+        unsafe { read_into_ptr(self.0, buffer.as_mut_ptr(), buffer.len()) }
+    }
+
+    /// Reads and decompresses the table at `offset`, returning [`None`] if the game failed to read it
+    ///
+    /// The returned buffer is allocated by the game's allocator and is owned by the caller
+    fn read_compressed_at_offset(&mut self, offset: usize) -> Option<NonNull<u8>> {
+        // SAFETY: The handle was initialized by `init_file`
+        NonNull::new(unsafe { read_compressed_at_offset(&mut self.0, offset) })
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 pub struct ArchiveMetadata {
@@ -66,6 +113,28 @@ pub struct SearchTableHeader {
     path_count: u32,
 }
 
+impl SearchTableHeader {
+    /// Compares every field except for the padding
+    pub fn semantically_eq(&self, other: &Self) -> bool {
+        self.search_data_size == other.search_data_size
+            && self.folder_count == other.folder_count
+            && self.path_link_count == other.path_link_count
+            && self.path_count == other.path_count
+    }
+
+    /// Checks that the tables described by the counts fit in `search_data_size`
+    pub fn is_valid(&self) -> bool {
+        let lookup_size = std::mem::size_of::<HashWithData>() as u64;
+        let tables_size = self.folder_count as u64
+            * (lookup_size + std::mem::size_of::<SearchFolder>() as u64)
+            + self.path_link_count as u64
+                * (lookup_size + std::mem::size_of::<SearchPathLink>() as u64)
+            + self.path_count as u64 * std::mem::size_of::<SearchPath>() as u64;
+
+        std::mem::size_of::<Self>() as u64 + tables_size <= self.search_data_size as u64
+    }
+}
+
 #[allow(unused)]
 const REGION_COUNT: usize = 5;
 const LOCALE_COUNT: usize = 14;
@@ -115,6 +184,127 @@ pub struct ResourceTableHeader {
     pub stream_data_count: u32,
 }
 
+impl ResourceTableHeader {
+    /// Compares every field except for the padding
+    pub fn semantically_eq(&self, other: &Self) -> bool {
+        // Destructured so that a new field can't be added without deciding if it is compared
+        let Self {
+            resource_data_size,
+            file_path_count,
+            file_entity_count,
+            file_package_count,
+            file_data_group_count,
+            file_package_child_count,
+            file_package_info_count,
+            file_package_desc_count,
+            file_package_data_count,
+            file_info_group_count,
+            file_group_info_count,
+            padding_1: _,
+            locale_count,
+            region_count,
+            padding_2: _,
+            version_patch,
+            version_minor,
+            version_major,
+            versioned_file_group_count,
+            versioned_file_count,
+            padding_3: _,
+            versioned_file_info_count,
+            versioned_file_desc_count,
+            versioned_file_data_count,
+            locale_hash_to_region,
+            stream_folder_count,
+            stream_path_count,
+            stream_entity_count,
+            stream_data_count,
+        } = *self;
+
+        resource_data_size == other.resource_data_size
+            && file_path_count == other.file_path_count
+            && file_entity_count == other.file_entity_count
+            && file_package_count == other.file_package_count
+            && file_data_group_count == other.file_data_group_count
+            && file_package_child_count == other.file_package_child_count
+            && file_package_info_count == other.file_package_info_count
+            && file_package_desc_count == other.file_package_desc_count
+            && file_package_data_count == other.file_package_data_count
+            && file_info_group_count == other.file_info_group_count
+            && file_group_info_count == other.file_group_info_count
+            && locale_count == other.locale_count
+            && region_count == other.region_count
+            && version_patch == other.version_patch
+            && version_minor == other.version_minor
+            && version_major == other.version_major
+            && versioned_file_group_count == other.versioned_file_group_count
+            && versioned_file_count == other.versioned_file_count
+            && versioned_file_info_count == other.versioned_file_info_count
+            && versioned_file_desc_count == other.versioned_file_desc_count
+            && versioned_file_data_count == other.versioned_file_data_count
+            && locale_hash_to_region == other.locale_hash_to_region
+            && stream_folder_count == other.stream_folder_count
+            && stream_path_count == other.stream_path_count
+            && stream_entity_count == other.stream_entity_count
+            && stream_data_count == other.stream_data_count
+    }
+
+    /// Checks that the tables described by the counts fit in `resource_data_size`
+    ///
+    /// The file path lookup stores its own counts in front of it instead of in the header, so
+    /// those are read out of `blob`.
+    pub fn is_valid(&self, blob: &[u8]) -> bool {
+        use std::mem::size_of as s;
+        let lookup_size = s::<HashWithData>() as u64;
+
+        let stream_size = s::<Self>() as u64
+            + self.stream_folder_count as u64 * s::<StreamFolder>() as u64
+            + self.stream_path_count as u64 * (lookup_size + s::<StreamPath>() as u64)
+            + self.stream_entity_count as u64 * s::<StreamEntity>() as u64
+            + self.stream_data_count as u64 * s::<StreamData>() as u64;
+
+        let Some(lookup_counts) = usize::try_from(stream_size)
+            .ok()
+            .and_then(|start| blob.get(start..start.checked_add(8)?))
+        else {
+            return false;
+        };
+        let file_path_lookup_count: u32 = bytemuck::pod_read_unaligned(&lookup_counts[..4]);
+        let file_path_bucket_count: u32 = bytemuck::pod_read_unaligned(&lookup_counts[4..]);
+
+        // The lookup is indexed by `hash % bucket_count`
+        if file_path_bucket_count == 0 {
+            return false;
+        }
+
+        let group_count = self.file_info_group_count as u64
+            + self.file_data_group_count as u64
+            + self.versioned_file_group_count as u64;
+        let info_count = self.file_package_info_count as u64
+            + self.file_group_info_count as u64
+            + self.versioned_file_info_count as u64;
+        let desc_count = self.file_package_desc_count as u64
+            + self.file_group_info_count as u64
+            + self.versioned_file_desc_count as u64;
+        let data_count = self.file_package_data_count as u64
+            + self.file_group_info_count as u64
+            + self.versioned_file_data_count as u64;
+
+        let file_size = 8
+            + file_path_bucket_count as u64 * s::<Bucket>() as u64
+            + file_path_lookup_count as u64 * lookup_size
+            + self.file_path_count as u64 * s::<FilePath>() as u64
+            + self.file_entity_count as u64 * s::<FileEntity>() as u64
+            + self.file_package_count as u64 * (lookup_size + s::<FilePackage>() as u64)
+            + group_count * s::<FileGroup>() as u64
+            + self.file_package_child_count as u64 * s::<FilePackageChild>() as u64
+            + info_count * s::<FileInfo>() as u64
+            + desc_count * s::<FileDescriptor>() as u64
+            + data_count * s::<FileData>() as u64;
+
+        stream_size + file_size <= self.resource_data_size as u64
+    }
+}
+
 pub struct SearchTables {
     raw: Box<[u8]>,
     header: SearchTableHeader,
@@ -161,6 +351,7 @@ impl SearchTables {
                 let new_buffer = unsafe {
                     std::alloc::alloc(Layout::from_size_align(total, 0x10).unwrap())
                 };
+                assert!(!new_buffer.is_null(), "Failed to allocate {} bytes for reserialized tables", total);
 
                 let buffer_slice = unsafe { std::slice::from_raw_parts_mut(new_buffer, total) };
 
@@ -238,7 +429,10 @@ impl SearchTables {
 impl ResourceTables {
     // reserializes the tables into a new boxed slice, releasing the old one
     // this will update all tables to point to the new memory range in the new byte slice
-    pub fn reserialize_internal(&mut self) {
+    //
+    // if a budget is provided and the new buffer would be larger than it, nothing is allocated
+    // and the tables are left untouched
+    pub fn reserialize_internal(&mut self, budget: Option<usize>) -> Result<(), ReserializeError> {
         macro_rules! reserialize_order {
             ($($id:ident,)*) => {
                 let mut total = std::mem::size_of::<ResourceTableHeader>();
@@ -250,9 +444,16 @@ impl ResourceTables {
                     };
                 )*
 
+                if let Some(budget) = budget {
+                    if total > budget {
+                        return Err(ReserializeError::BufferTooLarge { required: total, budget });
+                    }
+                }
+
                 let new_buffer = unsafe {
                     std::alloc::alloc(Layout::from_size_align(total, 0x10).unwrap())
                 };
+                assert!(!new_buffer.is_null(), "Failed to allocate {} bytes for reserialized tables", total);
 
                 let buffer_slice = unsafe { std::slice::from_raw_parts_mut(new_buffer, total) };
 
@@ -260,9 +461,15 @@ impl ResourceTables {
                     unsafe { self.$id.write_and_update(buffer_slice, $id); }
                 )*
 
-                println!("Current: {:#x?}", self.header);
+                if cfg!(feature = "verbose_logging") {
+                    log::debug!("reserialize resource header: current={:#x?}", self.header);
+                }
 
                 self.header.resource_data_size = total as u32;
+                self.header.stream_folder_count = self.stream_folder.len() as u32;
+                self.header.stream_path_count = self.stream_path.len() as u32;
+                self.header.stream_entity_count = self.stream_entity.len() as u32;
+                self.header.stream_data_count = self.stream_data.len() as u32;
                 self.header.file_package_count = self.file_package.len() as u32;
                 self.header.file_package_child_count = self.file_package_child.len() as u32;
                 self.header.file_data_group_count = self.file_group.len() as u32 - self.header.versioned_file_group_count - self.header.file_info_group_count;
@@ -272,7 +479,9 @@ impl ResourceTables {
                 self.header.file_package_desc_count = self.file_desc.len() as u32 - self.header.versioned_file_desc_count - self.header.file_group_info_count;
                 self.header.file_package_data_count = self.file_data.len()  as u32 - self.header.versioned_file_data_count - self.header.file_group_info_count;
                 unsafe { *new_buffer.cast::<ResourceTableHeader>() = self.header; }
-                println!("New: {:#x?}", self.header);
+                if cfg!(feature = "verbose_logging") {
+                    log::debug!("reserialize resource header: new={:#x?}", self.header);
+                }
 
                 self.raw = unsafe { Box::from_raw(buffer_slice) };
             }
@@ -285,6 +494,8 @@ impl ResourceTables {
             file_package, file_group, file_package_child,
             file_info, file_desc, file_data,
         }
+
+        Ok(())
     }
 
     #[allow(unused_assignments)]
@@ -379,9 +590,259 @@ impl ResourceTables {
     }
 }
 
+/// Entry counts for the archive tables, see [`Archive::stats`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ArchiveStats {
+    pub file_paths: usize,
+    pub file_entities: usize,
+    pub file_infos: usize,
+    pub file_descs: usize,
+    pub file_data: usize,
+    pub file_packages: usize,
+    pub file_groups: usize,
+    pub stream_entries: usize,
+    pub search_folders: usize,
+    pub search_paths: usize,
+    pub dynamic_file_paths_added: usize,
+    pub dynamic_file_infos_added: usize,
+}
+
+impl std::fmt::Display for ArchiveStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Archive Stats:")?;
+        writeln!(
+            f,
+            "\tFILE PATHS:      {} ({} added)",
+            self.file_paths, self.dynamic_file_paths_added
+        )?;
+        writeln!(f, "\tFILE ENTITIES:   {}", self.file_entities)?;
+        writeln!(
+            f,
+            "\tFILE INFOS:      {} ({} added)",
+            self.file_infos, self.dynamic_file_infos_added
+        )?;
+        writeln!(f, "\tFILE DESCS:      {}", self.file_descs)?;
+        writeln!(f, "\tFILE DATA:       {}", self.file_data)?;
+        writeln!(f, "\tFILE PACKAGES:   {}", self.file_packages)?;
+        writeln!(f, "\tFILE GROUPS:     {}", self.file_groups)?;
+        writeln!(f, "\tSTREAM ENTRIES:  {}", self.stream_entries)?;
+        writeln!(f, "\tSEARCH FOLDERS:  {}", self.search_folders)?;
+        writeln!(f, "\tSEARCH PATHS:    {}", self.search_paths)
+    }
+}
+
+/// An error returned by [`Archive::reserialize_with_budget`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReserializeError {
+    BufferTooLarge { required: usize, budget: usize },
+}
+
+impl std::fmt::Display for ReserializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BufferTooLarge { required, budget } => {
+                write!(
+                    f,
+                    "Resource tables need {required:#x} bytes, which is over the budget of {budget:#x} bytes"
+                )
+            }
+        }
+    }
+}
+
+/// A problem with the blobs passed to [`Archive::try_from_blobs`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TableBlobError {
+    TooSmall {
+        table: &'static str,
+        len: usize,
+    },
+    SizeMismatch {
+        table: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    InvalidCounts {
+        table: &'static str,
+    },
+}
+
+impl std::fmt::Display for TableBlobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooSmall { table, len } => {
+                write!(
+                    f,
+                    "{table} blob is only {len:#x} bytes, which is smaller than its header"
+                )
+            }
+            Self::SizeMismatch {
+                table,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{table} blob is {actual:#x} bytes but its header says it is {expected:#x} bytes"
+            ),
+            Self::InvalidCounts { table } => {
+                write!(
+                    f,
+                    "{table} blob header has counts that do not fit in the blob"
+                )
+            }
+        }
+    }
+}
+
+/// An inconsistency between the archive tables found by [`Archive::validate`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    FileInfoPathOutOfRange { info: u32, path: u32 },
+    FileInfoDescOutOfRange { info: u32, desc: u32 },
+    FilePathEntityOutOfRange { path: u32, entity: u32 },
+    FileDescGroupOutOfRange { desc: u32, group: u32 },
+    FileGroupInvalidRedirection { group: u32, redirection: u32 },
+    SearchHeaderInconsistent { search_data_size: u32 },
+    HeaderMismatch { table: &'static str },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FileInfoPathOutOfRange { info, path } => {
+                write!(f, "FileInfo {info:#x} points to missing FilePath {path:#x}")
+            }
+            Self::FileInfoDescOutOfRange { info, desc } => {
+                write!(
+                    f,
+                    "FileInfo {info:#x} points to missing FileDescriptor {desc:#x}"
+                )
+            }
+            Self::FilePathEntityOutOfRange { path, entity } => {
+                write!(
+                    f,
+                    "FilePath {path:#x} points to missing FileEntity {entity:#x}"
+                )
+            }
+            Self::FileDescGroupOutOfRange { desc, group } => {
+                write!(
+                    f,
+                    "FileDescriptor {desc:#x} points to missing FileGroup {group:#x}"
+                )
+            }
+            Self::FileGroupInvalidRedirection { group, redirection } => {
+                write!(
+                    f,
+                    "FileGroup {group:#x} has invalid redirection {redirection:#x}"
+                )
+            }
+            Self::SearchHeaderInconsistent { search_data_size } => {
+                write!(
+                    f,
+                    "Search table counts do not fit in its size of {search_data_size:#x} bytes"
+                )
+            }
+            Self::HeaderMismatch { table } => {
+                write!(
+                    f,
+                    "The {table} table header does not match the serialized one"
+                )
+            }
+        }
+    }
+}
+
+macro_rules! decl_transaction {
+    ($($tables:ident => { $($id:ident: $t:ty),* }),*) => {
+        /// A snapshot of the dynamic regions of every table in the archive, along with the entries
+        /// that have been removed from each lookup
+        ///
+        /// Changes made to the archive after calling [`Archive::transaction`] are applied directly
+        /// to the archive. [`ArchiveTransaction::commit`] keeps them, while
+        /// [`ArchiveTransaction::rollback`] restores every table to the snapshot.
+        ///
+        /// Only the dynamic regions are snapshotted, so modifications to entries that were already
+        /// in the archive (such as setting the load method of a vanilla file descriptor) are **not**
+        /// undone by a rollback. Transactions also cannot span a call to [`Archive::reserialize`],
+        /// since that merges the dynamic regions into the fixed ones.
+        #[must_use = "Transactions should be either committed or rolled back"]
+        pub struct ArchiveTransaction {
+            resource_raw: *const u8,
+            search_raw: *const u8,
+            $($($id: $t,)*)*
+        }
+
+        impl Archive {
+            /// Snapshots the dynamic regions of the archive, see [`ArchiveTransaction`]
+            pub fn transaction(&self) -> ArchiveTransaction {
+                ArchiveTransaction {
+                    resource_raw: self.resource.raw.as_ptr(),
+                    search_raw: self.search.raw.as_ptr(),
+                    $($($id: self.$tables.$id.snapshot_dynamic(),)*)*
+                }
+            }
+        }
+
+        impl ArchiveTransaction {
+            /// Keeps all of the changes made since this transaction was created
+            pub fn commit(self) {}
+
+            /// Discards all of the entries added since this transaction was created, and brings
+            /// back any lookup entries that were removed
+            #[track_caller]
+            pub fn rollback(self, archive: &mut Archive) {
+                assert!(
+                    self.resource_raw == archive.resource.raw.as_ptr()
+                        && self.search_raw == archive.search.raw.as_ptr(),
+                    "Cannot roll back a transaction after the archive has been reserialized"
+                );
+
+                $($(archive.$tables.$id.restore_dynamic(self.$id);)*)*
+            }
+        }
+    };
+}
+
+decl_transaction! {
+    resource => {
+        stream_folder: Vec<StreamFolder>,
+        stream_path_lookup: LookupSnapshot<BTreeMap<Hash40, u32>>,
+        stream_path: Vec<StreamPath>,
+        stream_entity: Vec<StreamEntity>,
+        stream_data: Vec<StreamData>,
+        file_path_lookup: LookupSnapshot<Box<[BTreeMap<Hash40, u32>]>>,
+        file_path: Vec<FilePath>,
+        file_entity: Vec<FileEntity>,
+        file_package_lookup: LookupSnapshot<BTreeMap<Hash40, u32>>,
+        file_package: Vec<FilePackage>,
+        file_group: Vec<FileGroup>,
+        file_package_child: Vec<FilePackageChild>,
+        file_info: Vec<FileInfo>,
+        file_desc: Vec<FileDescriptor>,
+        file_data: Vec<FileData>
+    },
+    search => {
+        search_folder_lookup: LookupSnapshot<BTreeMap<Hash40, u32>>,
+        search_folder: Vec<SearchFolder>,
+        search_path_lookup: LookupSnapshot<BTreeMap<Hash40, u32>>,
+        search_path_link: Vec<SearchPathLink>,
+        search_path: Vec<SearchPath>
+    }
+}
+
+/// A reasonable [`BucketLookup::load_factor`] at which to rebalance the file path lookup, see
+/// [`Archive::set_file_path_rebalance_threshold`]
+///
+/// Rebalancing is disabled by default, since the game reads the file path lookup with its original
+/// bucket count anyways.
+#[allow(dead_code)]
+pub const DEFAULT_FILE_PATH_REBALANCE_THRESHOLD: f32 = 4.0;
+
 pub struct Archive {
     resource: ResourceTables,
     search: SearchTables,
+    file_path_rebalance_threshold: Option<f32>,
+    dirty: bool,
 }
 
 macro_rules! decl_lookup {
@@ -450,8 +911,23 @@ macro_rules! decl_access {
                     TableSliceRef::new(self, &self.resource.$name, index, count)
                 }
 
+                pub fn [<get_ $name _slice_mut>](&mut self, index: u32, count: u32) -> Option<TableSliceMut<'_, $t>> {
+                    TableSliceMut::new(self, |archive| &mut archive.resource.$name, index, count)
+                }
+
+                // Indices into these tables get packed into the 24-bit data of a `HashWithData`,
+                // so we fail here instead of silently truncating them later
+                #[track_caller]
                 pub fn [<push_ $name>](&mut self, element: $t) -> u32 {
-                    self.resource.$name.push(element)
+                    self.mark_dirty();
+                    let index = self.resource.$name.push(element);
+                    assert!(
+                        index <= HashWithData::MAX_DATA,
+                        "{} table has overflowed the maximum of {:#x} entries",
+                        stringify!($name),
+                        HashWithData::MAX_DATA
+                    );
+                    index
                 }
             )*
         }
@@ -527,6 +1003,7 @@ impl Archive {
 
     #[track_caller]
     pub fn insert_search_path(&mut self, path: SearchPath) -> u32 {
+        self.mark_dirty();
         let index = self.search.search_path.push(path);
         let link_index = self
             .search
@@ -544,8 +1021,87 @@ impl Archive {
         link_index
     }
 
+    /// Inserts a search path if its hash is not already in the lookup, returning the index of
+    /// its link and whether or not it was inserted
+    ///
+    /// Unlike [`Archive::insert_search_path`], this does not panic on duplicate paths.
+    #[allow(dead_code)]
+    pub fn insert_or_get_search_path(&mut self, path: SearchPath) -> (u32, bool) {
+        match self.search.search_path_lookup.get(path.path()) {
+            Some(link_index) => (link_index, false),
+            None => (self.insert_search_path(path), true),
+        }
+    }
+
+    /// Removes a search path that was previously inserted, returning the index of its link
+    ///
+    /// The path is unlinked from its parent folder's chain and its link is invalidated, so it
+    /// can't be reached from either the lookup or the folder. The path itself stays in the table.
+    pub fn remove_search_path(&mut self, path: impl IntoHash) -> Option<u32> {
+        let path = path.into_hash();
+        let removed = *self.lookup_search_path(path)?;
+        let link_index = self.search.search_path_lookup.remove(path)?;
+        self.mark_dirty();
+
+        self.unlink_search_path(&removed, link_index);
+        if let Some(link) = self.search.search_path_link.get_mut(link_index) {
+            *link = SearchPathLink::invalid();
+        }
+        Some(link_index)
+    }
+
+    /// Points whatever comes before `link_index` in the parent folder's chain at the path after it
+    fn unlink_search_path(&mut self, removed: &SearchPath, link_index: u32) {
+        let Some(mut folder) = self.lookup_search_folder_mut(removed.parent()) else {
+            return;
+        };
+
+        if removed.is_folder() {
+            let count = folder.folder_count().saturating_sub(1);
+            folder.set_folder_count(count);
+        } else {
+            let count = folder.file_count().saturating_sub(1);
+            folder.set_file_count(count);
+        }
+
+        let Some(mut current_link) = folder.first_child_index() else {
+            return;
+        };
+
+        if current_link == link_index {
+            folder.set_first_child_index(removed.next_index().unwrap_or(u32::MAX));
+            return;
+        }
+
+        for _ in 0..MAX_SEARCH_PATH_CHAIN {
+            let Some(path_index) = self
+                .get_search_path_link(current_link)
+                .filter(|link| !link.is_invalid())
+                .map(|link| link.path_index())
+            else {
+                return;
+            };
+
+            let mut current = self.get_search_path_mut(path_index).unwrap();
+            match current.next_index() {
+                Some(next) if next == link_index => {
+                    match removed.next_index() {
+                        Some(after) => current.set_next_index(after),
+                        None => current.set_end(),
+                    }
+                    return;
+                }
+                Some(next) => current_link = next,
+                None => return,
+            }
+        }
+
+        panic!("Search path chain is longer than {MAX_SEARCH_PATH_CHAIN} entries, it likely contains a cycle");
+    }
+
     #[track_caller]
     pub fn insert_search_folder(&mut self, folder: SearchFolder) -> u32 {
+        self.mark_dirty();
         let new_index = self.search.search_folder.push(folder);
         assert!(
             self.search
@@ -569,9 +1125,189 @@ impl Archive {
             "{}",
             path.path().display()
         );
+
+        if let Some(threshold) = self.file_path_rebalance_threshold {
+            let lookup = &mut self.resource.file_path_lookup;
+            if lookup.load_factor() > threshold {
+                lookup.rebalance(lookup.bucket_count() * 2);
+            }
+        }
+
+        path_idx
+    }
+
+    /// Adds a new stream file that reads `size` bytes at `offset` in the data.arc, returning the
+    /// index of the new stream path
+    ///
+    /// The stream file is not localized or regional. It is added to the stream folder and the
+    /// search folder of its parent, and any of those folders that don't exist yet are created.
+    #[track_caller]
+    pub fn insert_stream_path_with_data(&mut self, path: &str, size: u64, offset: u64) -> u32 {
+        let hash = Hash40::const_new(path);
+        // Checked upfront so that we don't leave orphaned data and entities behind
+        assert!(
+            !self.resource.stream_path_lookup.contains_key(hash),
+            "{path}"
+        );
+        let (parent, file_name) = path
+            .rsplit_once('/')
+            .unwrap_or_else(|| panic!("Stream path {path} has no parent folder"));
+        let extension = file_name.rsplit_once('.').map_or("", |(_, ext)| ext);
+
+        let data_idx = self.push_stream_data(StreamData::new(size, offset));
+        let entity_idx = self.push_stream_entity(StreamEntity::new(data_idx));
+        let path_idx = self.insert_stream_path_into_folder(
+            Hash40::const_new(parent),
+            StreamPath::new(hash, entity_idx, StreamFileFlags::empty()),
+        );
+
+        self.insert_missing_search_folders(parent);
+        let search_path = SearchPath::new(hash, parent, file_name, extension);
+        let link_index = self.insert_search_path(search_path);
+        self.link_search_path(&search_path, link_index);
+
         path_idx
     }
 
+    /// Inserts the stream path at the end of the folder's range of stream paths, returning its
+    /// index
+    ///
+    /// Stream folders own contiguous ranges of the stream path table, so every path after the
+    /// folder's range is moved up by one to make room.
+    fn insert_stream_path_into_folder(&mut self, folder: Hash40, path: StreamPath) -> u32 {
+        let folder_index = self
+            .iter_stream_folder()
+            .find(|stream_folder| stream_folder.path() == folder)
+            .map(|stream_folder| stream_folder.index());
+
+        let Some(folder_index) = folder_index else {
+            let path_idx = self.push_stream_path(path);
+            let mut new_folder = StreamFolder::new(folder, path_idx);
+            new_folder.set_child_count(1);
+            self.push_stream_folder(new_folder);
+            assert!(self
+                .resource
+                .stream_path_lookup
+                .insert(path.path(), path_idx)
+                .is_none());
+            return path_idx;
+        };
+
+        let new_index = self
+            .get_stream_folder(folder_index)
+            .unwrap()
+            .child_range()
+            .end;
+        let last = self.push_stream_path(path);
+        for index in (new_index..last).rev() {
+            let moved = self.resource.stream_path[index];
+            self.resource.stream_path[index + 1] = moved;
+            // Removed paths can't be looked up anymore, so it doesn't matter where they end up
+            let _ = self
+                .resource
+                .stream_path_lookup
+                .set(moved.path(), index + 1);
+        }
+        self.resource.stream_path[new_index] = path;
+
+        for (index, stream_folder) in self.resource.stream_folder.iter_mut() {
+            if index == folder_index {
+                stream_folder.set_child_count(stream_folder.child_count() + 1);
+            } else if stream_folder.child_start_index() >= new_index {
+                stream_folder.set_child_start_index(stream_folder.child_start_index() + 1);
+            }
+        }
+
+        assert!(self
+            .resource
+            .stream_path_lookup
+            .insert(path.path(), new_index)
+            .is_none());
+        new_index
+    }
+
+    /// Creates a search folder for every folder in `path` that doesn't have one yet, linking each
+    /// of them into its parent
+    fn insert_missing_search_folders(&mut self, path: &str) {
+        let mut current_parent = Hash40::const_new("");
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            let new_parent = current_parent.const_with(component);
+            if self.lookup_search_folder(new_parent).is_none() {
+                let search_path = SearchPath::new_folder(
+                    new_parent,
+                    if current_parent == Hash40::const_new("") {
+                        Hash40::const_new("/")
+                    } else {
+                        current_parent.const_trim_trailing("/")
+                    },
+                    component,
+                );
+
+                let link_index = self.insert_search_path(search_path);
+                self.link_search_path(&search_path, link_index);
+                self.insert_search_folder(SearchFolder::new(
+                    search_path.path(),
+                    search_path.parent(),
+                    search_path.name(),
+                ));
+            }
+
+            current_parent = new_parent.const_with("/");
+        }
+    }
+
+    /// Appends `link_index` to the end of the parent folder's chain, the opposite of
+    /// [`Archive::unlink_search_path`]
+    ///
+    /// Nothing is linked if the parent folder doesn't exist.
+    fn link_search_path(&mut self, path: &SearchPath, link_index: u32) {
+        let Some(mut folder) = self.lookup_search_folder_mut(path.parent()) else {
+            return;
+        };
+
+        if path.is_folder() {
+            let count = folder.folder_count();
+            folder.set_folder_count(count + 1);
+        } else {
+            let count = folder.file_count();
+            folder.set_file_count(count + 1);
+        }
+
+        if folder.has_first_child() {
+            folder.first_child().into_last().set_next_index(link_index);
+        } else {
+            folder.set_first_child_index(link_index);
+        }
+    }
+
+    /// Inserts a file path if its hash is not already in the lookup, returning the index of
+    /// the file path and whether or not it was inserted
+    ///
+    /// Unlike [`Archive::insert_file_path`], this does not panic on duplicate paths.
+    pub fn insert_or_get_file_path(&mut self, path: FilePath) -> (u32, bool) {
+        match self
+            .resource
+            .file_path_lookup
+            .get(path.path_and_entity.hash40())
+        {
+            Some(path_idx) => (path_idx, false),
+            None => (self.insert_file_path(path), true),
+        }
+    }
+
+    /// Returns the average number of entries in each dynamic bucket of the file path lookup
+    pub fn file_path_load_factor(&self) -> f32 {
+        self.resource.file_path_lookup.load_factor()
+    }
+
+    /// Sets the load factor at which the dynamic section of the file path lookup gets rebalanced
+    /// when inserting new file paths
+    ///
+    /// Passing [`None`] disables rebalancing entirely, which is the default
+    pub fn set_file_path_rebalance_threshold(&mut self, threshold: Option<f32>) {
+        self.file_path_rebalance_threshold = threshold;
+    }
+
     #[track_caller]
     pub fn insert_file_package(&mut self, package: FilePackage) -> u32 {
         let package_idx = self.push_file_package(package);
@@ -586,48 +1322,488 @@ impl Archive {
         package_idx
     }
 
+    /// Inserts a new, empty package along with the data group that it owns, returning
+    /// `(group_index, package_index)`
+    ///
+    /// The package's info and child ranges are set to empty ranges at the end of their tables,
+    /// so that entries pushed afterwards can be appended to them
+    #[track_caller]
+    pub fn create_file_group_for_package(&mut self, mut package: FilePackage) -> (u32, u32) {
+        let group_idx = self.push_file_group(FileGroup::new_for_new_package_default());
+        package.set_data_group(group_idx);
+        package.set_info_range(self.num_file_info() as u32, 0);
+        package.set_child_package_range(self.num_file_package_child() as u32, 0);
+
+        let package_idx = self.insert_file_package(package);
+        (group_idx, package_idx)
+    }
+
+    /// Creates a new, empty costume package at `fighter/<fighter>/cXX` and links it to the
+    /// fighter's package, returning its index
+    ///
+    /// If the package already exists, its index is returned instead. Use
+    /// [`packages::create_fighter_packages`] when adding several slots to the same fighter, so
+    /// that the fighter's child packages only get moved once.
+    pub fn create_fighter_package(&mut self, fighter: Hash40, slot: u8) -> u32 {
+        packages::create_fighter_packages(self, fighter, &[slot])[0]
+    }
+
+    /// Iterates over every package whose path is `dir` or is nested under it, such as all of the
+    /// costume slots under `fighter/mario`
+    ///
+    /// Paths are compared by component using `hashes`, so packages or directories with unknown
+    /// hashes never match. This is a linear scan over every package, so it should only be used
+    /// during initialization.
+    pub fn iter_file_packages_under<'a>(
+        &'a self,
+        dir: Hash40,
+        hashes: &'a HashMemorySlab,
+    ) -> impl Iterator<Item = TableRef<'a, FilePackage>> + 'a {
+        let dir_components = hashes
+            .components_for(dir)
+            .map(|components| components.collect::<Vec<_>>());
+
+        self.iter_file_package().filter(move |package| {
+            let Some(dir_components) = dir_components.as_ref() else {
+                return false;
+            };
+
+            let Some(mut components) = hashes.components_for(package.path()) else {
+                return false;
+            };
+
+            dir_components
+                .iter()
+                .all(|dir_component| components.next() == Some(*dir_component))
+        })
+    }
+
+    /// Collects every package whose path starts with `prefix`, see
+    /// [`Archive::iter_file_packages_under`]
+    pub fn lookup_file_package_by_path_prefix<'a>(
+        &'a self,
+        prefix: Hash40,
+        hashes: &'a HashMemorySlab,
+    ) -> Vec<TableRef<'a, FilePackage>> {
+        self.iter_file_packages_under(prefix, hashes).collect()
+    }
+
+    /// Iterates over the file paths of every file in the package
+    ///
+    /// Files that have been reshared are skipped, since their path no longer belongs to the package
+    pub fn iter_file_paths_in_package(
+        &self,
+        package: impl IntoHash,
+    ) -> impl Iterator<Item = TableRef<'_, FilePath>> {
+        self.lookup_file_package(package)
+            .into_iter()
+            .flat_map(|package| package.infos())
+            .filter_map(|info| match info.try_file_path() {
+                TryFilePathResult::FilePath(path) => Some(path),
+                TryFilePathResult::Reshared(_) | TryFilePathResult::Missing => None,
+            })
+    }
+
+    /// Iterates over the search paths of every file under the folder, including the files in
+    /// any of its subfolders
+    ///
+    /// This walks the search section's linked lists, so it only visits entries under the folder
+    /// instead of the entire file path table
+    pub fn iter_files_under_folder(
+        &self,
+        folder: impl IntoHash,
+    ) -> impl Iterator<Item = TableRef<'_, SearchPath>> {
+        let mut pending = self
+            .lookup_search_folder(folder)
+            .map(|folder| folder.iter_children())
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        std::iter::from_fn(move || loop {
+            let current = pending.last_mut()?.next();
+            let Some(current) = current else {
+                pending.pop();
+                continue;
+            };
+
+            if !current.is_folder() {
+                return Some(current);
+            }
+
+            pending.push(current.as_folder().iter_children());
+        })
+    }
+
+    /// Iterates over every file info that has any of the provided flags set
+    pub fn iter_file_infos_with_flags(
+        &self,
+        flags: FileInfoFlags,
+    ) -> impl Iterator<Item = TableRef<'_, FileInfo>> {
+        self.iter_file_info()
+            .filter(move |info| info.flags().intersects(flags))
+    }
+
+    /// Iterates over every file info that was unshared during patching
+    pub fn iter_unshared_file_infos(&self) -> impl Iterator<Item = TableRef<'_, FileInfo>> {
+        self.iter_file_infos_with_flags(FileInfoFlags::IS_UNSHARED)
+    }
+
+    /// Iterates over every file info that was created during patching
+    pub fn iter_custom_file_infos(&self) -> impl Iterator<Item = TableRef<'_, FileInfo>> {
+        self.iter_file_infos_with_flags(FileInfoFlags::IS_CUSTOM_SLOT)
+    }
+
+    /// Checks that the indices stored in the archive tables point to entries that exist
+    ///
+    /// This does not stop at the first inconsistency, every error that is found is returned
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+
+        if !self.search.header.is_valid() {
+            errors.push(ValidationError::SearchHeaderInconsistent {
+                search_data_size: self.search.header.search_data_size,
+            });
+        }
+
+        let serialized_search: &SearchTableHeader =
+            bytemuck::from_bytes(&self.search.raw[..std::mem::size_of::<SearchTableHeader>()]);
+        if !self.search.header.semantically_eq(serialized_search) {
+            errors.push(ValidationError::HeaderMismatch { table: "search" });
+        }
+
+        let serialized_resource: &ResourceTableHeader =
+            bytemuck::from_bytes(&self.resource.raw[..std::mem::size_of::<ResourceTableHeader>()]);
+        if !self.resource.header.semantically_eq(serialized_resource) {
+            errors.push(ValidationError::HeaderMismatch { table: "resource" });
+        }
+
+        let num_file_path = self.num_file_path() as u32;
+        let num_file_entity = self.num_file_entity() as u32;
+        let num_file_desc = self.num_file_desc() as u32;
+        let num_file_group = self.num_file_group() as u32;
+
+        for info in self.iter_file_info() {
+            if info.path_index() >= num_file_path {
+                errors.push(ValidationError::FileInfoPathOutOfRange {
+                    info: info.index(),
+                    path: info.path_index(),
+                });
+            }
+
+            if info.base_desc_index() >= num_file_desc {
+                errors.push(ValidationError::FileInfoDescOutOfRange {
+                    info: info.index(),
+                    desc: info.base_desc_index(),
+                });
+            }
+        }
+
+        for path in self.iter_file_path() {
+            let entity = path.path_and_entity.data();
+            if entity >= num_file_entity {
+                errors.push(ValidationError::FilePathEntityOutOfRange {
+                    path: path.index(),
+                    entity,
+                });
+            }
+        }
+
+        for desc in self.iter_file_desc() {
+            if desc.group_idx() >= num_file_group {
+                errors.push(ValidationError::FileDescGroupOutOfRange {
+                    desc: desc.index(),
+                    group: desc.group_idx(),
+                });
+            }
+        }
+
+        for group in self.iter_file_group() {
+            let redirection = group.redirection();
+            if redirection != 0xffffff && redirection >= num_file_group {
+                errors.push(ValidationError::FileGroupInvalidRedirection {
+                    group: group.index(),
+                    redirection,
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     pub fn dump(&self, path: impl AsRef<Utf8Path>) {
         std::fs::write(path.as_ref(), &self.resource.raw).unwrap();
     }
 
+    /// Writes a human readable report of every package and the file infos in it, one line per entry
+    pub fn dump_text_report(&self, path: impl AsRef<Utf8Path>) -> std::io::Result<()> {
+        std::fs::write(path.as_ref(), self.text_report())
+    }
+
+    /// Builds the report written by [`Archive::dump_text_report`]
+    pub fn text_report(&self) -> String {
+        use std::fmt::Write;
+
+        let mut report = String::new();
+        for package in self.iter_file_package() {
+            let _ = writeln!(
+                report,
+                "FilePackage #{} path={} flags={:?}",
+                package.index(),
+                package.path().display(),
+                package.flags()
+            );
+
+            for info in package.infos() {
+                let path = self
+                    .get_file_path(info.path_index())
+                    .map(|path| path.path());
+                let _ = writeln!(
+                    report,
+                    "\tFileInfo #{} path={} entity={:#x} desc={:#x} flags={:?}",
+                    info.index(),
+                    path.map(|path| path.display().to_string())
+                        .unwrap_or_else(|| format!("<missing {:#x}>", info.path_index())),
+                    info.entity_index(),
+                    info.base_desc_index(),
+                    info.flags()
+                );
+            }
+        }
+
+        report
+    }
+
+    /// Writes the same information as [`Archive::dump_text_report`] as JSON, for tooling
+    pub fn dump_json_report(&self, path: impl AsRef<Utf8Path>) -> std::io::Result<()> {
+        let packages = self
+            .iter_file_package()
+            .map(|package| {
+                let infos = package
+                    .infos()
+                    .into_iter()
+                    .map(|info| {
+                        serde_json::json!({
+                            "index": info.index(),
+                            "path": self
+                                .get_file_path(info.path_index())
+                                .map(|path| path.path().display().to_string()),
+                            "entity": info.entity_index(),
+                            "desc": info.base_desc_index(),
+                            "flags": format!("{:?}", info.flags()),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                serde_json::json!({
+                    "index": package.index(),
+                    "path": package.path().display().to_string(),
+                    "flags": format!("{:?}", package.flags()),
+                    "infos": infos,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let contents = serde_json::to_string_pretty(&serde_json::json!({ "packages": packages }))
+            .map_err(std::io::Error::other)?;
+        std::fs::write(path.as_ref(), contents)
+    }
+
+    /// Iterates over every package along with the packages it lists as children
+    pub fn iter_file_packages_with_children(
+        &self,
+    ) -> impl Iterator<
+        Item = (
+            TableRef<'_, FilePackage>,
+            TableSliceRef<'_, FilePackageChild>,
+        ),
+    > {
+        self.iter_file_package().map(|package| {
+            let children = package.child_packages();
+            (package, children)
+        })
+    }
+
+    /// Writes every package and its child packages as a tree, which is useful when figuring out
+    /// why a package can't be found from its parent
+    pub fn print_package_tree(&self, path: impl AsRef<Utf8Path>) -> std::io::Result<()> {
+        use std::fmt::Write;
+
+        let mut tree = String::new();
+        for (package, children) in self.iter_file_packages_with_children() {
+            let _ = writeln!(tree, "{} (#{})", package.path().display(), package.index());
+
+            let count = children.len() as usize;
+            for (idx, child) in children.into_iter().enumerate() {
+                let child = child.package();
+                let _ = writeln!(
+                    tree,
+                    "{} {} (#{})",
+                    if idx + 1 == count {
+                        "└──"
+                    } else {
+                        "├──"
+                    },
+                    child.path().display(),
+                    child.index()
+                );
+            }
+        }
+
+        std::fs::write(path.as_ref(), tree)
+    }
+
     pub fn reserialize(&mut self) {
-        self.resource.reserialize_internal();
+        // Without a budget this can't fail
+        let _ = self.resource.reserialize_internal(None);
         self.search.reserialize_internal();
+        self.dirty = false;
+    }
+
+    /// Reserializes the archive, failing without allocating if the new resource tables would be
+    /// larger than `max_bytes`
+    ///
+    /// On failure the archive is left untouched and is still dirty.
+    pub fn reserialize_with_budget(&mut self, max_bytes: usize) -> Result<(), ReserializeError> {
+        self.resource.reserialize_internal(Some(max_bytes))?;
+        self.search.reserialize_internal();
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Counts the entries in each table
+    ///
+    /// The dynamic counts are only meaningful before reserializing, since reserializing moves every
+    /// entry into the fixed region.
+    pub fn stats(&self) -> ArchiveStats {
+        let resource = &self.resource;
+        let search = &self.search;
+        ArchiveStats {
+            file_paths: resource.file_path.len(),
+            file_entities: resource.file_entity.len(),
+            file_infos: resource.file_info.len(),
+            file_descs: resource.file_desc.len(),
+            file_data: resource.file_data.len(),
+            file_packages: resource.file_package.len(),
+            file_groups: resource.file_group.len(),
+            stream_entries: resource.stream_path.len(),
+            search_folders: search.search_folder.len(),
+            search_paths: search.search_path.len(),
+            dynamic_file_paths_added: resource.file_path.dynamic_len(),
+            dynamic_file_infos_added: resource.file_info.dynamic_len(),
+        }
+    }
+
+    /// The default budget for [`Archive::reserialize_with_budget`], which is the size of the
+    /// current resource tables with 20% headroom
+    pub fn default_reserialize_budget(&self) -> usize {
+        self.resource.raw.len() + self.resource.raw.len() / 5
+    }
+
+    /// Flags the archive as needing a [`Archive::reserialize`] before the game can see the changes
+    ///
+    /// This is done automatically when pushing or inserting entries. Editing existing entries
+    /// happens in place and does not need a reserialize.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns a bitmask of the tables that currently have dynamic entries
+    ///
+    /// Bits are assigned in the order that the tables are serialized in, starting with the
+    /// resource tables (`stream_folder` is bit 0, `file_data` is bit 14) followed by the search
+    /// tables (`search_folder_lookup` is bit 15, `search_path` is bit 19).
+    pub fn dirty_table_mask(&self) -> u32 {
+        let resource = &self.resource;
+        let search = &self.search;
+        [
+            !resource.stream_folder.is_dynamic_empty(),
+            resource.stream_path_lookup.dynamic_byte_len() != 0,
+            !resource.stream_path.is_dynamic_empty(),
+            !resource.stream_entity.is_dynamic_empty(),
+            !resource.stream_data.is_dynamic_empty(),
+            resource.file_path_lookup.dynamic_byte_len() != 0,
+            !resource.file_path.is_dynamic_empty(),
+            !resource.file_entity.is_dynamic_empty(),
+            resource.file_package_lookup.dynamic_byte_len() != 0,
+            !resource.file_package.is_dynamic_empty(),
+            !resource.file_group.is_dynamic_empty(),
+            !resource.file_package_child.is_dynamic_empty(),
+            !resource.file_info.is_dynamic_empty(),
+            !resource.file_desc.is_dynamic_empty(),
+            !resource.file_data.is_dynamic_empty(),
+            search.search_folder_lookup.dynamic_byte_len() != 0,
+            !search.search_folder.is_dynamic_empty(),
+            search.search_path_lookup.dynamic_byte_len() != 0,
+            !search.search_path_link.is_dynamic_empty(),
+            !search.search_path.is_dynamic_empty(),
+        ]
+        .into_iter()
+        .enumerate()
+        .fold(0, |mask, (bit, is_dirty)| mask | ((is_dirty as u32) << bit))
     }
 
     pub fn open() -> Self {
         let mut metadata = ArchiveMetadata::zeroed();
-        let resource_slice;
-        let search_slice;
-        unsafe {
-            let mut file: *mut FileNX = std::ptr::null_mut();
-            init_file(&mut file);
-            let mut buffer = [0u8; 0x108];
-            buffer[0x100..0x108].copy_from_slice(bytemuck::bytes_of(&0xdu64));
-            buffer[.."rom:/data.arc".len()].copy_from_slice("rom:/data.arc".as_bytes());
-            open_file(&mut file, buffer.as_ptr().cast());
-            let size = read_into_ptr(
-                file,
-                (&raw mut metadata).cast::<u8>(),
-                std::mem::size_of::<ArchiveMetadata>(),
-            );
-            assert_eq!(size, 0x38);
-            assert!(metadata.magic == ArchiveMetadata::MAGIC);
-            let resource_ptr =
-                read_compressed_at_offset(&mut file, metadata.resource_table_offset as usize);
-            let resource_size =
-                (*resource_ptr.cast::<ResourceTableHeader>()).resource_data_size as usize;
-            let search_ptr =
-                read_compressed_at_offset(&mut file, metadata.search_table_offset as usize);
-            let search_size = (*search_ptr.cast::<SearchTableHeader>()).search_data_size as usize;
-            resource_slice =
-                Box::from_raw(std::slice::from_raw_parts_mut(resource_ptr, resource_size));
-            search_slice = Box::from_raw(std::slice::from_raw_parts_mut(search_ptr, search_size));
-        }
+        let mut file = FileNXHandle::new();
+        assert!(file.open("rom:/data.arc"), "Failed to open data.arc");
+        let size = file.read_into(bytemuck::bytes_of_mut(&mut metadata));
+        assert_eq!(size, 0x38);
+        assert!(
+            metadata.magic == ArchiveMetadata::MAGIC,
+            "data.arc appears to be corrupt, invalid magic {:#x}",
+            metadata.magic
+        );
+        let resource_ptr = file
+            .read_compressed_at_offset(metadata.resource_table_offset as usize)
+            .expect("Failed to read resource table from data.arc");
+        let resource_size = unsafe {
+            (*resource_ptr.as_ptr().cast::<ResourceTableHeader>()).resource_data_size as usize
+        };
+        // The resource tables don't have a magic of their own, but they can't be smaller than their header
+        assert!(
+            resource_size >= std::mem::size_of::<ResourceTableHeader>(),
+            "data.arc appears to be corrupt, resource table is only {resource_size:#x} bytes"
+        );
+        let search_ptr = file
+            .read_compressed_at_offset(metadata.search_table_offset as usize)
+            .expect("Failed to read search table from data.arc");
+        let search_size =
+            unsafe { (*search_ptr.as_ptr().cast::<SearchTableHeader>()).search_data_size as usize };
+        assert!(
+            search_size >= std::mem::size_of::<SearchTableHeader>(),
+            "data.arc appears to be corrupt, search table is only {search_size:#x} bytes"
+        );
+        // SAFETY: `read_compressed_at_offset` gives ownership of the buffers to the caller, and the
+        //      sizes were read from the headers of the same buffers
+        let resource_slice = unsafe {
+            Box::from_raw(std::slice::from_raw_parts_mut(
+                resource_ptr.as_ptr(),
+                resource_size,
+            ))
+        };
+        let search_slice = unsafe {
+            Box::from_raw(std::slice::from_raw_parts_mut(
+                search_ptr.as_ptr(),
+                search_size,
+            ))
+        };
         let resource = ResourceTables::from_bytes(resource_slice);
         let search = SearchTables::from_bytes(search_slice);
 
-        Self { resource, search }
+        Self {
+            resource,
+            search,
+            file_path_rebalance_threshold: None,
+            dirty: false,
+        }
     }
 
     pub fn resource_blob(&self) -> &[u8] {
@@ -638,11 +1814,92 @@ impl Archive {
         &self.search.raw
     }
 
+    /// Creates an archive from the output of [`Archive::resource_blob`] and
+    /// [`Archive::search_blob`] without checking them, see [`Archive::try_from_blobs`]
+    ///
+    /// # Safety
+    /// - `packaged` must start with a valid [`ResourceTableHeader`] whose `resource_data_size`
+    ///   is the length of `packaged`, followed by the resource tables that it describes
+    /// - `search` must start with a valid [`SearchTableHeader`] whose `search_data_size` is the
+    ///   length of `search`, followed by the search tables that it describes
+    /// - Both buffers must be aligned for every table in them, which the game's buffers and
+    ///   reserialized ones are
     pub unsafe fn from_blobs(packaged: Box<[u8]>, search: Box<[u8]>) -> Self {
         Self {
             resource: ResourceTables::from_bytes(packaged),
             search: SearchTables::from_bytes(search),
+            file_path_rebalance_threshold: None,
+            dirty: false,
+        }
+    }
+
+    /// Checks that the header of a resource blob agrees with the size of the blob and that the
+    /// tables it describes fit in it, see [`Archive::try_from_blobs`]
+    pub fn check_resource_blob(packaged: &[u8]) -> Result<(), TableBlobError> {
+        let header_size = std::mem::size_of::<ResourceTableHeader>();
+        if packaged.len() < header_size {
+            return Err(TableBlobError::TooSmall {
+                table: "resource",
+                len: packaged.len(),
+            });
         }
+
+        let resource_header: ResourceTableHeader =
+            bytemuck::pod_read_unaligned(&packaged[..header_size]);
+        if resource_header.resource_data_size as usize != packaged.len() {
+            return Err(TableBlobError::SizeMismatch {
+                table: "resource",
+                expected: resource_header.resource_data_size as usize,
+                actual: packaged.len(),
+            });
+        }
+
+        if !resource_header.is_valid(packaged) {
+            return Err(TableBlobError::InvalidCounts { table: "resource" });
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the header of a search blob agrees with the size of the blob and that the
+    /// tables it describes fit in it, see [`Archive::try_from_blobs`]
+    pub fn check_search_blob(search: &[u8]) -> Result<(), TableBlobError> {
+        let header_size = std::mem::size_of::<SearchTableHeader>();
+        if search.len() < header_size {
+            return Err(TableBlobError::TooSmall {
+                table: "search",
+                len: search.len(),
+            });
+        }
+
+        let search_header: SearchTableHeader = bytemuck::pod_read_unaligned(&search[..header_size]);
+        if search_header.search_data_size as usize != search.len() {
+            return Err(TableBlobError::SizeMismatch {
+                table: "search",
+                expected: search_header.search_data_size as usize,
+                actual: search.len(),
+            });
+        }
+
+        if !search_header.is_valid() {
+            return Err(TableBlobError::InvalidCounts { table: "search" });
+        }
+
+        Ok(())
+    }
+
+    /// Checks the headers of both blobs before creating an archive from them
+    ///
+    /// This can only check that the headers agree with the size of the blobs, not the
+    /// contents of the tables, so the blobs should still come from [`Archive::resource_blob`]
+    /// and [`Archive::search_blob`]
+    pub fn try_from_blobs(packaged: Box<[u8]>, search: Box<[u8]>) -> Result<Self, TableBlobError> {
+        Self::check_resource_blob(&packaged)?;
+        Self::check_search_blob(&search)?;
+
+        // SAFETY: The headers were checked above, the rest is on the blobs having been written
+        //      by `reserialize`
+        Ok(unsafe { Self::from_blobs(packaged, search) })
     }
 
     pub fn resource_data_ptr(&self) -> *const u8 {
@@ -653,3 +1910,174 @@ impl Archive {
         self.search.raw.as_ptr()
     }
 }
+
+#[cfg(test)]
+impl Archive {
+    /// Creates an archive where every table is empty, for tests that build up their own entries
+    pub(crate) fn empty() -> Self {
+        // The file path lookup is prefixed by its hash count and bucket count, and it needs at
+        // least one (empty) bucket to be able to insert anything
+        let file_path_lookup: [u32; 4] = [0, 1, 0, 0];
+
+        let mut resource_header = ResourceTableHeader::zeroed();
+        resource_header.resource_data_size = (std::mem::size_of::<ResourceTableHeader>()
+            + std::mem::size_of_val(&file_path_lookup))
+            as u32;
+        let mut resource = bytemuck::bytes_of(&resource_header).to_vec();
+        resource.extend_from_slice(bytemuck::bytes_of(&file_path_lookup));
+
+        let mut search_header = SearchTableHeader::zeroed();
+        search_header.search_data_size = std::mem::size_of::<SearchTableHeader>() as u32;
+        let search = bytemuck::bytes_of(&search_header);
+
+        // The tables are cast in place, so the blobs need the same alignment as reserializing
+        // gives them
+        fn aligned(bytes: &[u8]) -> Box<[u8]> {
+            let layout = Layout::from_size_align(bytes.len(), 0x10).unwrap();
+            unsafe {
+                let buffer = std::alloc::alloc(layout);
+                assert!(!buffer.is_null());
+                let buffer = std::slice::from_raw_parts_mut(buffer, bytes.len());
+                buffer.copy_from_slice(bytes);
+                Box::from_raw(buffer)
+            }
+        }
+
+        Self::try_from_blobs(aligned(&resource), aligned(search)).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutate_package_infos_then_reserialize() {
+        let mut archive = Archive::empty();
+        let (_, package_idx) = archive.create_file_group_for_package(FilePackage::new(
+            "fighter/mario/c00",
+            "c00",
+            "fighter/mario",
+            0,
+        ));
+
+        let info_start = archive.num_file_info() as u32;
+        for index in 0..4 {
+            archive.push_file_info(FileInfo::new(
+                index,
+                index,
+                index,
+                FileInfoFlags::IS_REGULAR_FILE,
+            ));
+        }
+        archive
+            .get_file_package_mut(package_idx)
+            .unwrap()
+            .set_info_range(info_start, 4);
+
+        let range = archive.get_file_package(package_idx).unwrap().info_range();
+        let mut infos = archive
+            .get_file_info_slice_mut(range.start, range.end - range.start)
+            .unwrap();
+        for (_, info) in infos.iter_mut() {
+            info.set_flags(info.flags() | FileInfoFlags::IS_CUSTOM_SLOT);
+        }
+
+        archive.reserialize();
+
+        let package = archive.get_file_package(package_idx).unwrap();
+        assert_eq!(package.info_range(), info_start..info_start + 4);
+        for index in package.info_range() {
+            let info = archive.get_file_info(index).unwrap();
+            assert_eq!(
+                info.flags(),
+                FileInfoFlags::IS_REGULAR_FILE | FileInfoFlags::IS_CUSTOM_SLOT
+            );
+            assert_eq!(info.path_index(), index - info_start);
+        }
+    }
+
+    #[test]
+    fn slice_range_overflow_is_rejected() {
+        let mut archive = Archive::empty();
+        archive.push_file_info(FileInfo::new(0, 0, 0, FileInfoFlags::IS_REGULAR_FILE));
+
+        assert!(archive.get_file_info_slice(u32::MAX, 2).is_none());
+        assert!(archive.get_file_info_slice_mut(u32::MAX, 2).is_none());
+        assert!(archive.get_file_info_slice_mut(0, 1).is_some());
+    }
+
+    #[test]
+    fn resource_blob_counts_are_checked() {
+        let archive = Archive::empty();
+        assert!(Archive::check_resource_blob(archive.resource_blob()).is_ok());
+        assert!(Archive::check_search_blob(archive.search_blob()).is_ok());
+
+        let mut blob = archive.resource_blob().to_vec();
+        let mut header: ResourceTableHeader =
+            bytemuck::pod_read_unaligned(&blob[..std::mem::size_of::<ResourceTableHeader>()]);
+        header.file_path_count = 1;
+        blob[..std::mem::size_of::<ResourceTableHeader>()]
+            .copy_from_slice(bytemuck::bytes_of(&header));
+
+        assert!(matches!(
+            Archive::check_resource_blob(&blob),
+            Err(TableBlobError::InvalidCounts { table: "resource" })
+        ));
+    }
+
+    #[test]
+    fn insert_stream_path_then_reserialize() {
+        let mut archive = Archive::empty();
+        let bgm_a = archive.insert_stream_path_with_data(
+            "stream:/sound/bgm/bgm_a.nus3audio",
+            0x100,
+            0x1000,
+        );
+        archive.insert_stream_path_with_data("stream:/movie/intro.webm", 0x200, 0x2000);
+        let bgm_b = archive.insert_stream_path_with_data(
+            "stream:/sound/bgm/bgm_b.nus3audio",
+            0x300,
+            0x3000,
+        );
+
+        archive.reserialize();
+
+        assert_eq!(archive.resource.header.stream_folder_count, 2);
+        assert_eq!(archive.resource.header.stream_path_count, 3);
+        assert_eq!(archive.search.header.folder_count, 4);
+        assert_eq!(archive.search.header.path_link_count, 7);
+        assert_eq!(archive.search.header.path_count, 7);
+
+        // The movie was moved out of the way so that both sound files are in the bgm folder
+        let expected = [
+            ("stream:/sound/bgm/bgm_a.nus3audio", bgm_a, 0x100),
+            ("stream:/sound/bgm/bgm_b.nus3audio", bgm_b, 0x300),
+            ("stream:/movie/intro.webm", 2, 0x200),
+        ];
+        for (path, index, size) in expected {
+            let stream_path = archive.lookup_stream_path(path).unwrap();
+            assert_eq!(stream_path.index(), index);
+            let entity = archive
+                .get_stream_entity(stream_path.entity_start())
+                .unwrap();
+            assert_eq!(entity.data().size(), size);
+        }
+
+        let folders = archive
+            .iter_stream_folder()
+            .map(|folder| (folder.path(), folder.child_range()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            folders,
+            [
+                (Hash40::const_new("stream:/sound/bgm"), 0..2),
+                (Hash40::const_new("stream:/movie"), 2..3),
+            ]
+        );
+
+        let bgm = archive.lookup_search_folder("stream:/sound/bgm").unwrap();
+        assert_eq!(bgm.file_count(), 2);
+        assert_eq!(archive.iter_files_under_folder("stream:").count(), 3);
+    }
+}
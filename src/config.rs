@@ -0,0 +1,87 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use log::LevelFilter;
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+use crate::STRATUS_FOLDER;
+
+static CONFIG: OnceLock<StratusConfig> = OnceLock::new();
+
+/// User tunable settings, read from `stratus.toml` in the default stratus folder
+///
+/// Every field is optional, and the defaults match the behavior of stratus without a config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StratusConfig {
+    /// The maximum level of log messages that are emitted
+    pub log_level: LevelFilter,
+
+    /// Overrides the folder that stratus keeps its caches, state, and logs in
+    pub stratus_folder: Option<String>,
+
+    /// Whether to enable CPU boost mode while stratus is initializing
+    pub cpu_boost_on_init: bool,
+
+    /// Overrides the path to the hash blob, the `.meta` file is expected to be next to it
+    pub hash_blob_path: Option<String>,
+
+    /// Whether log messages are mirrored to `stratus.log` in the stratus folder
+    pub enable_verbose_file_logging: bool,
+}
+
+impl Default for StratusConfig {
+    fn default() -> Self {
+        Self {
+            log_level: LevelFilter::Info,
+            stratus_folder: None,
+            cpu_boost_on_init: false,
+            hash_blob_path: None,
+            enable_verbose_file_logging: true,
+        }
+    }
+}
+
+impl StratusConfig {
+    pub fn path() -> Utf8PathBuf {
+        Utf8PathBuf::from(STRATUS_FOLDER).join("stratus.toml")
+    }
+
+    /// Loads the config file, falling back to the default config if it does not exist or cannot
+    /// be parsed
+    ///
+    /// This is loaded before the logger is installed, so errors are printed instead of logged
+    fn load() -> Self {
+        let path = Self::path();
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+
+        match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("[stratus::config] Failed to parse {path}, using defaults: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    /// Fetches the config, loading it the first time this is called
+    pub fn get() -> &'static Self {
+        CONFIG.get_or_init(Self::load)
+    }
+
+    pub fn stratus_folder(&self) -> &Utf8Path {
+        Utf8Path::new(self.stratus_folder.as_deref().unwrap_or(STRATUS_FOLDER))
+    }
+
+    pub fn hash_blob_path(&self) -> Utf8PathBuf {
+        match self.hash_blob_path.as_deref() {
+            Some(path) => Utf8PathBuf::from(path),
+            None => self.stratus_folder().join("hashes.blob"),
+        }
+    }
+
+    pub fn hash_meta_path(&self) -> Utf8PathBuf {
+        self.hash_blob_path().with_extension("meta")
+    }
+}
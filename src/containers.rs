@@ -1,4 +1,9 @@
-use std::{collections::BTreeMap, fmt::Debug, ops::Range};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    ops::Range,
+    ptr::NonNull,
+};
 
 use bytemuck::{Pod, Zeroable};
 use smash_hash::Hash40;
@@ -15,7 +20,7 @@ use crate::{archive::Archive, data::HashWithData};
 /// can only be indexed by [`Index`](crate::index::Index), which informs this table if it should
 /// pull data from the fixed-length array or the dynamic region.
 pub(crate) struct Table<T> {
-    fixed: *mut [T],
+    fixed: NonNull<[T]>,
     dynamic: Vec<T>,
 }
 
@@ -33,7 +38,8 @@ impl<T: Pod> Table<T> {
         let slice = bytemuck::cast_slice_mut(slice);
 
         Self {
-            fixed: slice,
+            // Even an empty slice has a dangling, non-null pointer, so zero-count tables are fine
+            fixed: NonNull::from(slice),
             dynamic: vec![],
         }
     }
@@ -41,11 +47,15 @@ impl<T: Pod> Table<T> {
     /// Writes this table into the provided buffer at the provided offset, then updates this buffer
     /// to point to the provided buffer. The provided buffer must have a lifetime >= this structure
     pub unsafe fn write_and_update(&mut self, buffer: &mut [u8], offset: usize) {
+        // SAFETY: `self.fixed` is always created from a reference and the caller guarantees
+        //      that the memory it points to outlives this table
         buffer[offset..offset + self.fixed_byte_len()]
-            .copy_from_slice(unsafe { bytemuck::cast_slice(&*self.fixed) });
+            .copy_from_slice(unsafe { bytemuck::cast_slice(self.fixed.as_ref()) });
         buffer[offset + self.fixed_byte_len()..offset + self.byte_len()]
             .copy_from_slice(bytemuck::cast_slice(&self.dynamic));
-        self.fixed = bytemuck::cast_slice_mut(&mut buffer[offset..offset + self.byte_len()]);
+        self.fixed = NonNull::from(bytemuck::cast_slice_mut(
+            &mut buffer[offset..offset + self.byte_len()],
+        ));
         self.dynamic.clear();
     }
 
@@ -57,11 +67,21 @@ impl<T: Pod> Table<T> {
         self.dynamic.len() * std::mem::size_of::<T>()
     }
 
+    /// Clones the dynamic region of this table so that it can be restored later
+    pub fn snapshot_dynamic(&self) -> Vec<T> {
+        self.dynamic.clone()
+    }
+
+    /// Replaces the dynamic region of this table with one from [`Self::snapshot_dynamic`]
+    pub fn restore_dynamic(&mut self, dynamic: Vec<T>) {
+        self.dynamic = dynamic;
+    }
+
     /// Returns the length of the fixed array, in bytes
     pub fn fixed_byte_len(&self) -> usize {
         // SAFETY: Caller guarantees in constructor that there are no other mutable references
-        //      to this data
-        unsafe { std::mem::size_of_val(&(*self.fixed)) }
+        //      to this data, and `NonNull::from` a reference is always aligned and dereferenceable
+        unsafe { std::mem::size_of_val(self.fixed.as_ref()) }
     }
 }
 
@@ -71,10 +91,11 @@ impl<T> Table<T> {
     /// This uses the fixed-size array if the index is internal, otherwise uses the dynamic array
     pub fn get(&self, index: u32) -> Option<&T> {
         // SAFETY: Caller guarantees in constructor that there are no other mutable references
-        //      to this data, also they provide a reference so the pointer is non-null
+        //      to this data, also they provide a reference so the `NonNull` is aligned and
+        //      dereferenceable. The index was bounds checked against the fixed length
         let fixed_len = self.fixed_len() as u32;
         if index < fixed_len {
-            unsafe { Some((&*self.fixed).get_unchecked(index as usize)) }
+            unsafe { Some(self.fixed.as_ref().get_unchecked(index as usize)) }
         } else {
             self.dynamic.get((index - fixed_len) as usize)
         }
@@ -86,8 +107,8 @@ impl<T> Table<T> {
     pub fn get_mut(&mut self, index: u32) -> Option<&mut T> {
         let fixed_len = self.fixed_len() as u32;
         if index < fixed_len {
-            // SAFETY: See above
-            unsafe { Some((&mut *self.fixed).get_unchecked_mut(index as usize)) }
+            // SAFETY: See above, we hold `&mut self` so no other reference into the table exists
+            unsafe { Some(self.fixed.as_mut().get_unchecked_mut(index as usize)) }
         } else {
             self.dynamic.get_mut((index - fixed_len) as usize)
         }
@@ -95,7 +116,7 @@ impl<T> Table<T> {
 
     pub fn fixed(&self) -> &[T] {
         // SAFETY: See above
-        unsafe { &(*self.fixed) }
+        unsafe { self.fixed.as_ref() }
     }
 
     pub fn dynamic(&self) -> &[T] {
@@ -104,14 +125,12 @@ impl<T> Table<T> {
 
     /// Checks if a table contains the provided index
     pub fn contains(&self, index: u32) -> bool {
-        // SAFETY: See above
-        (self.dynamic.len() + unsafe { (&*self.fixed).len() }) as u32 > index
+        (self.dynamic.len() + self.fixed.len()) as u32 > index
     }
 
     /// Gets the length of the fixed-size array
     pub fn fixed_len(&self) -> usize {
-        // SAFETY: See above
-        unsafe { (&*self.fixed).len() }
+        self.fixed.len()
     }
 
     /// Gets the length of the dynamic array
@@ -164,7 +183,8 @@ impl<T> Table<T> {
     pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
         // SAFETY: See above
         unsafe {
-            (*self.fixed)
+            self.fixed
+                .as_ref()
                 .iter()
                 .enumerate()
                 .map(|(index, data)| (index as u32, data))
@@ -176,6 +196,80 @@ impl<T> Table<T> {
                 )
         }
     }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (u32, &mut T)> {
+        let fixed_len = self.fixed_len();
+        // SAFETY: Caller guarantees in constructor that there are no other mutable references
+        //      to this data, and we are holding `&mut self` for the lifetime of the iterator so
+        //      nothing else can reference this table while it is alive
+        unsafe {
+            self.fixed
+                .as_mut()
+                .iter_mut()
+                .enumerate()
+                .map(|(index, data)| (index as u32, data))
+                .chain(
+                    self.dynamic
+                        .iter_mut()
+                        .enumerate()
+                        .map(move |(index, data)| ((index + fixed_len) as u32, data)),
+                )
+        }
+    }
+
+    /// Iterates mutably over `count` entries of the table starting at `start`
+    ///
+    /// The range is clamped to the length of the table
+    pub fn iter_range_mut(
+        &mut self,
+        start: u32,
+        count: u32,
+    ) -> impl Iterator<Item = (u32, &mut T)> {
+        let fixed_len = self.fixed_len();
+        let start = (start as usize).min(self.len());
+        let end = (start + count as usize).min(self.len());
+
+        let fixed_range = start.min(fixed_len)..end.min(fixed_len);
+        let dynamic_range = start.max(fixed_len) - fixed_len..end.max(fixed_len) - fixed_len;
+
+        // SAFETY: See above
+        unsafe {
+            self.fixed.as_mut()[fixed_range.clone()]
+                .iter_mut()
+                .enumerate()
+                .map(move |(index, data)| ((index + fixed_range.start) as u32, data))
+                .chain(
+                    self.dynamic[dynamic_range.clone()]
+                        .iter_mut()
+                        .enumerate()
+                        .map(move |(index, data)| {
+                            ((index + dynamic_range.start + fixed_len) as u32, data)
+                        }),
+                )
+        }
+    }
+}
+
+impl<T> std::ops::Index<u32> for Table<T> {
+    type Output = T;
+
+    #[track_caller]
+    fn index(&self, index: u32) -> &Self::Output {
+        let len = self.len();
+        self.get(index).unwrap_or_else(|| {
+            panic!("index {index:#x} out of bounds for table of length {len:#x}")
+        })
+    }
+}
+
+impl<T> std::ops::IndexMut<u32> for Table<T> {
+    #[track_caller]
+    fn index_mut(&mut self, index: u32) -> &mut Self::Output {
+        let len = self.len();
+        self.get_mut(index).unwrap_or_else(|| {
+            panic!("index {index:#x} out of bounds for table of length {len:#x}")
+        })
+    }
 }
 
 /// Represents an immutable reference to a piece of data in a table
@@ -196,6 +290,16 @@ pub struct TableRef<'a, T> {
     index: u32,
 }
 
+// These are implemented manually so that they do not require `T: Clone`, a
+// `TableRef` is just a pair of shared references and an index
+impl<T> Clone for TableRef<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TableRef<'_, T> {}
+
 impl<T> std::ops::Deref for TableRef<'_, T> {
     type Target = T;
 
@@ -354,6 +458,26 @@ impl<'a, T> TableMut<'a, T> {
     }
 }
 
+impl<'a, T: Clone> TableMut<'a, T> {
+    /// Pushes a copy of the referenced data to the end of the table and returns a [`TableMut`]
+    /// pointing to the copy.
+    ///
+    /// This can't be a [`Clone`] implementation since we can't hand out a second mutable
+    /// reference to the same table, so instead this consumes the original reference.
+    #[allow(dead_code)]
+    pub fn detach(self) -> Self {
+        let value = (*self).clone();
+        let index = self.table.push(value);
+        // SAFETY: See `archive_mut`
+        unsafe { (*self.archive).mark_dirty() };
+        Self {
+            archive: self.archive,
+            table: self.table,
+            index,
+        }
+    }
+}
+
 pub struct TableSliceRef<'a, T> {
     archive: &'a Archive,
     table: &'a Table<T>,
@@ -368,7 +492,8 @@ impl<'a, T> TableSliceRef<'a, T> {
         start: u32,
         count: u32,
     ) -> Option<Self> {
-        if count != 0 && !table.contains(start + count - 1) {
+        let end = start.checked_add(count)?;
+        if count != 0 && !table.contains(end - 1) {
             return None;
         }
 
@@ -430,8 +555,15 @@ impl<'a, T> Iterator for TableSliceIter<'a, T> {
         let next = self.range.next()?;
         TableRef::new(self.archive, self.table, next)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // The range was bounds checked when the slice was created, so every index is valid
+        self.range.size_hint()
+    }
 }
 
+impl<T> ExactSizeIterator for TableSliceIter<'_, T> {}
+
 impl<'a, T> IntoIterator for TableSliceRef<'a, T> {
     type IntoIter = TableSliceIter<'a, T>;
     type Item = TableRef<'a, T>;
@@ -458,6 +590,111 @@ impl<'a, T> IntoIterator for &TableSliceRef<'a, T> {
     }
 }
 
+/// Represents a mutable reference to a contiguous range of data in a table
+///
+/// This is the mutable counterpart to [`TableSliceRef`]. Like [`TableMut`], it takes the whole
+/// archive as an exclusive reference on construction, so while this slice is alive nothing else
+/// can touch the archive.
+pub struct TableSliceMut<'a, T> {
+    archive: *mut Archive,
+    table: &'a mut Table<T>,
+    start: u32,
+    count: u32,
+}
+
+impl<'a, T> TableSliceMut<'a, T> {
+    pub(crate) fn new(
+        archive: &'a mut Archive,
+        get_table: impl FnOnce(&mut Archive) -> &mut Table<T>,
+        start: u32,
+        count: u32,
+    ) -> Option<Self> {
+        let archive: *mut Archive = archive;
+        let table = get_table(unsafe { &mut *archive });
+        let end = start.checked_add(count)?;
+        if count != 0 && !table.contains(end - 1) {
+            return None;
+        }
+
+        Some(Self {
+            archive,
+            table,
+            start,
+            count,
+        })
+    }
+
+    pub fn range(&self) -> Range<u32> {
+        self.start..self.start + self.count
+    }
+
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: u32) -> Option<TableRef<'_, T>> {
+        (index.wrapping_sub(self.start) < self.count).then_some(TableRef {
+            // SAFETY: We took an exclusive reference when constructing this data
+            archive: unsafe { &*self.archive },
+            table: self.table,
+            index,
+        })
+    }
+
+    pub fn get_local(&self, index: u32) -> Option<TableRef<'_, T>> {
+        (index < self.count).then_some(TableRef {
+            // SAFETY: See above
+            archive: unsafe { &*self.archive },
+            table: self.table,
+            index: self.start + index,
+        })
+    }
+
+    pub fn get_mut(&mut self, index: u32) -> Option<TableMut<'_, T>> {
+        (index.wrapping_sub(self.start) < self.count).then_some(TableMut {
+            archive: self.archive,
+            table: self.table,
+            index,
+        })
+    }
+
+    pub fn get_local_mut(&mut self, index: u32) -> Option<TableMut<'_, T>> {
+        (index < self.count).then_some(TableMut {
+            archive: self.archive,
+            table: self.table,
+            index: self.start + index,
+        })
+    }
+
+    pub fn iter(&self) -> TableSliceIter<'_, T> {
+        TableSliceIter {
+            // SAFETY: See above
+            archive: unsafe { &*self.archive },
+            table: self.table,
+            range: self.start..self.start + self.count,
+        }
+    }
+
+    /// Iterates over the raw data in this slice, alongside the index of each entry
+    ///
+    /// Unlike [`iter`](Self::iter), the yielded references cannot navigate the archive, since
+    /// handing out more than one [`TableMut`] at a time would alias the archive pointer.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (u32, &mut T)> {
+        self.table.iter_range_mut(self.start, self.count)
+    }
+}
+
+/// The state of a lookup that can change without reserializing, taken by
+/// [`IndexLookup::snapshot_dynamic`] and [`BucketLookup::snapshot_dynamic`]
+pub struct LookupSnapshot<D> {
+    dynamic: D,
+    removed: BTreeSet<Hash40>,
+}
+
 /// A container for looking up table indexes from a [`hash`](Hash40)
 ///
 /// Like the [`Table`], the index lookup contains both a fixed-length array and a dynamic region.
@@ -467,6 +704,11 @@ impl<'a, T> IntoIterator for &TableSliceRef<'a, T> {
 /// The dynamic region of the lookup is a [`BTreeMap`] of [`Hash40`] -> [`Index`].
 pub struct IndexLookup {
     fixed: *mut [HashWithData],
+    /// Hashes in the fixed-size section that have been [`removed`](Self::remove)
+    ///
+    /// The entries keep their original index, since the game reads the same number of entries
+    /// from the lookup as from the table it indexes into
+    removed: BTreeSet<Hash40>,
     dynamic: BTreeMap<Hash40, u32>,
 }
 
@@ -485,10 +727,28 @@ impl IndexLookup {
 
         Self {
             fixed: slice,
+            removed: BTreeSet::new(),
             dynamic: BTreeMap::new(),
         }
     }
 
+    /// Clones the dynamic region and the removed entries of this lookup so that they can be
+    /// restored later
+    pub fn snapshot_dynamic(&self) -> LookupSnapshot<BTreeMap<Hash40, u32>> {
+        LookupSnapshot {
+            dynamic: self.dynamic.clone(),
+            removed: self.removed.clone(),
+        }
+    }
+
+    /// Replaces the dynamic region and the removed entries of this lookup with the ones from
+    /// [`Self::snapshot_dynamic`]
+    pub fn restore_dynamic(&mut self, snapshot: LookupSnapshot<BTreeMap<Hash40, u32>>) {
+        self.dynamic = snapshot.dynamic;
+        self.removed = snapshot.removed;
+    }
+
+    /// Removed entries are still written with their original index, see [`Self::remove`]
     pub unsafe fn write_and_update(&mut self, buffer: &mut [u8], offset: usize) {
         buffer[offset..offset + self.fixed_byte_len()]
             .copy_from_slice(unsafe { bytemuck::cast_slice(&*self.fixed) });
@@ -525,28 +785,32 @@ impl IndexLookup {
         unsafe { std::mem::size_of_val(&*self.fixed) }
     }
 
-    /// Checks if the provided hash is contained within this lookup
-    pub fn contains_key(&self, hash: Hash40) -> bool {
+    /// Finds the position of the hash in the fixed-size section, including removed entries
+    fn fixed_position(&self, hash: Hash40) -> Option<usize> {
         // SAFETY: See above
-        unsafe {
-            (*self.fixed)
-                .binary_search_by_key(&hash, |key| key.hash40())
-                .is_ok()
-                || self.dynamic.contains_key(&hash)
-        }
+        unsafe { (*self.fixed).binary_search_by_key(&hash, |key| key.hash40()) }.ok()
+    }
+
+    /// Checks if the provided hash is contained within this lookup and has not been removed
+    pub fn contains_key(&self, hash: Hash40) -> bool {
+        self.get(hash).is_some()
+    }
+
+    /// Same as [`Self::contains_key`]
+    pub fn contains_valid_key(&self, hash: Hash40) -> bool {
+        self.contains_key(hash)
     }
 
     /// Gets the index that the provided hash points to
     ///
     /// If this hash is not in this lookup, this method returns [`None`]
     pub fn get(&self, hash: Hash40) -> Option<u32> {
-        // SAFETY: See above
-        unsafe {
-            (*self.fixed)
-                .binary_search_by_key(&hash, |key| key.hash40())
-                .ok()
-                .map(|index| (*self.fixed)[index].data())
-                .or_else(|| self.dynamic.get(&hash).copied())
+        match self.fixed_position(hash) {
+            // SAFETY: See above
+            Some(pos) => {
+                (!self.removed.contains(&hash)).then(|| unsafe { (*self.fixed)[pos].data() })
+            }
+            None => self.dynamic.get(&hash).copied(),
         }
     }
 
@@ -558,8 +822,11 @@ impl IndexLookup {
     /// you are intending to set an index for an **existing** hash. If you want to set or insert, use [`Self::insert`]
     #[must_use = "Operation can fail if the hash is not present"]
     pub fn set(&mut self, hash: Hash40, new_index: u32) -> bool {
-        // SAFETY: See above
-        if let Ok(pos) = unsafe { (*self.fixed).binary_search_by_key(&hash, |key| key.hash40()) } {
+        if let Some(pos) = self.fixed_position(hash) {
+            if self.removed.contains(&hash) {
+                return false;
+            }
+            // SAFETY: See above
             unsafe {
                 (*self.fixed)[pos].set_data(new_index);
             }
@@ -576,65 +843,124 @@ impl IndexLookup {
     ///
     /// This will return whatever the previous index was
     pub fn insert(&mut self, hash: Hash40, index: u32) -> Option<u32> {
-        if let Ok(pos) = unsafe { (*self.fixed).binary_search_by_key(&hash, |key| key.hash40()) } {
-            unsafe {
-                let prev = (*self.fixed)[pos].data();
-                (*self.fixed)[pos].set_data(index);
-                Some(prev)
-            }
+        if let Some(pos) = self.fixed_position(hash) {
+            // SAFETY: See above
+            let prev = unsafe { (*self.fixed)[pos].data() };
+            unsafe { (*self.fixed)[pos].set_data(index) };
+            (!self.removed.remove(&hash)).then_some(prev)
         } else {
             self.dynamic.insert(hash, index)
         }
     }
 
+    /// Removes the provided hash from this lookup
+    ///
+    /// Entries in the dynamic region are removed outright. Entries in the fixed-size section
+    /// are marked as removed and will no longer be returned from [`Self::get`] or [`Self::iter`],
+    /// but they are still serialized with their original index since the game reads as many
+    /// entries from the lookup as there are in the table that it indexes into.
+    ///
+    /// This will return whatever the previous index was
+    pub fn remove(&mut self, hash: Hash40) -> Option<u32> {
+        if let Some(prev) = self.dynamic.remove(&hash) {
+            return Some(prev);
+        }
+
+        let pos = self.fixed_position(hash)?;
+        // SAFETY: See above
+        self.removed
+            .insert(hash)
+            .then(|| unsafe { (*self.fixed)[pos].data() })
+    }
+
     pub(crate) fn iter(&self) -> IndexLookupIter<'_> {
         // SAFETY: See above
-        let mut fixed = unsafe { (*self.fixed).iter() };
-        let mut dynamic = self.dynamic.iter();
-        IndexLookupIter {
-            current_fixed: fixed.next().map(|h| (h.hash40(), h.data())),
-            current_dynamic: dynamic.next().map(|(h, i)| (*h, *i)),
+        let fixed = unsafe { (*self.fixed).iter() };
+        let mut iter = IndexLookupIter {
+            remaining: fixed.len() - self.removed.len() + self.dynamic.len(),
+            current_fixed: None,
+            current_dynamic: None,
             fixed_iter: fixed,
-            dynamic_iter: dynamic,
-        }
+            dynamic_iter: self.dynamic.iter(),
+            removed: &self.removed,
+        };
+        iter.current_fixed = iter.next_fixed();
+        iter.current_dynamic = iter.next_dynamic();
+        iter
     }
 }
 
 pub(crate) struct IndexLookupIter<'a> {
+    remaining: usize,
     current_fixed: Option<(Hash40, u32)>,
     current_dynamic: Option<(Hash40, u32)>,
     fixed_iter: std::slice::Iter<'a, HashWithData>,
     dynamic_iter: std::collections::btree_map::Iter<'a, Hash40, u32>,
+    removed: &'a BTreeSet<Hash40>,
+}
+
+impl IndexLookupIter<'_> {
+    fn next_fixed(&mut self) -> Option<(Hash40, u32)> {
+        let removed = self.removed;
+        self.fixed_iter
+            .by_ref()
+            .find(|hash| !removed.contains(&hash.hash40()))
+            .map(|hash| (hash.hash40(), hash.data()))
+    }
+
+    fn next_dynamic(&mut self) -> Option<(Hash40, u32)> {
+        self.dynamic_iter
+            .next()
+            .map(|(hash, index)| (*hash, *index))
+    }
 }
 
 impl<'a> Iterator for IndexLookupIter<'a> {
     type Item = (Hash40, u32);
 
     fn next(&mut self) -> Option<Self::Item> {
+        self.remaining = self.remaining.saturating_sub(1);
         match (self.current_fixed, self.current_dynamic) {
             (None, None) => None,
             (Some((fixed_hash, fixed_index)), Some((dyn_hash, dyn_index))) => {
-                if fixed_hash < dyn_hash {
-                    self.current_fixed = self.fixed_iter.next().map(|h| (h.hash40(), h.data()));
-
-                    Some((fixed_hash, fixed_index))
-                } else {
-                    self.current_fixed = self.dynamic_iter.next().map(|(h, i)| (*h, *i));
-                    Some((dyn_hash, dyn_index))
+                match fixed_hash.cmp(&dyn_hash) {
+                    std::cmp::Ordering::Less => {
+                        self.current_fixed = self.next_fixed();
+                        Some((fixed_hash, fixed_index))
+                    }
+                    // `IndexLookup::insert` updates the fixed entry instead of adding a dynamic
+                    // one, but if both ever exist then the fixed one is what `IndexLookup::get`
+                    // returns, so we yield that and skip over the dynamic one
+                    std::cmp::Ordering::Equal => {
+                        self.remaining = self.remaining.saturating_sub(1);
+                        self.current_fixed = self.next_fixed();
+                        self.current_dynamic = self.next_dynamic();
+                        Some((fixed_hash, fixed_index))
+                    }
+                    std::cmp::Ordering::Greater => {
+                        self.current_dynamic = self.next_dynamic();
+                        Some((dyn_hash, dyn_index))
+                    }
                 }
             }
             (Some((hash, index)), None) => {
-                self.current_fixed = self.fixed_iter.next().map(|h| (h.hash40(), h.data()));
+                self.current_fixed = self.next_fixed();
                 Some((hash, index))
             }
             (None, Some((hash, index))) => {
-                self.current_dynamic = self.dynamic_iter.next().map(|(h, i)| (*h, *i));
+                self.current_dynamic = self.next_dynamic();
                 Some((hash, index))
             }
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
 
+impl ExactSizeIterator for IndexLookupIter<'_> {}
+
 /// Represents a bucket in a [`BucketLookup`]
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
@@ -655,7 +981,13 @@ pub(crate) struct Bucket {
 pub struct BucketLookup {
     fixed_hashes: *mut [HashWithData],
     fixed_buckets: *const [Bucket],
+    /// Hashes in the fixed-size section that have been [`removed`](Self::remove), these are
+    /// dropped when the lookup is reserialized
+    removed: BTreeSet<Hash40>,
     dynamic: Box<[BTreeMap<Hash40, u32>]>,
+    /// The number of entries across all of the dynamic buckets, tracked on insertion and removal
+    /// so that [`Self::load_factor`] doesn't have to visit every bucket
+    dynamic_count: usize,
 }
 
 impl BucketLookup {
@@ -685,20 +1017,42 @@ impl BucketLookup {
         Self {
             fixed_hashes: hash_slice,
             fixed_buckets: bucket_slice,
+            removed: BTreeSet::new(),
             dynamic: buckets.into_boxed_slice(),
+            dynamic_count: 0,
+        }
+    }
+
+    /// Clones the dynamic buckets and the removed entries of this lookup so that they can be
+    /// restored later
+    pub fn snapshot_dynamic(&self) -> LookupSnapshot<Box<[BTreeMap<Hash40, u32>]>> {
+        LookupSnapshot {
+            dynamic: self.dynamic.clone(),
+            removed: self.removed.clone(),
         }
     }
 
+    /// Replaces the dynamic buckets and the removed entries of this lookup with the ones from
+    /// [`Self::snapshot_dynamic`]
+    ///
+    /// The snapshot keeps whatever bucket count it was taken with, so this also undoes any
+    /// rebalancing that happened in the meantime
+    pub fn restore_dynamic(&mut self, snapshot: LookupSnapshot<Box<[BTreeMap<Hash40, u32>]>>) {
+        self.dynamic_count = snapshot.dynamic.iter().map(|bucket| bucket.len()).sum();
+        self.dynamic = snapshot.dynamic;
+        self.removed = snapshot.removed;
+    }
+
+    /// Removed entries are dropped from the new buffer, and the buckets are rebuilt around them
     pub unsafe fn write_and_update(&mut self, buffer: &mut [u8], offset: usize) {
-        let full_count = (*self.fixed_buckets)
-            .iter()
-            .map(|bucket| bucket.count)
-            .sum::<u32>()
-            + self
-                .dynamic
-                .iter()
-                .map(|dynamic| dynamic.len() as u32)
-                .sum::<u32>();
+        // The game expects the bucket count to stay the same, so any rebalancing of the
+        // dynamic section needs to be undone before we can merge it into the fixed section
+        let fixed_bucket_count = (&*self.fixed_buckets).len();
+        if self.dynamic.len() != fixed_bucket_count {
+            self.rebalance(fixed_bucket_count);
+        }
+
+        let full_count = self.len() as u32;
         buffer[offset..offset + 4].copy_from_slice(bytemuck::bytes_of(&full_count));
         buffer[offset + 4..offset + 8]
             .copy_from_slice(bytemuck::bytes_of(&(self.dynamic.len() as u32)));
@@ -719,24 +1073,30 @@ impl BucketLookup {
         for bucket_idx in 0..self.dynamic.len() {
             let old_bucket = unsafe { (&*self.fixed_buckets)[bucket_idx] };
             let dynamic_bucket = &self.dynamic[bucket_idx];
+
+            let mut fixed_count = 0;
+            for hash in unsafe {
+                &(&*self.fixed_hashes)
+                    [old_bucket.start as usize..(old_bucket.start + old_bucket.count) as usize]
+            } {
+                if !self.removed.contains(&hash.hash40()) {
+                    hash_slice[(hash_start + fixed_count) as usize] = *hash;
+                    fixed_count += 1;
+                }
+            }
+
             let new_bucket = Bucket {
                 start: hash_start,
-                count: old_bucket.count + dynamic_bucket.len() as u32,
+                count: fixed_count + dynamic_bucket.len() as u32,
             };
 
             unsafe {
                 (*bucket_slice)[bucket_idx] = new_bucket;
             }
 
-            hash_slice[hash_start as usize..(hash_start + old_bucket.count) as usize]
-                .copy_from_slice(unsafe {
-                    &(&*self.fixed_hashes)
-                        [old_bucket.start as usize..(old_bucket.start + old_bucket.count) as usize]
-                });
-
             if !dynamic_bucket.is_empty() {
                 for (idx, (hash, data)) in dynamic_bucket.iter().enumerate() {
-                    hash_slice[(hash_start + old_bucket.count) as usize + idx] =
+                    hash_slice[(hash_start + fixed_count) as usize + idx] =
                         HashWithData::new(*hash, *data);
                 }
 
@@ -749,17 +1109,19 @@ impl BucketLookup {
 
         self.fixed_buckets = bucket_slice;
         self.fixed_hashes = hash_slice;
+        self.removed.clear();
         self.dynamic.iter_mut().for_each(|bucket| bucket.clear());
+        self.dynamic_count = 0;
     }
 
     pub fn byte_len(&self) -> usize {
-        // Add extra 8 bytes for bucket count
+        // Add extra 8 bytes for bucket count, removed entries aren't written
         self.fixed_byte_len() + self.dynamic_byte_len() + 8
+            - self.removed.len() * std::mem::size_of::<HashWithData>()
     }
 
     pub fn dynamic_byte_len(&self) -> usize {
-        self.dynamic.iter().map(|map| map.len()).sum::<usize>()
-            * std::mem::size_of::<HashWithData>()
+        self.dynamic_count * std::mem::size_of::<HashWithData>()
     }
 
     /// Returns the size of the fixed-length section, in bytes
@@ -771,6 +1133,14 @@ impl BucketLookup {
         }
     }
 
+    /// Gets the index of the dynamic bucket that the provided hash belongs in
+    ///
+    /// This is not necessarily the same as the fixed bucket index, since the dynamic section
+    /// can be [`rebalanced`](Self::rebalance)
+    fn dynamic_bucket_index(&self, hash: Hash40) -> usize {
+        (hash.raw() as usize) % self.dynamic.len()
+    }
+
     fn borrow_bucket(&self, hash: Hash40) -> (usize, &[HashWithData]) {
         // SAFETY: See above
         unsafe {
@@ -779,7 +1149,7 @@ impl BucketLookup {
             let bucket = &(*self.fixed_buckets)[bucket_index];
 
             (
-                bucket_index,
+                self.dynamic_bucket_index(hash),
                 &(&*self.fixed_hashes)
                     [bucket.start as usize..(bucket.start + bucket.count) as usize],
             )
@@ -794,24 +1164,17 @@ impl BucketLookup {
             let bucket = &(*self.fixed_buckets)[bucket_index];
 
             (
-                bucket_index,
+                self.dynamic_bucket_index(hash),
                 &mut (&mut *self.fixed_hashes)
                     [bucket.start as usize..(bucket.start + bucket.count) as usize],
             )
         }
     }
 
-    /// Calculates the total length of the bucket lookup
+    /// Calculates the total length of the bucket lookup, not counting removed entries
     pub fn len(&self) -> usize {
         // SAFETY: See above
-        unsafe {
-            (&*self.fixed_buckets).len()
-                + self
-                    .dynamic
-                    .iter()
-                    .map(|dyn_bucket| dyn_bucket.len())
-                    .sum::<usize>()
-        }
+        unsafe { (&*self.fixed_hashes).len() - self.removed.len() + self.dynamic_count }
     }
 
     /// Returns the number of buckets
@@ -819,6 +1182,37 @@ impl BucketLookup {
         self.dynamic.len()
     }
 
+    /// Returns the average number of entries in each dynamic bucket
+    pub fn load_factor(&self) -> f32 {
+        if self.dynamic.is_empty() {
+            return 0.0;
+        }
+
+        self.dynamic_count as f32 / self.dynamic.len() as f32
+    }
+
+    /// Redistributes all of the entries in the dynamic section into `new_bucket_count` buckets
+    ///
+    /// This only affects the dynamic section, the fixed section keeps the bucket layout from the
+    /// archive. When this lookup is reserialized, the dynamic section is redistributed back into
+    /// the same number of buckets as the fixed section.
+    pub fn rebalance(&mut self, new_bucket_count: usize) {
+        assert!(new_bucket_count != 0);
+
+        let mut buckets = Vec::with_capacity(new_bucket_count);
+        for _ in 0..new_bucket_count {
+            buckets.push(BTreeMap::new());
+        }
+
+        for bucket in std::mem::take(&mut self.dynamic).into_vec() {
+            for (hash, index) in bucket {
+                buckets[(hash.raw() as usize) % new_bucket_count].insert(hash, index);
+            }
+        }
+
+        self.dynamic = buckets.into_boxed_slice();
+    }
+
     /// Returns an iterator over the **new** buckets
     pub(crate) fn buckets(&self) -> impl Iterator<Item = Bucket> + '_ {
         // SAFETY: See above
@@ -829,21 +1223,21 @@ impl BucketLookup {
                 .zip(self.dynamic.iter())
                 .map(move |(fixed, dynamic)| {
                     let start = prev_end;
-                    let count = fixed.count + dynamic.len() as u32;
+                    let removed = (&*self.fixed_hashes)
+                        [fixed.start as usize..(fixed.start + fixed.count) as usize]
+                        .iter()
+                        .filter(|hash| self.removed.contains(&hash.hash40()))
+                        .count() as u32;
+                    let count = fixed.count - removed + dynamic.len() as u32;
                     prev_end += count;
                     Bucket { start, count }
                 })
         }
     }
 
-    /// Checks if the provided hash is contained within this lookup
+    /// Checks if the provided hash is contained within this lookup and has not been removed
     pub fn contains_key(&self, hash: Hash40) -> bool {
-        let (bucket_index, hashes) = self.borrow_bucket(hash);
-
-        hashes
-            .binary_search_by_key(&hash, |key| key.hash40())
-            .is_ok()
-            || self.dynamic[bucket_index].contains_key(&hash)
+        self.get(hash).is_some()
     }
 
     /// Gets the index that the provided hash points to
@@ -852,11 +1246,15 @@ impl BucketLookup {
     pub fn get(&self, hash: Hash40) -> Option<u32> {
         let (bucket_index, hashes) = self.borrow_bucket(hash);
 
-        hashes
-            .binary_search_by_key(&hash, |key| key.hash40())
-            .ok()
-            .map(|index| hashes[index].data())
-            .or_else(|| self.dynamic[bucket_index].get(&hash).copied())
+        match hashes.binary_search_by_key(&hash, |key| key.hash40()) {
+            Ok(pos) => (!self.removed.contains(&hash)).then(|| hashes[pos].data()),
+            Err(_) => self.dynamic[bucket_index].get(&hash).copied(),
+        }
+    }
+
+    /// Same as [`Self::contains_key`]
+    pub fn contains_valid_key(&self, hash: Hash40) -> bool {
+        self.contains_key(hash)
     }
 
     /// Sets the index of the provided hash
@@ -870,6 +1268,9 @@ impl BucketLookup {
         let (bucket_index, hashes) = self.borrow_bucket_mut(hash);
 
         if let Ok(pos) = hashes.binary_search_by_key(&hash, |key| key.hash40()) {
+            if self.removed.contains(&hash) {
+                return false;
+            }
             hashes[pos].set_data(new_index);
             true
         } else if let Some(index) = self.dynamic[bucket_index].get_mut(&hash) {
@@ -889,9 +1290,37 @@ impl BucketLookup {
         if let Ok(pos) = hashes.binary_search_by_key(&hash, |key| key.hash40()) {
             let prev = hashes[pos].data();
             hashes[pos].set_data(index);
-            Some(prev)
+            (!self.removed.remove(&hash)).then_some(prev)
         } else {
-            self.dynamic[bucket_index].insert(hash, index)
+            let prev = self.dynamic[bucket_index].insert(hash, index);
+            if prev.is_none() {
+                self.dynamic_count += 1;
+            }
+            prev
+        }
+    }
+
+    /// Removes the provided hash from this lookup
+    ///
+    /// Entries in the dynamic section are removed outright. Entries in the fixed-size section
+    /// are marked as removed until the lookup is reserialized, which drops them.
+    ///
+    /// This will return whatever the previous index was
+    pub fn remove(&mut self, hash: Hash40) -> Option<u32> {
+        let (bucket_index, hashes) = self.borrow_bucket(hash);
+
+        match hashes.binary_search_by_key(&hash, |key| key.hash40()) {
+            Ok(pos) => {
+                let prev = hashes[pos].data();
+                self.removed.insert(hash).then_some(prev)
+            }
+            Err(_) => {
+                let prev = self.dynamic[bucket_index].remove(&hash);
+                if prev.is_some() {
+                    self.dynamic_count -= 1;
+                }
+                prev
+            }
         }
     }
 
@@ -902,20 +1331,24 @@ impl BucketLookup {
         let dynamic_buckets = &self.dynamic;
 
         BucketLookupIter {
-            bucket_count: self.dynamic.len(),
+            // Removed entries are skipped while iterating, so they don't count towards the length
+            remaining: self.len(),
+            bucket_count: fixed_buckets.len().max(self.dynamic.len()),
             current_bucket: 0,
             current_fixed: None,
             current_dynamic: None,
-            fixed_bucket: fixed_hashes.iter(),
-            dynamic_bucket: dynamic_buckets[0].iter(),
+            fixed_bucket: Default::default(),
+            dynamic_bucket: Default::default(),
             fixed_hashes,
             fixed_buckets,
             dynamic: dynamic_buckets,
+            removed: &self.removed,
         }
     }
 }
 
 pub(crate) struct BucketLookupIter<'a> {
+    remaining: usize,
     bucket_count: usize,
     current_bucket: usize,
 
@@ -928,51 +1361,100 @@ pub(crate) struct BucketLookupIter<'a> {
     fixed_hashes: &'a [HashWithData],
     fixed_buckets: &'a [Bucket],
     dynamic: &'a [BTreeMap<Hash40, u32>],
+    removed: &'a BTreeSet<Hash40>,
 }
 
-impl<'a> Iterator for BucketLookupIter<'a> {
-    type Item = (Hash40, u32);
+impl BucketLookupIter<'_> {
+    fn next_fixed(&mut self) -> Option<(Hash40, u32)> {
+        let removed = self.removed;
+        self.fixed_bucket
+            .by_ref()
+            .find(|hash| !removed.contains(&hash.hash40()))
+            .map(|hash| (hash.hash40(), hash.data()))
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        while self.current_fixed.is_none()
-            && self.current_dynamic.is_none()
-            && self.current_bucket < self.bucket_count
-        {
-            let fixed_bucket = self.fixed_buckets[self.current_bucket];
-            self.fixed_bucket = self.fixed_hashes
-                [fixed_bucket.start as usize..(fixed_bucket.start + fixed_bucket.count) as usize]
-                .iter();
+    fn next_dynamic(&mut self) -> Option<(Hash40, u32)> {
+        self.dynamic_bucket
+            .next()
+            .map(|(hash, index)| (*hash, *index))
+    }
 
-            self.dynamic_bucket = self.dynamic[self.current_bucket].iter();
+    /// Loads the next bucket, returning `false` once every bucket has been visited
+    ///
+    /// This must only be called once both the fixed and dynamic sides of the current bucket have
+    /// been exhausted, otherwise the remaining entries of the current bucket get skipped
+    fn advance_bucket(&mut self) -> bool {
+        debug_assert!(self.current_fixed.is_none() && self.current_dynamic.is_none());
 
-            self.current_fixed = self.fixed_bucket.next().map(|h| (h.hash40(), h.data()));
-            self.current_dynamic = self.dynamic_bucket.next().map(|(h, i)| (*h, *i));
-            self.current_bucket += 1;
+        if self.current_bucket >= self.bucket_count {
+            return false;
         }
 
-        match (self.current_fixed, self.current_dynamic) {
-            (None, None) => None,
-            (Some((hash, index)), None) => {
-                self.current_fixed = self.fixed_bucket.next().map(|h| (h.hash40(), h.data()));
-                Some((hash, index))
-            }
-            (None, Some((hash, index))) => {
-                self.current_dynamic = self.dynamic_bucket.next().map(|(h, i)| (*h, *i));
-                Some((hash, index))
-            }
-            (Some((fixed_hash, fixed_index)), Some((dyn_hash, dyn_index))) => {
-                if fixed_hash < dyn_hash {
-                    self.current_fixed = self.fixed_bucket.next().map(|h| (h.hash40(), h.data()));
-                    Some((fixed_hash, fixed_index))
-                } else {
-                    self.current_dynamic = self.dynamic_bucket.next().map(|(h, i)| (*h, *i));
-                    Some((dyn_hash, dyn_index))
+        // If the dynamic section has been rebalanced, the bucket counts won't match
+        // so the missing buckets on either side are treated as empty
+        let fixed_bucket = self
+            .fixed_buckets
+            .get(self.current_bucket)
+            .copied()
+            .unwrap_or(Bucket { start: 0, count: 0 });
+        self.fixed_bucket = self.fixed_hashes
+            [fixed_bucket.start as usize..(fixed_bucket.start + fixed_bucket.count) as usize]
+            .iter();
+
+        self.dynamic_bucket = match self.dynamic.get(self.current_bucket) {
+            Some(bucket) => bucket.iter(),
+            None => Default::default(),
+        };
+
+        self.current_fixed = self.next_fixed();
+        self.current_dynamic = self.next_dynamic();
+        self.current_bucket += 1;
+        true
+    }
+}
+
+impl<'a> Iterator for BucketLookupIter<'a> {
+    type Item = (Hash40, u32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match (self.current_fixed, self.current_dynamic) {
+                (None, None) => {
+                    if !self.advance_bucket() {
+                        return None;
+                    }
+                }
+                (Some(fixed), None) => {
+                    self.remaining -= 1;
+                    self.current_fixed = self.next_fixed();
+                    return Some(fixed);
+                }
+                (None, Some(dynamic)) => {
+                    self.remaining -= 1;
+                    self.current_dynamic = self.next_dynamic();
+                    return Some(dynamic);
+                }
+                (Some(fixed), Some(dynamic)) => {
+                    self.remaining -= 1;
+                    if fixed.0 < dynamic.0 {
+                        self.current_fixed = self.next_fixed();
+                        return Some(fixed);
+                    } else {
+                        self.current_dynamic = self.next_dynamic();
+                        return Some(dynamic);
+                    }
                 }
             }
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
 
+impl ExactSizeIterator for BucketLookupIter<'_> {}
+
 impl<T: Debug> Debug for TableRef<'_, T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         T::fmt(self, f)
@@ -992,3 +1474,236 @@ impl<T: Debug> Debug for TableSliceRef<'_, T> {
             .finish()
     }
 }
+
+impl<T: Debug> Debug for TableSliceMut<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.range().filter_map(|index| self.table.get(index)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(path: &str) -> Hash40 {
+        Hash40::const_new(path)
+    }
+
+    /// Builds the fixed-size section of an [`IndexLookup`], sorted the same way as the archive
+    fn index_lookup_buffer(entries: &[(&str, u32)]) -> Vec<HashWithData> {
+        let mut buffer = entries
+            .iter()
+            .map(|(path, index)| HashWithData::new(hash(path), *index))
+            .collect::<Vec<_>>();
+        buffer.sort_unstable_by_key(|hash| hash.hash40());
+        buffer
+    }
+
+    /// Builds the fixed-size section of a [`BucketLookup`], as `u32`s so that it is aligned
+    fn bucket_lookup_buffer(entries: &[(&str, u32)], bucket_count: usize) -> Vec<u32> {
+        let mut buckets = vec![vec![]; bucket_count];
+        for (path, index) in entries {
+            let hash = hash(path);
+            buckets[hash.raw() as usize % bucket_count].push(HashWithData::new(hash, *index));
+        }
+
+        let mut start = 0;
+        let mut bucket_headers = vec![];
+        let mut hashes = vec![];
+        for mut bucket in buckets {
+            bucket.sort_unstable_by_key(|hash| hash.hash40());
+            bucket_headers.push(Bucket {
+                start,
+                count: bucket.len() as u32,
+            });
+            start += bucket.len() as u32;
+            hashes.extend(bucket);
+        }
+
+        let mut buffer = bytemuck::cast_slice::<_, u32>(&bucket_headers).to_vec();
+        buffer.extend_from_slice(bytemuck::cast_slice(&hashes));
+        buffer
+    }
+
+    #[test]
+    fn index_lookup_remove_fixed() {
+        let mut buffer = index_lookup_buffer(&[("a", 0), ("b", 1), ("c", 2)]);
+        let count = buffer.len();
+        let mut lookup = unsafe { IndexLookup::new(bytemuck::cast_slice_mut(&mut buffer), count) };
+
+        assert_eq!(lookup.remove(hash("b")), Some(1));
+        assert_eq!(lookup.get(hash("b")), None);
+        assert!(!lookup.contains_key(hash("b")));
+        assert!(!lookup.contains_valid_key(hash("b")));
+        assert_eq!(lookup.get(hash("a")), Some(0));
+        assert_eq!(lookup.get(hash("c")), Some(2));
+
+        assert_eq!(lookup.remove(hash("b")), None);
+        assert!(!lookup.set(hash("b"), 3));
+    }
+
+    #[test]
+    fn index_lookup_remove_dynamic() {
+        let mut buffer = index_lookup_buffer(&[("a", 0)]);
+        let count = buffer.len();
+        let mut lookup = unsafe { IndexLookup::new(bytemuck::cast_slice_mut(&mut buffer), count) };
+
+        assert_eq!(lookup.insert(hash("d"), 3), None);
+        assert_eq!(lookup.remove(hash("d")), Some(3));
+        assert_eq!(lookup.get(hash("d")), None);
+        assert_eq!(lookup.remove(hash("d")), None);
+    }
+
+    #[test]
+    fn index_lookup_insert_after_remove() {
+        let mut buffer = index_lookup_buffer(&[("a", 0), ("b", 1)]);
+        let count = buffer.len();
+        let mut lookup = unsafe { IndexLookup::new(bytemuck::cast_slice_mut(&mut buffer), count) };
+
+        assert_eq!(lookup.remove(hash("a")), Some(0));
+        assert_eq!(lookup.insert(hash("a"), 5), None);
+        assert_eq!(lookup.get(hash("a")), Some(5));
+    }
+
+    #[test]
+    fn index_lookup_restore_undoes_remove() {
+        let mut buffer = index_lookup_buffer(&[("a", 0), ("b", 1)]);
+        let count = buffer.len();
+        let mut lookup = unsafe { IndexLookup::new(bytemuck::cast_slice_mut(&mut buffer), count) };
+
+        let snapshot = lookup.snapshot_dynamic();
+        lookup.remove(hash("a"));
+        lookup.insert(hash("c"), 2);
+        lookup.restore_dynamic(snapshot);
+
+        assert_eq!(lookup.get(hash("a")), Some(0));
+        assert_eq!(lookup.get(hash("c")), None);
+    }
+
+    #[test]
+    fn index_lookup_iter_skips_removed() {
+        let mut buffer = index_lookup_buffer(&[("a", 0), ("b", 1), ("c", 2)]);
+        let count = buffer.len();
+        let mut lookup = unsafe { IndexLookup::new(bytemuck::cast_slice_mut(&mut buffer), count) };
+
+        lookup.remove(hash("b"));
+        lookup.insert(hash("d"), 3);
+
+        let iter = lookup.iter();
+        assert_eq!(iter.len(), 3);
+        let entries = iter.collect::<Vec<_>>();
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|(entry, _)| *entry != hash("b")));
+    }
+
+    #[test]
+    fn index_lookup_iter_yields_duplicate_hash_once() {
+        let mut buffer = index_lookup_buffer(&[("a", 0), ("b", 1)]);
+        let count = buffer.len();
+        let mut lookup = unsafe { IndexLookup::new(bytemuck::cast_slice_mut(&mut buffer), count) };
+
+        // `IndexLookup::insert` never does this, but the iterator shouldn't rely on that
+        lookup.dynamic.insert(hash("a"), 7);
+
+        let entries = lookup.iter().collect::<Vec<_>>();
+        assert_eq!(
+            entries
+                .iter()
+                .filter(|(entry, _)| *entry == hash("a"))
+                .count(),
+            1
+        );
+        assert!(entries.contains(&(hash("a"), 0)));
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn bucket_lookup_remove() {
+        let entries = [("a", 0), ("b", 1), ("c", 2), ("d", 3)];
+        let mut buffer = bucket_lookup_buffer(&entries, 2);
+        let mut lookup =
+            unsafe { BucketLookup::new(bytemuck::cast_slice_mut(&mut buffer), entries.len(), 2) };
+
+        assert_eq!(lookup.remove(hash("b")), Some(1));
+        assert_eq!(lookup.get(hash("b")), None);
+        assert!(!lookup.contains_key(hash("b")));
+        assert_eq!(lookup.remove(hash("b")), None);
+        assert_eq!(lookup.len(), 3);
+
+        lookup.insert(hash("e"), 4);
+        assert_eq!(lookup.remove(hash("e")), Some(4));
+        assert_eq!(lookup.remove(hash("e")), None);
+
+        let iter = lookup.iter();
+        assert_eq!(iter.len(), 3);
+        assert!(iter.map(|(entry, _)| entry).all(|entry| entry != hash("b")));
+    }
+
+    #[test]
+    fn bucket_lookup_restore_undoes_remove() {
+        let entries = [("a", 0), ("b", 1)];
+        let mut buffer = bucket_lookup_buffer(&entries, 2);
+        let mut lookup =
+            unsafe { BucketLookup::new(bytemuck::cast_slice_mut(&mut buffer), entries.len(), 2) };
+
+        let snapshot = lookup.snapshot_dynamic();
+        lookup.remove(hash("a"));
+        lookup.insert(hash("c"), 2);
+        lookup.restore_dynamic(snapshot);
+
+        assert_eq!(lookup.get(hash("a")), Some(0));
+        assert_eq!(lookup.get(hash("c")), None);
+        assert_eq!(lookup.len(), 2);
+    }
+
+    #[test]
+    fn bucket_lookup_write_drops_removed() {
+        let entries = [("a", 0), ("b", 1), ("c", 2), ("d", 3)];
+        let mut buffer = bucket_lookup_buffer(&entries, 2);
+        let mut lookup =
+            unsafe { BucketLookup::new(bytemuck::cast_slice_mut(&mut buffer), entries.len(), 2) };
+
+        lookup.remove(hash("b"));
+        lookup.insert(hash("e"), 4);
+
+        let byte_len = lookup.byte_len();
+        let mut new_buffer = vec![0u32; byte_len / 4];
+        unsafe { lookup.write_and_update(bytemuck::cast_slice_mut(&mut new_buffer), 0) };
+
+        assert_eq!(new_buffer[0], 4);
+        assert_eq!(new_buffer[1], 2);
+        assert_eq!(
+            byte_len,
+            8 + 2 * std::mem::size_of::<Bucket>() + 4 * std::mem::size_of::<HashWithData>()
+        );
+        assert_eq!(lookup.buckets().map(|bucket| bucket.count).sum::<u32>(), 4);
+        assert_eq!(lookup.get(hash("b")), None);
+        for (path, index) in [("a", 0), ("c", 2), ("d", 3), ("e", 4)] {
+            assert_eq!(lookup.get(hash(path)), Some(index));
+        }
+    }
+
+    #[test]
+    fn bucket_lookup_load_factor_tracks_dynamic_entries() {
+        let mut buffer = bucket_lookup_buffer(&[("a", 0)], 2);
+        let mut lookup = unsafe { BucketLookup::new(bytemuck::cast_slice_mut(&mut buffer), 1, 2) };
+
+        assert_eq!(lookup.load_factor(), 0.0);
+        lookup.insert(hash("b"), 1);
+        lookup.insert(hash("c"), 2);
+        lookup.insert(hash("c"), 3);
+        assert_eq!(lookup.load_factor(), 1.0);
+        assert_eq!(lookup.len(), 3);
+
+        lookup.rebalance(4);
+        assert_eq!(lookup.load_factor(), 0.5);
+
+        lookup.remove(hash("b"));
+        lookup.remove(hash("b"));
+        lookup.remove(hash("a"));
+        assert_eq!(lookup.load_factor(), 0.25);
+        assert_eq!(lookup.len(), 1);
+    }
+}
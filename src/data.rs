@@ -8,7 +8,7 @@ use camino::{Utf8Path, Utf8PathBuf};
 use smash_hash::Hash40;
 
 use crate::{
-    containers::{TableMut, TableRef, TableSliceRef},
+    containers::{TableMut, TableRef, TableSliceIter, TableSliceRef},
     HashDisplay, LocalePreferences,
 };
 
@@ -214,7 +214,11 @@ impl HashWithData {
     const DATA_READ_MASK: u32 = 0xFFFF_FF00;
     const DATA_WRITE_MASK: u32 = 0x00FF_FFFF;
 
+    /// The largest value that can be stored in the data field
+    pub const MAX_DATA: u32 = Self::DATA_WRITE_MASK;
+
     pub const fn new(hash: Hash40, data: u32) -> Self {
+        debug_assert!(data <= Self::MAX_DATA, "HashWithData data field overflow");
         Self {
             crc: hash.crc32(),
             len_and_data: hash.length() as u32 | ((data & Self::DATA_WRITE_MASK) << 8),
@@ -243,6 +247,11 @@ impl HashWithData {
     }
 
     pub fn set_data(&mut self, data: u32) {
+        debug_assert!(
+            data <= Self::MAX_DATA,
+            "HashWithData data field overflow: {:#x}",
+            data
+        );
         self.len_and_data = (self.len_and_data & 0xFF) | ((data & Self::DATA_WRITE_MASK) << 8);
     }
 }
@@ -257,6 +266,14 @@ bitflags::bitflags! {
         const IS_LOCALIZED_VERSIONED_DATA = 1 << 3;
     }
 
+    /// Bit layout:
+    /// - `0..=14`: the buffer alignment the game loads the file with, read by `jemalloc_hook`
+    ///   as `bits() & 0x7FFF` (this overlaps with `IS_REGULAR_FILE` and `IS_GRAPHICS_ARCHIVE`)
+    /// - `15..=21`: vanilla flags
+    /// - `27..=31`: flags that only stratus sets, none of which are inside of the alignment mask
+    ///
+    /// Everything is unsigned, so `IS_RESHARED` being the top bit does not change any of the
+    /// masking above
     #[repr(transparent)]
     #[derive(Debug, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
     pub struct FileInfoFlags : u32 {
@@ -270,11 +287,17 @@ bitflags::bitflags! {
         // Stratus exclusive flags, set by file table patching
         // for customized behavior or further diagnostic information at runtime
 
+        /// Indicates that the file was shared in vanilla but has been given its own data
+        const IS_UNSHARED = 1 << 27;
+        /// Indicates that the file info was created by stratus for a new file or a new
+        /// costume slot, instead of coming from the vanilla `data.arc`
+        const IS_CUSTOM_SLOT = 1 << 28;
         /// Indicates that a file was retargeted. This means that the file needs to stay shared
         /// to a file even if that file has a different file name. This allows us to work with
         /// files that conceptually make sense to stay shared, like Dark Samus's `bunshin` article
         const IS_RETARGETED = 1 << 29;
         const IS_GROUP_FIXED = 1 << 30;
+        /// `0x8000_0000`
         const IS_RESHARED = 1 << 31;
     }
 
@@ -306,7 +329,14 @@ pub struct FileData {
 }
 
 impl FileData {
-    pub fn new_for_unsharing(size: u32, offset: u32) -> Self {
+    /// Creates file data for a file that is loaded from the SD card instead of the data.arc
+    ///
+    /// The compressed size is set to 0 so that when `ResLoadingThread` loads the file as part of a
+    /// package or group, it does not advance its buffer cursor for data that doesn't exist in the
+    /// data.arc. This only makes sense for files with a [`FileLoadMethod::Owned`] load method, as
+    /// those are the only ones that get loaded as single files through our hook, which provides
+    /// the real data.
+    pub fn new_sd_backed(size: u32, offset: u32) -> Self {
         Self {
             in_group_offset: offset,
             compressed_size: 0,
@@ -315,6 +345,40 @@ impl FileData {
         }
     }
 
+    /// Creates file data for a brand new file that only exists on the SD card
+    ///
+    /// See [`FileData::new_sd_backed`] for why the compressed size is 0
+    pub fn new_owned_from_sd(decompressed_size: u32) -> Self {
+        Self::new_sd_backed(decompressed_size, 0)
+    }
+
+    /// Creates file data for a zstd compressed file in the data.arc
+    #[allow(dead_code)]
+    pub fn new_compressed(
+        compressed_size: u32,
+        decompressed_size: u32,
+        in_group_offset: u32,
+    ) -> Self {
+        Self {
+            in_group_offset,
+            compressed_size,
+            decompressed_size,
+            flags: FileFlags::IS_ZSTD_COMPRESSION | FileFlags::IS_COMPRESSED,
+        }
+    }
+
+    /// Creates file data for an uncompressed file in the data.arc, which is stored with the same
+    /// compressed and decompressed size
+    #[allow(dead_code)]
+    pub fn new_uncompressed(decompressed_size: u32, in_group_offset: u32) -> Self {
+        Self {
+            in_group_offset,
+            compressed_size: decompressed_size,
+            decompressed_size,
+            flags: FileFlags::empty(),
+        }
+    }
+
     pub fn group_offset(&self) -> u32 {
         self.in_group_offset
     }
@@ -347,7 +411,8 @@ pub enum FileLoadMethod {
     // Index is for FileInfo
     PackageSkip(u32),
 
-    Unknown,
+    // Raw value of the load method, kind 0x05 is used by the game but its purpose is unknown
+    Unknown(u32),
 
     // Index is for FileEntity
     SharedButOwned(u32),
@@ -357,6 +422,8 @@ pub enum FileLoadMethod {
 }
 
 impl FileLoadMethod {
+    const UNDOCUMENTED_KIND: u8 = 0x05;
+
     pub fn is_owned(&self) -> bool {
         matches!(self, Self::Owned(_))
     }
@@ -364,6 +431,15 @@ impl FileLoadMethod {
     pub fn is_skip(&self) -> bool {
         matches!(self, Self::PackageSkip(_))
     }
+
+    /// Returns true if the kind of this load method is not one that the vanilla archive uses
+    pub fn is_unrecognized(&self) -> bool {
+        matches!(self, Self::Unknown(_)) && self.raw_kind() != Self::UNDOCUMENTED_KIND
+    }
+
+    pub fn raw_kind(&self) -> u8 {
+        (u32::from(*self) >> 24) as u8
+    }
 }
 
 impl From<u32> for FileLoadMethod {
@@ -374,10 +450,9 @@ impl From<u32> for FileLoadMethod {
             0x00 => Self::Unowned(value),
             0x01 => Self::Owned(value),
             0x03 => Self::PackageSkip(value),
-            0x05 => Self::Unknown,
             0x09 => Self::SharedButOwned(value),
             0x10 => Self::UnsupportedRegionLocale(value),
-            _ => panic!("Unsuppored load method {:#02x}", kind),
+            _ => Self::Unknown((kind << 24) | value),
         }
     }
 }
@@ -388,7 +463,7 @@ impl From<FileLoadMethod> for u32 {
             FileLoadMethod::Unowned(value) => (0x00, value),
             FileLoadMethod::Owned(value) => (0x01, value),
             FileLoadMethod::PackageSkip(value) => (0x03, value),
-            FileLoadMethod::Unknown => (0x05, 0),
+            FileLoadMethod::Unknown(raw) => return raw,
             FileLoadMethod::SharedButOwned(value) => (0x09, value),
             FileLoadMethod::UnsupportedRegionLocale(value) => (0x10, value),
         };
@@ -476,6 +551,10 @@ impl FileInfo {
         self.flags.intersects(FileInfoFlags::IS_LOCALIZED)
     }
 
+    pub fn is_custom_slot(&self) -> bool {
+        self.flags.intersects(FileInfoFlags::IS_CUSTOM_SLOT)
+    }
+
     fn desc_index(&self) -> u32 {
         if self.is_regional() {
             self.desc + LocalePreferences::get().region as u32 + 1
@@ -490,6 +569,20 @@ impl FileInfo {
         self.flags
     }
 
+    pub fn path_index(&self) -> u32 {
+        self.path
+    }
+
+    pub fn entity_index(&self) -> u32 {
+        self.entity
+    }
+
+    /// Gets the index of the first descriptor for this file, without taking the
+    /// current locale or region into account
+    pub fn base_desc_index(&self) -> u32 {
+        self.desc
+    }
+
     pub fn set_flags(&mut self, flags: FileInfoFlags) {
         self.flags = flags;
     }
@@ -531,6 +624,15 @@ impl FileInfo {
         }
         self.desc = desc;
     }
+
+    /// Makes the file non-localized and sets its descriptor in one step
+    ///
+    /// Unlike [`FileInfo::set_desc`], this never panics. The caller is responsible for making sure that
+    /// the new descriptor is valid to load without the locale/region offset being applied to it.
+    pub fn force_set_desc_and_clear_locale_flags(&mut self, desc: u32) {
+        self.set_non_localized();
+        self.desc = desc;
+    }
 }
 
 pub enum TryFilePathResult<'a> {
@@ -663,9 +765,10 @@ pub struct FilePath {
 impl FilePath {
     pub fn from_utf8_path(path: impl AsRef<Utf8Path>) -> Self {
         let path = path.as_ref();
-        let parent_str = path.parent().unwrap().as_str();
+        // Paths without a directory (or the root itself) have an empty parent, not "/"
+        let parent_str = path.parent().map(Utf8Path::as_str).unwrap_or("");
         let mut parent = Hash40::const_new(parent_str);
-        if !parent_str.ends_with('/') {
+        if !parent_str.is_empty() && !parent_str.ends_with('/') {
             parent = parent.const_with("/");
         }
         let file_name = path
@@ -828,6 +931,16 @@ impl FilePackage {
         (self.flags & flags) == flags
     }
 
+    #[allow(dead_code)]
+    pub fn info_start(&self) -> u32 {
+        self.info_start
+    }
+
+    #[allow(dead_code)]
+    pub fn info_count(&self) -> u32 {
+        self.info_count
+    }
+
     pub fn info_range(&self) -> Range<u32> {
         self.info_start..self.info_start + self.info_count
     }
@@ -875,6 +988,22 @@ impl<'a> TableRef<'a, FilePackage> {
         self.archive().get_file_group(dg.redirection)
     }
 
+    /// Fetches the group that holds the files for this package, following the sym link if this
+    /// package has one
+    pub fn resolve_group(&self) -> Option<TableRef<'a, FileGroup>> {
+        if self.has_sym_link() {
+            let target = self.sym_link();
+            target
+                .has_file_group()
+                .then(|| target.file_group())
+                .flatten()
+        } else if self.has_file_group() {
+            self.file_group()
+        } else {
+            None
+        }
+    }
+
     pub fn child_packages(&self) -> TableSliceRef<'a, FilePackageChild> {
         self.archive()
             .get_file_package_child_slice(self.child_start, self.child_count)
@@ -900,11 +1029,23 @@ impl FilePackageChild {
     pub fn path(&self) -> Hash40 {
         self.0.hash40()
     }
+
+    /// The index of the child package in the package table, without looking it up
+    pub fn package_index(&self) -> u32 {
+        self.0.data()
+    }
+
+    #[allow(dead_code)]
+    pub fn set_package_index(&mut self, index: u32) {
+        self.0.set_data(index);
+    }
 }
 
 impl<'a> TableRef<'a, FilePackageChild> {
     pub fn package(&self) -> TableRef<'a, FilePackage> {
-        self.archive().get_file_package(self.0.data()).unwrap()
+        self.archive()
+            .get_file_package(self.package_index())
+            .unwrap()
     }
 }
 
@@ -920,17 +1061,36 @@ pub struct FileGroup {
 }
 
 impl FileGroup {
-    pub fn new_for_new_package() -> Self {
+    /// The size given to new groups by default. ResLoadingThread skips groups whose size is zero, so this
+    /// only needs to be non-zero for our files to get picked up by the streaming decompressor.
+    pub const DEFAULT_NEW_PACKAGE_SIZE: u32 = 0x10;
+
+    pub fn new_for_new_package(compressed_size: u32, decompressed_size: u32) -> Self {
         Self {
             archive_offset: [0; 2],
-            decompressed_size: 0x10,
-            compressed_size: 0x10,
+            decompressed_size,
+            compressed_size,
             child_count: 0,
             child_start: 0,
             redirection: 0xffffff,
         }
     }
 
+    pub fn new_for_new_package_default() -> Self {
+        Self::new_for_new_package(
+            Self::DEFAULT_NEW_PACKAGE_SIZE,
+            Self::DEFAULT_NEW_PACKAGE_SIZE,
+        )
+    }
+
+    pub fn archive_offset(&self) -> (u32, u32) {
+        (self.archive_offset[0], self.archive_offset[1])
+    }
+
+    pub fn set_archive_offset(&mut self, lo: u32, hi: u32) {
+        self.archive_offset = [lo, hi];
+    }
+
     pub fn redirection(&self) -> u32 {
         self.redirection
     }
@@ -954,6 +1114,16 @@ impl<'a> TableRef<'a, FileGroup> {
             .get_file_info_slice(self.child_start, self.child_count)
             .unwrap()
     }
+
+    #[allow(dead_code)]
+    pub fn iter_infos(&self) -> TableSliceIter<'a, FileInfo> {
+        self.file_info_slice().into_iter()
+    }
+
+    #[allow(dead_code)]
+    pub fn info_count(&self) -> u32 {
+        self.child_count
+    }
 }
 
 #[repr(C)]
@@ -963,12 +1133,57 @@ pub struct StreamData {
     offset: u64,
 }
 
+impl StreamData {
+    pub fn new(size: u64, offset: u64) -> Self {
+        Self { size, offset }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: u64) {
+        self.size = size;
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn set_offset(&mut self, offset: u64) {
+        self.offset = offset;
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
 pub struct StreamEntity {
     stream_data: u32,
 }
 
+impl StreamEntity {
+    pub fn new(stream_data: u32) -> Self {
+        Self { stream_data }
+    }
+
+    pub fn data_index(&self) -> u32 {
+        self.stream_data
+    }
+}
+
+impl<'a> TableRef<'a, StreamEntity> {
+    pub fn data(&self) -> TableRef<'a, StreamData> {
+        self.archive().get_stream_data(self.stream_data).unwrap()
+    }
+}
+
+impl<'a> TableMut<'a, StreamEntity> {
+    pub fn data_mut(self) -> TableMut<'a, StreamData> {
+        let index = self.stream_data;
+        self.into_archive_mut().get_stream_data_mut(index).unwrap()
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
 pub struct StreamFolder {
@@ -976,6 +1191,40 @@ pub struct StreamFolder {
     child_start_index: u32,
 }
 
+impl StreamFolder {
+    pub fn new(path: impl IntoHash, child_start_index: u32) -> Self {
+        Self {
+            name_and_child_count: HashWithData::new(path.into_hash(), 0x0),
+            child_start_index,
+        }
+    }
+
+    pub fn path(&self) -> Hash40 {
+        self.name_and_child_count.hash40()
+    }
+
+    pub fn child_count(&self) -> u32 {
+        self.name_and_child_count.data()
+    }
+
+    pub fn set_child_count(&mut self, count: u32) {
+        self.name_and_child_count.set_data(count);
+    }
+
+    pub fn child_start_index(&self) -> u32 {
+        self.child_start_index
+    }
+
+    pub fn set_child_start_index(&mut self, index: u32) {
+        self.child_start_index = index;
+    }
+
+    /// The stream paths in this folder, which are always stored next to each other
+    pub fn child_range(&self) -> Range<u32> {
+        self.child_start_index..self.child_start_index + self.child_count()
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
 pub struct StreamPath {
@@ -983,6 +1232,72 @@ pub struct StreamPath {
     flags: StreamFileFlags,
 }
 
+impl StreamPath {
+    pub fn new(path: impl IntoHash, entity: u32, flags: StreamFileFlags) -> Self {
+        Self {
+            path_and_desc: HashWithData::new(path.into_hash(), entity),
+            flags,
+        }
+    }
+
+    pub fn path(&self) -> Hash40 {
+        self.path_and_desc.hash40()
+    }
+
+    pub fn flags(&self) -> StreamFileFlags {
+        self.flags
+    }
+
+    /// The first stream entity of this path, regional and localized paths own one entity per
+    /// region/locale starting here
+    pub fn entity_start(&self) -> u32 {
+        self.path_and_desc.data()
+    }
+
+    pub fn set_entity_start(&mut self, entity: u32) {
+        self.path_and_desc.set_data(entity);
+    }
+
+    /// The number of stream entities starting at [`StreamPath::entity_start`] that this path owns
+    pub fn entity_count(&self) -> u32 {
+        if self.flags.intersects(StreamFileFlags::IS_REGIONAL) {
+            Region::COUNT as u32
+        } else if self.flags.intersects(StreamFileFlags::IS_LOCALIZED) {
+            Locale::COUNT as u32
+        } else {
+            1
+        }
+    }
+
+    pub fn entity_index(&self) -> u32 {
+        let index = self.path_and_desc.data();
+        if self.flags.intersects(StreamFileFlags::IS_REGIONAL) {
+            index + LocalePreferences::get().region as u32
+        } else if self.flags.intersects(StreamFileFlags::IS_LOCALIZED) {
+            index + LocalePreferences::get().locale as u32
+        } else {
+            index
+        }
+    }
+}
+
+impl<'a> TableRef<'a, StreamPath> {
+    pub fn entity(&self) -> TableRef<'a, StreamEntity> {
+        self.archive()
+            .get_stream_entity(self.entity_index())
+            .unwrap()
+    }
+}
+
+impl<'a> TableMut<'a, StreamPath> {
+    pub fn entity_mut(self) -> TableMut<'a, StreamEntity> {
+        let index = self.entity_index();
+        self.into_archive_mut()
+            .get_stream_entity_mut(index)
+            .unwrap()
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
 pub struct SearchFolder {
@@ -1039,6 +1354,11 @@ impl SearchFolder {
     pub fn has_first_child(&self) -> bool {
         self.first_child_index != u32::MAX
     }
+
+    /// The link index of the first child of this folder, if it has any children
+    pub fn first_child_index(&self) -> Option<u32> {
+        self.has_first_child().then_some(self.first_child_index)
+    }
 }
 
 impl<'a> TableRef<'a, SearchFolder> {
@@ -1048,6 +1368,52 @@ impl<'a> TableRef<'a, SearchFolder> {
             .unwrap()
             .path()
     }
+
+    /// Iterates over the direct children (both files and folders) of this folder
+    pub fn iter_children(&self) -> SearchPathWalker<'a> {
+        SearchPathWalker(self.has_first_child().then(|| self.first_child()), 0)
+    }
+
+    pub fn count_children(&self) -> usize {
+        self.iter_children().count()
+    }
+}
+
+/// The most entries that a [`SearchPath`] chain can have before it is assumed to be a cycle,
+/// which is far more than any folder in the vanilla archive has
+pub const MAX_SEARCH_PATH_CHAIN: usize = 65536;
+
+#[track_caller]
+fn step_search_path_chain(steps: &mut usize) {
+    *steps += 1;
+    assert!(
+        *steps <= MAX_SEARCH_PATH_CHAIN,
+        "Search path chain is longer than {MAX_SEARCH_PATH_CHAIN} entries, it likely contains a cycle"
+    );
+}
+
+/// Walks the linked list of [`SearchPath`]s belonging to a folder, panicking if the list is
+/// longer than [`MAX_SEARCH_PATH_CHAIN`]
+pub struct SearchPathWalker<'a>(Option<TableRef<'a, SearchPath>>, usize);
+
+impl<'a> SearchPathWalker<'a> {
+    pub fn new(start: TableRef<'a, SearchPath>) -> Self {
+        Self(Some(start), 0)
+    }
+}
+
+impl<'a> Iterator for SearchPathWalker<'a> {
+    type Item = TableRef<'a, SearchPath>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.0.take()?;
+        step_search_path_chain(&mut self.1);
+        if !current.is_end() {
+            self.0 = Some(current.next());
+        }
+
+        Some(current)
+    }
 }
 
 impl<'a> TableMut<'a, SearchFolder> {
@@ -1154,6 +1520,11 @@ impl SearchPath {
     pub fn set_next_index(&mut self, index: u32) {
         self.path_and_next_index.set_data(index);
     }
+
+    /// The link index of the next path in the parent folder's chain, if this is not the last one
+    pub fn next_index(&self) -> Option<u32> {
+        (!self.is_end()).then(|| self.path_and_next_index.data())
+    }
 }
 
 impl<'a> TableRef<'a, SearchPath> {
@@ -1198,6 +1569,16 @@ impl<'a> TableMut<'a, SearchPath> {
             .path()
     }
 
+    /// Follows the chain to its last entry, so that a new entry can be linked after it
+    pub fn into_last(mut self) -> TableMut<'a, SearchPath> {
+        let mut steps = 0;
+        while !self.is_end() {
+            step_search_path_chain(&mut steps);
+            self = self.next();
+        }
+        self
+    }
+
     pub fn as_folder_ref(&self) -> TableRef<'_, SearchFolder> {
         assert!(self.is_folder());
         self.archive().lookup_search_folder(self.path()).unwrap()
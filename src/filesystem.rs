@@ -1,8 +1,12 @@
 use std::{
     alloc::Layout,
-    hint::unreachable_unchecked,
+    fmt::Display,
     io::{Read, Seek, SeekFrom},
     ptr::NonNull,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
 use bytemuck::{Pod, Zeroable};
@@ -12,11 +16,28 @@ use smash_hash::{Hash40, Hash40Map};
 
 use crate::{
     data::{IntoHash, Locale, Region},
-    hash_interner::{HashMemorySlab, InternPathResult, InternerCache},
+    hash_interner::{HashMemorySlab, InternPathError, InternPathResult, InternerCache},
     mount_save::Language,
+    state::ModOrder,
     HashDisplay, LocalePreferences,
 };
 
+// The switch only gives us three cores to work with
+const DISCOVERY_THREAD_COUNT: usize = 3;
+
+/// Files ending with this extension are zstd frames of the file without the extension
+const ZSTD_EXTENSION: &str = ".zst";
+
+/// Mods distributed as gzipped tarballs, which get extracted into [`TAR_GZ_EXTRACT_FOLDER`]
+pub const TAR_GZ_EXTENSION: &str = ".tar.gz";
+
+/// Folder inside of the mods folder that `.tar.gz` mods are extracted to. It starts with a `.` so
+/// that it is skipped when discovering mods
+const TAR_GZ_EXTRACT_FOLDER: &str = ".extracted";
+
+/// The largest possible size of a zstd frame header, which contains the decompressed size
+const ZSTD_FRAME_HEADER_SIZE_MAX: u64 = 18;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Pod, Zeroable)]
 struct DiscoveredFilesystemHeader {
@@ -42,8 +63,9 @@ struct FileHeader {
     num_files: u32,
 }
 
+/// Which tier of the locale preferences a file is specific to
 #[derive(Debug, Copy, Clone)]
-enum Regionalized {
+pub enum Regionalized {
     None,
     Locale(u8),
     Language(u8),
@@ -58,7 +80,8 @@ impl FileIndex {
     const REGION_LOCALE_LANGUAGE_TY: u32 = 0xC0000000;
     const REGION_LOCALE_LANGUAGE_IDX: u32 = 0x3F000000;
     const IS_COMPRESSED: u32 = 0x00800000;
-    const INDEX: u32 = 0x007FFFFF;
+    const IS_ZSTD: u32 = 0x00400000;
+    const INDEX: u32 = 0x003FFFFF;
 
     const fn get_regionalized(&self) -> Regionalized {
         let ty_bits = ((self.0 & Self::REGION_LOCALE_LANGUAGE_TY) >> 30) as u8;
@@ -68,7 +91,10 @@ impl FileIndex {
             0b01 => Regionalized::Locale(idx_bits),
             0b10 => Regionalized::Language(idx_bits),
             0b11 => Regionalized::Region(idx_bits),
-            _ => unsafe { unreachable_unchecked() },
+            // The type is only two bits wide, so there is no room for another encoding here. If
+            // one ever shows up (i.e. for DLC specific files) it needs a layout change, not a
+            // new match arm
+            _ => unreachable!(),
         }
     }
 
@@ -76,11 +102,21 @@ impl FileIndex {
         (self.0 & Self::IS_COMPRESSED) != 0
     }
 
+    /// Whether the compressed data is a zstd frame instead of a DEFLATE stream from a zip
+    const fn is_zstd(&self) -> bool {
+        (self.0 & Self::IS_ZSTD) != 0
+    }
+
     const fn index(&self) -> u32 {
         self.0 & Self::INDEX
     }
 
-    const fn from_parts(regionalized: Regionalized, compressed: bool, index: u32) -> Self {
+    const fn from_parts(
+        regionalized: Regionalized,
+        compressed: bool,
+        zstd: bool,
+        index: u32,
+    ) -> Self {
         let (ty, idx) = match regionalized {
             Regionalized::None => (0, 0),
             Regionalized::Locale(idx) => (1, idx),
@@ -92,6 +128,7 @@ impl FileIndex {
             (((ty as u32) & 0x3) << 30)
                 | (((idx as u32) & 0x3F) << 24)
                 | (((compressed as u32) & 0x1) << 23)
+                | (((zstd as u32) & 0x1) << 22)
                 | (index & Self::INDEX),
         )
     }
@@ -147,8 +184,74 @@ impl HashedFile {
     }
 }
 
+/// An error returned by [`FileSystem::read_file_into_slice`]
+#[derive(Debug)]
+pub enum ReadError {
+    BufferSizeMismatch { expected: usize, actual: usize },
+    Io(std::io::Error),
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BufferSizeMismatch { expected, actual } => write!(
+                f,
+                "buffer is {actual:#x} bytes but the decompressed file is {expected:#x} bytes"
+            ),
+            Self::Io(e) => write!(f, "failed to read file: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// An error returned by [`FileSystem::from_bytes`] when the serialized file system is malformed
+#[derive(Debug)]
+pub enum ParseError {
+    /// The data ended before the section that the header describes, i.e. a partially written cache
+    UnexpectedEof { expected: usize, actual: usize },
+    /// There is more data after every section that the header describes
+    TrailingBytes { expected: usize, actual: usize },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEof { expected, actual } => write!(
+                f,
+                "unexpected end of data, expected at least {expected:#x} bytes but there are only {actual:#x}"
+            ),
+            Self::TrailingBytes { expected, actual } => write!(
+                f,
+                "expected {expected:#x} bytes but there are {actual:#x}"
+            ),
+        }
+    }
+}
+
+/// Takes `len` bytes from `bytes` at `cursor`, advancing the cursor past them
+fn checked_slice<'a>(
+    bytes: &'a [u8],
+    cursor: &mut usize,
+    len: usize,
+) -> Result<&'a [u8], ParseError> {
+    let end = *cursor + len;
+    let slice = bytes.get(*cursor..end).ok_or(ParseError::UnexpectedEof {
+        expected: end,
+        actual: bytes.len(),
+    })?;
+    *cursor = end;
+    Ok(slice)
+}
+
 pub struct FileSystem {
     raw: Box<[u8]>,
+    conflicts: Vec<ConflictReport>,
+    root_priorities: Vec<usize>,
     header: DiscoveredFilesystemHeader,
     roots: *const [Root],
     root_bytes: *const [u8],
@@ -164,10 +267,69 @@ impl FileSystem {
         self.header.checksum
     }
 
+    /// The number of unique file paths, regional variants of a file share the same path
+    pub fn file_count(&self) -> usize {
+        self.header.paths as usize
+    }
+
+    /// The number of mod folders/zip files that files were discovered in
+    pub fn root_count(&self) -> usize {
+        self.header.roots as usize
+    }
+
+    #[allow(dead_code)]
+    pub fn compressed_file_count(&self) -> usize {
+        self.header.compressed_files as usize
+    }
+
+    #[allow(dead_code)]
+    pub fn uncompressed_file_count(&self) -> usize {
+        self.header.uncompressed_files as usize
+    }
+
+    /// Reads the checksum out of a serialized file system without loading it, returning [`None`]
+    /// if the bytes are too short to contain a header
+    pub fn read_checksum(bytes: &[u8]) -> Option<u32> {
+        let header = bytes.get(..std::mem::size_of::<DiscoveredFilesystemHeader>())?;
+        let header: DiscoveredFilesystemHeader = bytemuck::pod_read_unaligned(header);
+        Some(header.checksum)
+    }
+
     pub fn raw(&self) -> &[u8] {
         &self.raw
     }
 
+    /// Attaches the conflicts found by [`Discovery`], since they are not part of the serialized
+    /// file system
+    pub fn with_conflicts(mut self, conflicts: Vec<ConflictReport>) -> Self {
+        self.conflicts = conflicts;
+        self
+    }
+
+    /// Attaches the priority of each root from the mod order, since it is not part of the
+    /// serialized file system
+    pub fn with_root_priorities(mut self, priorities: Vec<usize>) -> Self {
+        self.root_priorities = priorities;
+        self
+    }
+
+    /// The position of the mod at `root_idx` in the mod order, lower values win
+    /// conflicts and unlisted mods are [`usize::MAX`]
+    #[allow(dead_code)]
+    pub fn priority_for_root(&self, root_idx: u32) -> usize {
+        self.root_priorities
+            .get(root_idx as usize)
+            .copied()
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Files that were provided by more than one mod
+    ///
+    /// This is empty when the file system was loaded from the cache instead of being discovered.
+    pub fn conflicts(&self) -> &[ConflictReport] {
+        &self.conflicts
+    }
+
     #[allow(dead_code)]
     pub fn iter_file_paths(
         &self,
@@ -204,54 +366,83 @@ impl FileSystem {
         }
     }
 
-    pub fn from_bytes(bytes: Box<[u8]>) -> Self {
-        let header: DiscoveredFilesystemHeader =
-            *bytemuck::from_bytes(&bytes[..std::mem::size_of::<DiscoveredFilesystemHeader>()]);
-        let mut cursor = std::mem::size_of::<DiscoveredFilesystemHeader>();
-        let lookup: *const [HashedFile] = bytemuck::cast_slice(
-            &bytes[cursor..cursor + header.paths as usize * std::mem::size_of::<HashedFile>()],
-        );
-        cursor += header.paths as usize * std::mem::size_of::<HashedFile>();
-
-        let file_headers: *const [FileHeader] = bytemuck::cast_slice(
-            &bytes[cursor..cursor + header.paths as usize * std::mem::size_of::<FileHeader>()],
-        );
-        cursor += header.paths as usize * std::mem::size_of::<FileHeader>();
-
-        let files: *const [File] = bytemuck::cast_slice(
-            &bytes[cursor
-                ..cursor
-                    + (header.uncompressed_files + header.compressed_files) as usize
-                        * std::mem::size_of::<File>()],
-        );
-        cursor += (header.uncompressed_files + header.compressed_files) as usize
-            * std::mem::size_of::<File>();
-
-        let uncompressed: *const [UncompressedFile] = bytemuck::cast_slice(
-            &bytes[cursor
-                ..cursor
-                    + header.uncompressed_files as usize * std::mem::size_of::<UncompressedFile>()],
-        );
-        cursor += header.uncompressed_files as usize * std::mem::size_of::<UncompressedFile>();
-
-        let compressed: *const [CompressedFile] = bytemuck::cast_slice(
-            &bytes[cursor
-                ..cursor
-                    + header.compressed_files as usize * std::mem::size_of::<CompressedFile>()],
-        );
-        cursor += header.compressed_files as usize * std::mem::size_of::<CompressedFile>();
-
-        let roots: *const [Root] = bytemuck::cast_slice(
-            &bytes[cursor..cursor + header.roots as usize * std::mem::size_of::<Root>()],
-        );
-        cursor += header.roots as usize * std::mem::size_of::<Root>();
+    /// Same as [`FileSystem::iter_file_sizes`], but `predicate` is checked before resolving the
+    /// file for the locale preferences so that uninteresting files can be skipped cheaply
+    pub fn iter_file_sizes_filtered<'a, F: Fn(Hash40) -> bool + 'a>(
+        &'a self,
+        preferences: LocalePreferences,
+        predicate: F,
+    ) -> impl Iterator<Item = (Hash40, u32)> + 'a {
+        unsafe {
+            (*self.lookup)
+                .iter()
+                .filter(move |hashed_file| predicate(hashed_file.path()))
+                .filter_map(move |hashed_file| {
+                    self.get_file_by_header(hashed_file.index(), preferences)
+                        .map(|file| (hashed_file.path(), self.get_decompressed_size(file)))
+                })
+        }
+    }
 
-        let root_bytes = &raw const bytes[cursor..cursor + header.root_byte_len as usize];
-        cursor += header.root_byte_len as usize;
-        assert_eq!(cursor, bytes.len());
+    pub fn from_bytes(bytes: Box<[u8]>) -> Result<Self, ParseError> {
+        let mut cursor = 0;
+        let header: DiscoveredFilesystemHeader = *bytemuck::from_bytes(checked_slice(
+            &bytes,
+            &mut cursor,
+            std::mem::size_of::<DiscoveredFilesystemHeader>(),
+        )?);
+
+        let lookup: *const [HashedFile] = bytemuck::cast_slice(checked_slice(
+            &bytes,
+            &mut cursor,
+            header.paths as usize * std::mem::size_of::<HashedFile>(),
+        )?);
+
+        let file_headers: *const [FileHeader] = bytemuck::cast_slice(checked_slice(
+            &bytes,
+            &mut cursor,
+            header.paths as usize * std::mem::size_of::<FileHeader>(),
+        )?);
+
+        let files: *const [File] = bytemuck::cast_slice(checked_slice(
+            &bytes,
+            &mut cursor,
+            (header.uncompressed_files as usize + header.compressed_files as usize)
+                * std::mem::size_of::<File>(),
+        )?);
+
+        let uncompressed: *const [UncompressedFile] = bytemuck::cast_slice(checked_slice(
+            &bytes,
+            &mut cursor,
+            header.uncompressed_files as usize * std::mem::size_of::<UncompressedFile>(),
+        )?);
+
+        let compressed: *const [CompressedFile] = bytemuck::cast_slice(checked_slice(
+            &bytes,
+            &mut cursor,
+            header.compressed_files as usize * std::mem::size_of::<CompressedFile>(),
+        )?);
+
+        let roots: *const [Root] = bytemuck::cast_slice(checked_slice(
+            &bytes,
+            &mut cursor,
+            header.roots as usize * std::mem::size_of::<Root>(),
+        )?);
+
+        let root_bytes: *const [u8] =
+            checked_slice(&bytes, &mut cursor, header.root_byte_len as usize)?;
+
+        if cursor != bytes.len() {
+            return Err(ParseError::TrailingBytes {
+                expected: cursor,
+                actual: bytes.len(),
+            });
+        }
 
-        Self {
+        Ok(Self {
             raw: bytes,
+            conflicts: vec![],
+            root_priorities: vec![],
             header,
             roots,
             root_bytes,
@@ -260,7 +451,7 @@ impl FileSystem {
             lookup,
             uncompressed,
             compressed,
-        }
+        })
     }
 
     pub fn get_decompressed_size(&self, file: &File) -> u32 {
@@ -272,6 +463,15 @@ impl FileSystem {
     }
 
     fn get_file_by_header(&self, header_idx: u32, preferences: LocalePreferences) -> Option<&File> {
+        self.get_file_and_tier_by_header(header_idx, preferences)
+            .map(|(file, _)| file)
+    }
+
+    fn get_file_and_tier_by_header(
+        &self,
+        header_idx: u32,
+        preferences: LocalePreferences,
+    ) -> Option<(&File, Regionalized)> {
         let header = unsafe { (*self.file_headers)[header_idx as usize] };
         let files = unsafe {
             &(&*self.files)[header.start as usize..(header.start + header.num_files) as usize]
@@ -283,16 +483,17 @@ impl FileSystem {
         let mut base_match = None;
 
         for file in files {
-            match file.index.get_regionalized() {
-                Regionalized::None => base_match = Some(file),
+            let tier = file.index.get_regionalized();
+            match tier {
+                Regionalized::None => base_match = Some((file, tier)),
                 Regionalized::Locale(idx) if preferences.locale as u8 == idx => {
-                    locale_match = Some(file)
+                    locale_match = Some((file, tier))
                 }
                 Regionalized::Language(idx) if preferences.language as u8 == idx => {
-                    language_match = Some(file)
+                    language_match = Some((file, tier))
                 }
                 Regionalized::Region(idx) if preferences.region as u8 == idx => {
-                    region_match = Some(file)
+                    region_match = Some((file, tier))
                 }
                 _ => {}
             }
@@ -304,17 +505,33 @@ impl FileSystem {
             .or(base_match)
     }
 
-    pub fn lookup_file(&self, hash: Hash40, preferences: LocalePreferences) -> Option<&File> {
-        let file_header_index = unsafe {
+    fn lookup_file_header_index(&self, hash: Hash40) -> Option<u32> {
+        unsafe {
             let index = (&*self.lookup)
                 .binary_search_by_key(&hash, |file| file.path())
                 .ok()?;
-            (*self.lookup)[index].index()
-        };
+            Some((*self.lookup)[index].index())
+        }
+    }
 
+    pub fn lookup_file(&self, hash: Hash40, preferences: LocalePreferences) -> Option<&File> {
+        let file_header_index = self.lookup_file_header_index(hash)?;
         self.get_file_by_header(file_header_index, preferences)
     }
 
+    /// Looks up a file the same way as [`FileSystem::lookup_file`], but also returns which tier
+    /// of the preferences it matched.
+    ///
+    /// Files are picked in the order locale, language, region and then base.
+    pub fn lookup_file_with_fallback(
+        &self,
+        hash: Hash40,
+        preferences: LocalePreferences,
+    ) -> Option<(&File, Regionalized)> {
+        let file_header_index = self.lookup_file_header_index(hash)?;
+        self.get_file_and_tier_by_header(file_header_index, preferences)
+    }
+
     fn get_root(&self, root_idx: u32) -> &str {
         let root = unsafe { (&*self.roots)[root_idx as usize] };
         unsafe {
@@ -368,15 +585,27 @@ impl FileSystem {
             .unwrap();
 
             let decompressed_ptr = unsafe { std::alloc::alloc(decompressed_buffer_layout) };
+            assert!(
+                !decompressed_ptr.is_null(),
+                "Failed to allocate {} bytes for decompressed file",
+                decompressed_buffer_layout.size()
+            );
 
             let decompressed_buffer = unsafe {
                 std::slice::from_raw_parts_mut(decompressed_ptr, decompressed_buffer_layout.size())
             };
 
             // TODO: figure out removing unwrap, or don't this is technically user data
-            flate2::bufread::DeflateDecoder::new(std::io::Cursor::new(compressed_buffer))
-                .read_exact(decompressed_buffer)
-                .unwrap();
+            if file.index.is_zstd() {
+                zstd::stream::read::Decoder::with_buffer(compressed_buffer)
+                    .unwrap()
+                    .read_exact(decompressed_buffer)
+                    .unwrap();
+            } else {
+                flate2::bufread::DeflateDecoder::new(std::io::Cursor::new(compressed_buffer))
+                    .read_exact(decompressed_buffer)
+                    .unwrap();
+            }
 
             unsafe {
                 std::alloc::dealloc(
@@ -394,7 +623,16 @@ impl FileSystem {
         }
     }
 
-    fn read_zip_file(&self, root: u32, file: &CompressedFile, alignment: usize) -> NonNull<u8> {
+    /// Reads the compressed bytes of a file out of either a zip or a standalone zstd file
+    ///
+    /// The returned pointer is tagged as compressed unless the file was stored without compression
+    fn read_compressed_file(
+        &self,
+        path: &str,
+        file: &CompressedFile,
+        is_zstd: bool,
+        alignment: usize,
+    ) -> NonNull<u8> {
         // SAFETY: Compressed size must be <= u32::MAX, and if it's not then we are going
         // to OOM anyways. Realistically I don't think a user is going to do that so I
         // won't bother doing the unwrap panic check here (and if they do the worst that
@@ -409,10 +647,8 @@ impl FileSystem {
         let slice =
             unsafe { std::slice::from_raw_parts_mut(compressed_buffer, buffer_layout.size()) };
 
-        let root = self.get_root(root);
-
         // TODO: Check if this can be unwrap_unchecked?
-        let mut zip_file = std::fs::File::open(root).unwrap();
+        let mut zip_file = std::fs::File::open(path).unwrap();
         zip_file
             .seek(SeekFrom::Start(file.compressed_start as u64))
             .unwrap();
@@ -420,13 +656,71 @@ impl FileSystem {
 
         zip_file.read_exact(slice).unwrap();
 
-        if file.compressed_size == file.decompressed_size {
+        if !is_zstd && file.compressed_size == file.decompressed_size {
             unsafe { NonNull::new_unchecked(compressed_buffer) }
         } else {
             unsafe { NonNull::new_unchecked(Self::into_compressed_ptr(compressed_buffer).unwrap()) }
         }
     }
 
+    /// Reads and decompresses a file directly into `buffer`, which must be exactly
+    /// [`FileSystem::get_decompressed_size`] bytes long
+    ///
+    /// Unlike [`FileSystem::read_file`], this does not allocate a buffer for the file data. Compressed
+    /// data is streamed from the SD card into the decompressor.
+    pub fn read_file_into_slice(
+        &self,
+        hash: Hash40,
+        file: &File,
+        buffer: &mut [u8],
+    ) -> Result<(), ReadError> {
+        let expected = self.get_decompressed_size(file) as usize;
+        if buffer.len() != expected {
+            return Err(ReadError::BufferSizeMismatch {
+                expected,
+                actual: buffer.len(),
+            });
+        }
+
+        let root = self.get_root(file.root);
+
+        if file.index.is_compressed() {
+            let compressed_file = unsafe { &(&*self.compressed)[file.index.index() as usize] };
+            let mut reader = if file.index.is_zstd() && !root.ends_with(".zip") {
+                std::fs::File::open(format!("{root}/{}{ZSTD_EXTENSION}", hash.display()))?
+            } else {
+                std::fs::File::open(root)?
+            };
+            reader.seek(SeekFrom::Start(compressed_file.compressed_start as u64))?;
+            let mut reader = reader.take(compressed_file.compressed_size as u64);
+
+            if file.index.is_zstd() {
+                zstd::stream::read::Decoder::new(reader)?.read_exact(buffer)?;
+            } else if compressed_file.compressed_size == compressed_file.decompressed_size {
+                // Stored zip entries are not compressed
+                reader.read_exact(buffer)?;
+            } else {
+                flate2::read::DeflateDecoder::new(reader).read_exact(buffer)?;
+            }
+        } else {
+            std::fs::File::open(format!("{root}/{}", hash.display()))?.read_exact(buffer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads and decompresses a file into a [`Vec`], for callers that don't care about the
+    /// alignment of the data
+    ///
+    /// Files that get handed to the game should still use [`FileSystem::read_file`], since the
+    /// game expects the buffer to be aligned for the file type
+    #[allow(dead_code)]
+    pub fn read_file_to_vec(&self, hash: Hash40, file: &File) -> Result<Vec<u8>, ReadError> {
+        let mut buffer = vec![0u8; self.get_decompressed_size(file) as usize];
+        self.read_file_into_slice(hash, file, &mut buffer)?;
+        Ok(buffer)
+    }
+
     pub fn read_file(
         &self,
         hash: Hash40,
@@ -440,7 +734,15 @@ impl FileSystem {
 
         if file.index.is_compressed() {
             let compressed_file = unsafe { &(&*self.compressed)[file.index.index() as usize] };
-            let ptr = self.read_zip_file(file.root, compressed_file, alignment);
+            let path = if file.index.is_zstd() && !root.ends_with(".zip") {
+                filepath_buffer.clear();
+                let _ = write!(filepath_buffer, "{root}/{}{ZSTD_EXTENSION}", hash.display());
+                filepath_buffer.as_str()
+            } else {
+                root
+            };
+            let ptr =
+                self.read_compressed_file(path, compressed_file, file.index.is_zstd(), alignment);
             if leave_compressed {
                 ptr
             } else {
@@ -451,15 +753,20 @@ impl FileSystem {
             let _ = write!(filepath_buffer, "{root}/{}", hash.display());
             let uncompressed_file = unsafe { (*self.uncompressed)[file.index.index() as usize] };
 
-            let buffer = unsafe {
-                std::slice::from_raw_parts_mut(
-                    std::alloc::alloc(
-                        Layout::from_size_align(uncompressed_file.size as usize, alignment)
-                            .unwrap(),
-                    ),
-                    uncompressed_file.size as usize,
+            let buffer_ptr = unsafe {
+                std::alloc::alloc(
+                    Layout::from_size_align(uncompressed_file.size as usize, alignment).unwrap(),
                 )
             };
+            assert!(
+                !buffer_ptr.is_null(),
+                "Failed to allocate {} bytes for uncompressed file",
+                uncompressed_file.size
+            );
+
+            let buffer = unsafe {
+                std::slice::from_raw_parts_mut(buffer_ptr, uncompressed_file.size as usize)
+            };
 
             let mut file = std::fs::File::open(&filepath_buffer).unwrap();
             file.read_exact(buffer).unwrap();
@@ -477,6 +784,13 @@ enum FileKind {
         compressed_size: u32,
         decompressed_size: u32,
     },
+    ZstdCompressed {
+        start: u32,
+        compressed_size: u32,
+        decompressed_size: u32,
+        /// Offset of the frame relative to `start`, for files that contain multiple frames
+        frame_start: u64,
+    },
 }
 
 struct DiscoveredFile {
@@ -503,26 +817,223 @@ impl DiscoveredFiles {
             .chain(self.base.iter())
     }
 
+    /// Sets the file for its regional slot, returning the root index of the file that ends up in
+    /// the slot and the file that lost if the slot was already taken
+    ///
+    /// The new file only replaces the existing one if its root does not have a lower priority,
+    /// so mods that are not in the mod order still resolve in discovery order.
     fn set_by_regionalized(
         &mut self,
         file: DiscoveredFile,
         regionalized: Regionalized,
-    ) -> Option<DiscoveredFile> {
-        match regionalized {
-            Regionalized::None => self.base.replace(file),
-            Regionalized::Locale(idx) => self.by_locale[idx as usize].replace(file),
-            Regionalized::Language(idx) => self.by_language[idx as usize].replace(file),
-            Regionalized::Region(idx) => self.by_region[idx as usize].replace(file),
+        priorities: &[usize],
+    ) -> Option<(u32, DiscoveredFile)> {
+        let slot = match regionalized {
+            Regionalized::None => &mut self.base,
+            Regionalized::Locale(idx) => &mut self.by_locale[idx as usize],
+            Regionalized::Language(idx) => &mut self.by_language[idx as usize],
+            Regionalized::Region(idx) => &mut self.by_region[idx as usize],
+        };
+
+        if let Some(existing) = slot.as_ref() {
+            if priorities[existing.root_index as usize] < priorities[file.root_index as usize] {
+                return Some((existing.root_index, file));
+            }
+        }
+
+        let winner = file.root_index;
+        slot.replace(file).map(|replaced| (winner, replaced))
+    }
+}
+
+/// A file that was provided by more than one mod, the mod that is earliest in the mod order wins
+/// and the last mod to be discovered wins between unlisted mods
+#[derive(Debug, Clone)]
+pub struct ConflictReport {
+    pub path: Hash40,
+    pub winning_mod: Utf8PathBuf,
+    pub losing_mods: Vec<Utf8PathBuf>,
+}
+
+fn record_conflict(
+    conflicts: &mut Hash40Map<ConflictReport>,
+    roots: &[Utf8PathBuf],
+    path: &Utf8Path,
+    winner: u32,
+    loser: u32,
+) {
+    let winning_mod = mod_source_path(&roots[winner as usize]);
+    let losing_mod = mod_source_path(&roots[loser as usize]);
+    log::warn!(
+        "[stratus::discovery] {path} from {winning_mod} overrides the one from {losing_mod}"
+    );
+
+    let hash = path.into_hash();
+    let report = conflicts.entry(hash).or_insert_with(|| ConflictReport {
+        path: hash,
+        winning_mod: winning_mod.clone(),
+        losing_mods: vec![],
+    });
+    report.winning_mod = winning_mod;
+    report.losing_mods.push(losing_mod);
+}
+
+/// File names, checked in order, that a mod can provide its metadata in at its root
+pub const MOD_METADATA_FILES: [&str; 2] = ["mod_info.toml", "mod.toml"];
+
+/// Optional metadata that a mod can provide at its root, every field can be omitted:
+///
+/// ```toml
+/// name = "Fancy Mario"
+/// author = "someone"            # or `authors = ["someone", "someone else"]`
+/// version = "1.0.0"
+/// description = "Makes Mario fancy"
+/// ```
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct ModMetadata {
+    /// The name to display instead of the folder/zip file name
+    pub name: Option<String>,
+    /// A single author, which is merged into [`Self::authors`] when parsing
+    author: Option<String>,
+    pub authors: Vec<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+}
+
+impl ModMetadata {
+    fn parse(path: &Utf8Path, contents: &[u8]) -> Option<Self> {
+        let contents = std::str::from_utf8(contents).ok()?;
+        match toml::from_str::<Self>(contents) {
+            Ok(mut metadata) => {
+                if let Some(author) = metadata.author.take() {
+                    metadata.authors.insert(0, author);
+                }
+                Some(metadata)
+            }
+            Err(e) => {
+                log::warn!("[stratus::discovery] Failed to parse mod metadata for {path}: {e}");
+                None
+            }
         }
     }
+
+    pub fn read_from_folder(path: &Utf8Path) -> Option<Self> {
+        let contents = MOD_METADATA_FILES
+            .iter()
+            .find_map(|name| std::fs::read(path.join(name)).ok())?;
+        Self::parse(path, &contents)
+    }
+
+    pub fn read_from_zip(path: &Utf8Path) -> Option<Self> {
+        let mut zip_buffer = vec![0u8; RECOMMENDED_BUFFER_SIZE];
+        let mut file = std::fs::File::open(path).ok()?;
+        let zip = rawzip::ZipArchive::from_file(file.try_clone().ok()?, &mut zip_buffer).ok()?;
+
+        let mut found: [Option<Vec<u8>>; MOD_METADATA_FILES.len()] = Default::default();
+        let mut entries = zip.entries(&mut zip_buffer);
+        while let Some(next) = entries.next_entry().ok()? {
+            if next.is_dir() {
+                continue;
+            }
+
+            let Some(slot) = MOD_METADATA_FILES
+                .iter()
+                .position(|name| next.file_path().as_bytes() == name.as_bytes())
+            else {
+                continue;
+            };
+
+            let wayfinder = next.wayfinder();
+            let entry = zip.get_entry(wayfinder).ok()?;
+            let compressed_size = wayfinder.compressed_size_hint() as usize;
+            let decompressed_size = wayfinder.uncompressed_size_hint() as usize;
+
+            let mut compressed = vec![0u8; compressed_size];
+            file.seek(SeekFrom::Start(entry.compressed_data_range().0))
+                .ok()?;
+            file.read_exact(&mut compressed).ok()?;
+
+            // Same as discovery, entries with matching sizes are treated as stored
+            found[slot] = Some(if compressed_size == decompressed_size {
+                compressed
+            } else {
+                let mut decompressed = vec![0u8; decompressed_size];
+                flate2::bufread::DeflateDecoder::new(std::io::Cursor::new(compressed))
+                    .read_exact(&mut decompressed)
+                    .ok()?;
+                decompressed
+            });
+        }
+
+        let contents = found.into_iter().flatten().next()?;
+        Self::parse(path, &contents)
+    }
 }
 
 pub struct Discovery {
     compressed_files: usize,
     uncompressed_files: usize,
     roots: Vec<Utf8PathBuf>,
+    priorities: Vec<usize>,
+    metadata: Vec<Option<ModMetadata>>,
     files: Hash40Map<DiscoveredFiles>,
     checksum: u32,
+    errors: Vec<DiscoveryError>,
+    conflicts: Vec<ConflictReport>,
+}
+
+#[derive(Debug, Clone)]
+pub enum DiscoveryError {
+    InvalidLocaleSuffix {
+        path: Utf8PathBuf,
+        suffix: String,
+    },
+    InvalidRegionSuffix {
+        path: Utf8PathBuf,
+        suffix: String,
+    },
+    InvalidIndicator {
+        path: Utf8PathBuf,
+    },
+    Intern {
+        path: Utf8PathBuf,
+        error: InternPathError,
+    },
+    InvalidZstdFrame {
+        path: Utf8PathBuf,
+    },
+    PathTooDeep {
+        path: Utf8PathBuf,
+        components: usize,
+    },
+}
+
+impl Display for DiscoveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidLocaleSuffix { path, suffix } => {
+                write!(f, "invalid locale suffix '{suffix}' in file {path}")
+            }
+            Self::InvalidRegionSuffix { path, suffix } => {
+                write!(
+                    f,
+                    "invalid region/language suffix '{suffix}' in file {path}"
+                )
+            }
+            Self::InvalidIndicator { path } => {
+                write!(f, "invalid region/locale indicator in file {path}")
+            }
+            Self::Intern { path, error } => write!(f, "failed to intern {path}: {error}"),
+            Self::InvalidZstdFrame { path } => write!(
+                f,
+                "{path} is not a stored zstd frame with a known decompressed size"
+            ),
+            Self::PathTooDeep { path, components } => {
+                write!(f, "{path} has too many path components ({components})")
+            }
+        }
+    }
 }
 
 fn detect_regional_and_cache(
@@ -530,34 +1041,39 @@ fn detect_regional_and_cache(
     hash: &mut HashMemorySlab,
     cache: &mut InternerCache,
     new_filepath_buffer: &mut String,
-) -> (InternPathResult, Regionalized) {
+    progress: &mut dyn FnMut(usize),
+) -> Result<(InternPathResult, Regionalized), DiscoveryError> {
     let mut regional = Regionalized::None;
     let mut filepath = path;
     if let Some(file_stem) = path.file_stem() {
         if let Some(pos) = file_stem.find('+') {
+            let suffix = &file_stem[pos + 1..];
             // +xx_yy locale indicator
             if file_stem.len() - pos == 6 {
-                let locale = Locale::from_str(&file_stem[pos + 1..]).unwrap_or_else(|| {
-                    panic!(
-                        "Invalid locale suffix {} in file {}",
-                        &file_stem[pos + 1..],
-                        path
-                    )
-                });
+                let Some(locale) = Locale::from_str(suffix) else {
+                    return Err(DiscoveryError::InvalidLocaleSuffix {
+                        path: path.to_path_buf(),
+                        suffix: suffix.to_string(),
+                    });
+                };
                 regional = Regionalized::Locale(locale as u8);
             }
             // +xx region/language indicator
             else if file_stem.len() - pos == 3 {
-                let substr = &file_stem[pos + 1..];
-                if let Some(language) = Language::from_str(substr) {
+                if let Some(language) = Language::from_str(suffix) {
                     regional = Regionalized::Language(language as u8);
-                } else if let Some(region) = Region::from_str(substr) {
+                } else if let Some(region) = Region::from_str(suffix) {
                     regional = Regionalized::Region(region as u8);
                 } else {
-                    panic!("Invalid region/language suffix {} in file {}", substr, path);
+                    return Err(DiscoveryError::InvalidRegionSuffix {
+                        path: path.to_path_buf(),
+                        suffix: suffix.to_string(),
+                    });
                 }
             } else {
-                panic!("Invalid region/locale indicator in file {}", path);
+                return Err(DiscoveryError::InvalidIndicator {
+                    path: path.to_path_buf(),
+                });
             }
             new_filepath_buffer.clear();
             if let Some(parent) = path.parent() {
@@ -576,17 +1092,182 @@ fn detect_regional_and_cache(
     }
 
     if let Some(file_name) = filepath.file_name() {
-        hash.intern_path(cache, Utf8Path::new(file_name));
+        let _ = hash.intern_path(cache, Utf8Path::new(file_name));
     }
     if let Some(ext) = filepath.extension() {
-        hash.intern_path(cache, Utf8Path::new(ext));
+        let _ = hash.intern_path(cache, Utf8Path::new(ext));
+    }
+
+    match hash.intern_path_with_progress(cache, filepath, progress) {
+        Ok(result) => Ok((result, regional)),
+        Err(InternPathError::PathTooDeep { components }) => Err(DiscoveryError::PathTooDeep {
+            path: filepath.to_path_buf(),
+            components,
+        }),
+        Err(error) => Err(DiscoveryError::Intern {
+            path: filepath.to_path_buf(),
+            error,
+        }),
     }
+}
+
+/// Reads the decompressed size out of the header of the zstd frame at the current position
+fn read_zstd_content_size(reader: impl Read) -> Option<u32> {
+    let mut header = Vec::with_capacity(ZSTD_FRAME_HEADER_SIZE_MAX as usize);
+    reader
+        .take(ZSTD_FRAME_HEADER_SIZE_MAX)
+        .read_to_end(&mut header)
+        .ok()?;
+    zstd::zstd_safe::get_frame_content_size(&header)
+        .ok()??
+        .try_into()
+        .ok()
+}
+
+/// Identifies the contents of a `.tar.gz` mod without reading the whole file, so that it is only
+/// extracted again when it changes
+///
+/// The last 8 bytes of a gzip file are the CRC32 and the size of the decompressed data, which
+/// catches archives that were replaced with one of the same size. The modification time is also
+/// included, for platforms where it is available.
+fn tar_gz_marker(archive_path: &Utf8Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(archive_path)?;
+    let metadata = file.metadata()?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+
+    let mut trailer = [0u8; 8];
+    file.seek(SeekFrom::End(-(trailer.len() as i64)))?;
+    file.read_exact(&mut trailer)?;
+    let content_crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+
+    Ok(format!("{} {modified} {content_crc:08x}", metadata.len()))
+}
 
-    let result = hash.intern_path(cache, filepath);
-    (result, regional)
+/// The folder that the `.tar.gz` mod at `archive_path` is extracted to by [`extract_tar_gz`]
+pub fn tar_gz_extracted_path(mods_root: &Utf8Path, archive_path: &Utf8Path) -> Utf8PathBuf {
+    let name = archive_path
+        .file_name()
+        .and_then(|name| name.strip_suffix(TAR_GZ_EXTENSION))
+        .unwrap_or_default();
+    mods_root.join(TAR_GZ_EXTRACT_FOLDER).join(name)
+}
+
+/// Maps a mod root back to the path that the user put in the mods folder, so that mods extracted
+/// from a `.tar.gz` are reported as the archive instead of the extracted folder
+fn mod_source_path(root: &Utf8Path) -> Utf8PathBuf {
+    let extracted_from = root
+        .parent()
+        .filter(|parent| parent.file_name() == Some(TAR_GZ_EXTRACT_FOLDER))
+        .and_then(|parent| parent.parent())
+        .zip(root.file_name());
+    match extracted_from {
+        Some((mods_root, name)) => mods_root.join(format!("{name}{TAR_GZ_EXTENSION}")),
+        None => root.to_path_buf(),
+    }
+}
+
+/// Extracts a `.tar.gz` mod into [`TAR_GZ_EXTRACT_FOLDER`], returning the folder it was extracted to
+///
+/// The archive is only extracted again if its [marker](tar_gz_marker) has changed since the last
+/// extraction. Entries that are inside of a folder with the same name as the archive have that
+/// folder stripped.
+fn extract_tar_gz(mods_root: &Utf8Path, archive_path: &Utf8Path) -> std::io::Result<Utf8PathBuf> {
+    let name = archive_path
+        .file_name()
+        .and_then(|name| name.strip_suffix(TAR_GZ_EXTENSION))
+        .unwrap_or_default();
+    let extract_root = mods_root.join(TAR_GZ_EXTRACT_FOLDER);
+    let target = tar_gz_extracted_path(mods_root, archive_path);
+    // Kept outside of the extracted folder so that it doesn't get discovered as a mod file
+    let marker_path = extract_root.join(format!("{name}.marker"));
+
+    let marker = tar_gz_marker(archive_path)?;
+    if std::fs::read_to_string(&marker_path).is_ok_and(|existing| existing == marker) {
+        return Ok(target);
+    }
+
+    if target.exists() {
+        std::fs::remove_dir_all(&target)?;
+    }
+    std::fs::create_dir_all(&target)?;
+
+    let now = std::time::Instant::now();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(std::fs::File::open(
+        archive_path,
+    )?));
+    let mut extracted = 0usize;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let entry_path = entry.path()?.into_owned();
+        let relative = entry_path.strip_prefix(name).unwrap_or(&entry_path);
+
+        // Don't let entries write outside of the folder we are extracting to
+        if !relative
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+        {
+            log::warn!(
+                "[stratus::discovery] Skipping {} in {archive_path}, it is not a relative path",
+                entry_path.display()
+            );
+            continue;
+        }
+
+        let out_path = target.as_std_path().join(relative);
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&out_path)?;
+        extracted += 1;
+    }
+
+    std::fs::write(&marker_path, marker)?;
+    println!(
+        "[stratus::discovery] Extracted {extracted} files from {archive_path} in {:.3}s",
+        now.elapsed().as_secs_f32()
+    );
+
+    Ok(target)
 }
 
 impl Discovery {
+    /// Problems with individual files that were found during discovery, the offending files are skipped
+    #[allow(dead_code)]
+    pub fn errors(&self) -> &[DiscoveryError] {
+        &self.errors
+    }
+
+    /// The priority of each root from the mod order, see [`ModOrder::priority`]
+    pub fn root_priorities(&self) -> &[usize] {
+        &self.priorities
+    }
+
+    /// Consumes the discovery, returning the files that were provided by more than one mod
+    pub fn into_conflicts(self) -> Vec<ConflictReport> {
+        self.conflicts
+    }
+
+    /// The checksum of every discovered root and file, which is the same between boots if the
+    /// mod set has not changed
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    /// The metadata file provided by the mod at `root_idx`, if it had one
+    #[allow(dead_code)]
+    pub fn metadata_for_root(&self, root_idx: u32) -> Option<&ModMetadata> {
+        self.metadata.get(root_idx as usize)?.as_ref()
+    }
+
     pub fn as_slab(&self) -> Box<[u8]> {
         let root_byte_len = self
             .roots
@@ -602,12 +1283,15 @@ impl Discovery {
             + std::mem::size_of::<Root>() * self.roots.len()
             + root_byte_len;
 
-        let slab = unsafe {
-            std::slice::from_raw_parts_mut(
-                std::alloc::alloc(Layout::from_size_align(total_memory_size, 0x10).unwrap()),
-                total_memory_size,
-            )
-        };
+        let slab_ptr =
+            unsafe { std::alloc::alloc(Layout::from_size_align(total_memory_size, 0x10).unwrap()) };
+        assert!(
+            !slab_ptr.is_null(),
+            "Failed to allocate {} bytes for filesystem slab",
+            total_memory_size
+        );
+
+        let slab = unsafe { std::slice::from_raw_parts_mut(slab_ptr, total_memory_size) };
 
         let header = DiscoveredFilesystemHeader {
             checksum: self.checksum,
@@ -670,7 +1354,31 @@ impl Discovery {
                             decompressed_size,
                         };
                         compressed_cursor += 1;
-                        FileIndex::from_parts(file.regionalized, true, compressed_cursor as u32 - 1)
+                        FileIndex::from_parts(
+                            file.regionalized,
+                            true,
+                            false,
+                            compressed_cursor as u32 - 1,
+                        )
+                    }
+                    FileKind::ZstdCompressed {
+                        start,
+                        compressed_size,
+                        decompressed_size,
+                        frame_start,
+                    } => {
+                        compressed_files[compressed_cursor] = CompressedFile {
+                            compressed_start: (start as u64 + frame_start) as u32,
+                            compressed_size,
+                            decompressed_size,
+                        };
+                        compressed_cursor += 1;
+                        FileIndex::from_parts(
+                            file.regionalized,
+                            true,
+                            true,
+                            compressed_cursor as u32 - 1,
+                        )
                     }
                     FileKind::Uncompressed { size } => {
                         uncompressed_files[uncompressed_cursor] = UncompressedFile { size };
@@ -678,6 +1386,7 @@ impl Discovery {
                         FileIndex::from_parts(
                             file.regionalized,
                             false,
+                            false,
                             uncompressed_cursor as u32 - 1,
                         )
                     }
@@ -741,19 +1450,72 @@ impl Discovery {
         }
     }
 
+    fn collect_directory_roots(directories: &[&Utf8Path]) -> Vec<Vec<(Utf8PathBuf, u32)>> {
+        let next = AtomicUsize::new(0);
+        let collected: Vec<Mutex<Vec<(Utf8PathBuf, u32)>>> =
+            directories.iter().map(|_| Mutex::new(vec![])).collect();
+
+        std::thread::scope(|scope| {
+            for _ in 0..DISCOVERY_THREAD_COUNT.min(directories.len()) {
+                scope.spawn(|| loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = directories.get(idx) else {
+                        break;
+                    };
+
+                    let mut files = vec![];
+                    Self::discover_and_update_recursive(
+                        path,
+                        path,
+                        &mut |file_path: &Utf8Path, len: u32| {
+                            files.push((file_path.to_path_buf(), len));
+                        },
+                    );
+                    *collected[idx].lock().unwrap() = files;
+                });
+            }
+        });
+
+        collected
+            .into_iter()
+            .map(|files| files.into_inner().unwrap())
+            .collect()
+    }
+
+    /// Discovers all of the mod folders and zip files in `root`
+    ///
+    /// `is_enabled` is called with the name of each mod folder/zip file, mods that it returns
+    /// `false` for are skipped entirely
+    ///
+    /// When more than one mod provides the same file, `mod_order` decides which one is used
+    #[allow(dead_code)]
     pub fn new_in_root(
         root: &Utf8Path,
         hashes: &mut HashMemorySlab,
         cache: &mut InternerCache,
+        is_enabled: impl Fn(&str) -> bool,
+        mod_order: &ModOrder,
+    ) -> Self {
+        Self::new_in_root_with_progress(root, hashes, cache, is_enabled, mod_order, &mut |_| {})
+    }
+
+    /// Same as [`Self::new_in_root`], but reports interning progress through `progress`
+    ///
+    /// See [`HashMemorySlab::intern_path_with_progress`]
+    pub fn new_in_root_with_progress(
+        root: &Utf8Path,
+        hashes: &mut HashMemorySlab,
+        cache: &mut InternerCache,
+        is_enabled: impl Fn(&str) -> bool,
+        mod_order: &ModOrder,
+        progress: &mut dyn FnMut(usize),
     ) -> Self {
         let mut zip_buffer = vec![0u8; RECOMMENDED_BUFFER_SIZE];
         let mut filepath_buffer = String::with_capacity(0x180);
 
         let mut roots = vec![];
-        let mut files: Hash40Map<DiscoveredFiles> = Hash40Map::default();
-        let mut compressed_files = 0;
-        let mut uncompressed_files = 0;
-        let mut checksum = crc32fast::Hasher::new();
+        let mut is_zip = vec![];
+        let mut priorities = vec![];
         for entry in root.read_dir_utf8().unwrap() {
             let entry = entry.unwrap();
 
@@ -763,49 +1525,162 @@ impl Discovery {
 
             let ft = entry.file_type().unwrap();
 
-            let path = entry.path();
+            if (ft.is_dir() || ft.is_file()) && !is_enabled(entry.file_name()) {
+                println!(
+                    "[stratus::discovery] Skipping disabled mod {}",
+                    entry.file_name()
+                );
+                continue;
+            }
 
-            let root_idx = roots.len() as u32;
             if ft.is_dir() {
-                checksum.update(path.as_str().as_bytes());
-                roots.push(path.to_path_buf());
-                Self::discover_and_update_recursive(
-                    path,
-                    path,
-                    &mut |file_path: &Utf8Path, len: u32| {
-                        checksum.update(file_path.as_str().as_bytes());
-                        checksum.update(&len.to_le_bytes());
-                        println!("\tDiscovered {file_path}");
-                        let (_, regional) = detect_regional_and_cache(
-                            file_path,
-                            hashes,
-                            cache,
-                            &mut filepath_buffer,
+                roots.push(entry.path().to_path_buf());
+                is_zip.push(false);
+                priorities.push(mod_order.priority(entry.file_name()));
+            } else if ft.is_file() && entry.file_name().ends_with(".zip") {
+                roots.push(entry.path().to_path_buf());
+                is_zip.push(true);
+                priorities.push(mod_order.priority(entry.file_name()));
+            } else if ft.is_file() && entry.file_name().ends_with(TAR_GZ_EXTENSION) {
+                // Entries in a gzipped tarball can't be read without decompressing everything
+                // before them, so these get extracted once and treated like a folder
+                match extract_tar_gz(root, entry.path()) {
+                    Ok(extracted) => {
+                        roots.push(extracted);
+                        is_zip.push(false);
+                        priorities.push(mod_order.priority(entry.file_name()));
+                    }
+                    Err(e) => {
+                        log::warn!(
+                            "[stratus::discovery] Failed to extract {}: {e}",
+                            entry.path()
                         );
+                    }
+                }
+            }
+        }
 
-                        let path = if matches!(&regional, Regionalized::None) {
-                            file_path
-                        } else {
-                            Utf8Path::new(&filepath_buffer)
+        let metadata = roots
+            .iter()
+            .zip(is_zip.iter())
+            .map(|(root, is_zip)| {
+                if *is_zip {
+                    ModMetadata::read_from_zip(root)
+                } else {
+                    ModMetadata::read_from_folder(root)
+                }
+            })
+            .collect();
+
+        // Phase 1: walk the directories in parallel, the hash slab is not touched here
+        let now = std::time::Instant::now();
+        let directories = roots
+            .iter()
+            .zip(is_zip.iter())
+            .filter_map(|(root, is_zip)| (!*is_zip).then_some(root.as_path()))
+            .collect::<Vec<_>>();
+        let mut directory_files = Self::collect_directory_roots(&directories).into_iter();
+        println!(
+            "[stratus::discovery] Walked {} mod directories in {:.3}s",
+            directories.len(),
+            now.elapsed().as_secs_f32()
+        );
+
+        // Phase 2: intern and insert every file serially, in root order
+        let now = std::time::Instant::now();
+        let mut files: Hash40Map<DiscoveredFiles> = Hash40Map::default();
+        let mut compressed_files = 0;
+        let mut uncompressed_files = 0;
+        let mut checksum = crc32fast::Hasher::new();
+        let mut errors = vec![];
+        let mut conflicts: Hash40Map<ConflictReport> = Hash40Map::default();
+        for (root_idx, (path, is_zip)) in roots.iter().zip(is_zip).enumerate() {
+            let root_idx = root_idx as u32;
+            // Changing the mod order changes which files win, so it has to invalidate the cache
+            checksum.update(&(priorities[root_idx as usize] as u64).to_le_bytes());
+            if !is_zip {
+                checksum.update(path.as_str().as_bytes());
+                for (file_path, len) in directory_files.next().unwrap() {
+                    checksum.update(file_path.as_str().as_bytes());
+                    checksum.update(&len.to_le_bytes());
+                    println!("\tDiscovered {file_path}");
+
+                    let kind = if file_path.as_str().ends_with(ZSTD_EXTENSION) {
+                        let decompressed_size = std::fs::File::open(path.join(&file_path))
+                            .ok()
+                            .and_then(read_zstd_content_size);
+                        let Some(decompressed_size) = decompressed_size else {
+                            errors.push(DiscoveryError::InvalidZstdFrame { path: file_path });
+                            continue;
                         };
 
+                        FileKind::ZstdCompressed {
+                            start: 0,
+                            compressed_size: len,
+                            decompressed_size,
+                            frame_start: 0,
+                        }
+                    } else {
+                        FileKind::Uncompressed { size: len }
+                    };
+
+                    let file_path = Utf8Path::new(
+                        file_path
+                            .as_str()
+                            .strip_suffix(ZSTD_EXTENSION)
+                            .unwrap_or(file_path.as_str()),
+                    );
+
+                    let regional = match detect_regional_and_cache(
+                        file_path,
+                        hashes,
+                        cache,
+                        &mut filepath_buffer,
+                        progress,
+                    ) {
+                        Ok((_, regional)) => regional,
+                        Err(e) => {
+                            errors.push(e);
+                            continue;
+                        }
+                    };
+
+                    let path = if matches!(&regional, Regionalized::None) {
+                        file_path
+                    } else {
+                        Utf8Path::new(&filepath_buffer)
+                    };
+
+                    if matches!(kind, FileKind::Uncompressed { .. }) {
                         uncompressed_files += 1;
-                        files
-                            .entry(path.into_hash())
-                            .or_default()
-                            .set_by_regionalized(
-                                DiscoveredFile {
-                                    root_index: root_idx,
-                                    regionalized: regional,
-                                    kind: FileKind::Uncompressed { size: len },
-                                },
-                                regional,
-                            );
-                    },
-                );
-            } else if ft.is_file() && entry.file_name().ends_with(".zip") {
+                    } else {
+                        compressed_files += 1;
+                    }
+
+                    let replaced = files
+                        .entry(path.into_hash())
+                        .or_default()
+                        .set_by_regionalized(
+                            DiscoveredFile {
+                                root_index: root_idx,
+                                regionalized: regional,
+                                kind,
+                            },
+                            regional,
+                            &priorities,
+                        );
+
+                    if let Some((winner, loser)) = replaced {
+                        if matches!(loser.kind, FileKind::Uncompressed { .. }) {
+                            uncompressed_files -= 1;
+                        } else {
+                            compressed_files -= 1;
+                        }
+                        record_conflict(&mut conflicts, &roots, path, winner, loser.root_index);
+                    }
+                }
+            } else {
                 checksum.update(path.as_str().as_bytes());
-                roots.push(path.to_path_buf());
                 let zip = rawzip::ZipArchive::from_file(
                     std::fs::File::open(path).unwrap(),
                     &mut zip_buffer,
@@ -818,20 +1693,60 @@ impl Discovery {
                         continue;
                     }
 
-                    let fp = unsafe { std::str::from_utf8_unchecked(next.file_path().as_bytes()) };
+                    let mut fp =
+                        unsafe { std::str::from_utf8_unchecked(next.file_path().as_bytes()) };
 
                     let wayfinder = next.wayfinder();
                     checksum.update(fp.as_bytes());
                     checksum.update(&(wayfinder.uncompressed_size_hint() as u32).to_le_bytes());
                     let file = zip.get_entry(wayfinder).unwrap();
+                    let start = file.compressed_data_range().0;
+
+                    // zstd files have to be stored in the zip without any compression of their own
+                    let kind = if let Some(stripped) = fp.strip_suffix(ZSTD_EXTENSION) {
+                        let decompressed_size = (wayfinder.compressed_size_hint()
+                            == wayfinder.uncompressed_size_hint())
+                        .then(|| {
+                            let mut zip_file = std::fs::File::open(path).ok()?;
+                            zip_file.seek(SeekFrom::Start(start)).ok()?;
+                            read_zstd_content_size(zip_file)
+                        })
+                        .flatten();
+                        let Some(decompressed_size) = decompressed_size else {
+                            errors.push(DiscoveryError::InvalidZstdFrame {
+                                path: path.join(fp),
+                            });
+                            continue;
+                        };
+
+                        fp = stripped;
+                        FileKind::ZstdCompressed {
+                            start: start as u32,
+                            compressed_size: wayfinder.compressed_size_hint() as u32,
+                            decompressed_size,
+                            frame_start: 0,
+                        }
+                    } else {
+                        FileKind::Compressed {
+                            start: start as u32,
+                            compressed_size: wayfinder.compressed_size_hint() as u32,
+                            decompressed_size: wayfinder.uncompressed_size_hint() as u32,
+                        }
+                    };
 
-                    let regional = detect_regional_and_cache(
+                    let regional = match detect_regional_and_cache(
                         Utf8Path::new(fp),
                         hashes,
                         cache,
                         &mut filepath_buffer,
-                    )
-                    .1;
+                        progress,
+                    ) {
+                        Ok((_, regional)) => regional,
+                        Err(e) => {
+                            errors.push(e);
+                            continue;
+                        }
+                    };
 
                     let path = if matches!(&regional, Regionalized::None) {
                         Utf8Path::new(fp)
@@ -840,32 +1755,50 @@ impl Discovery {
                     };
                     // let hash = Hash40::const_new(fp);
 
-                    files
+                    let replaced = files
                         .entry(path.into_hash())
                         .or_default()
                         .set_by_regionalized(
                             DiscoveredFile {
                                 root_index: root_idx,
                                 regionalized: regional,
-                                kind: FileKind::Compressed {
-                                    start: file.compressed_data_range().0 as u32,
-                                    compressed_size: wayfinder.compressed_size_hint() as u32,
-                                    decompressed_size: wayfinder.uncompressed_size_hint() as u32,
-                                },
+                                kind,
                             },
                             regional,
+                            &priorities,
                         );
                     compressed_files += 1;
+
+                    if let Some((winner, loser)) = replaced {
+                        if matches!(loser.kind, FileKind::Uncompressed { .. }) {
+                            uncompressed_files -= 1;
+                        } else {
+                            compressed_files -= 1;
+                        }
+                        record_conflict(&mut conflicts, &roots, path, winner, loser.root_index);
+                    }
                 }
             }
         }
+        println!(
+            "[stratus::discovery] Interned discovered files in {:.3}s",
+            now.elapsed().as_secs_f32()
+        );
+
+        for error in errors.iter() {
+            log::warn!("[stratus::discovery] Skipping file: {error}");
+        }
 
         Self {
             compressed_files,
             uncompressed_files,
             roots,
+            priorities,
+            metadata,
             files,
             checksum: checksum.finalize(),
+            errors,
+            conflicts: conflicts.into_values().collect(),
         }
     }
 }
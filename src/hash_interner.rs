@@ -22,10 +22,55 @@ impl u24 {
         u32::from_le_bytes([a, b, c, 0])
     }
 
+    /// Panics if `n` does not fit in 24 bits, see [`u24::try_from_u32`] for a fallible version
     pub const fn from_u32(n: u32) -> Self {
+        match Self::try_from_u32(n) {
+            Some(value) => value,
+            None => panic!("value does not fit in a u24"),
+        }
+    }
+
+    pub const fn try_from_u32(n: u32) -> Option<Self> {
         let [a, b, c, d] = n.to_le_bytes();
-        assert!(d == 0);
-        Self([a, b, c])
+        if d == 0 {
+            Some(Self([a, b, c]))
+        } else {
+            None
+        }
+    }
+}
+
+impl From<u24> for u32 {
+    fn from(value: u24) -> Self {
+        value.to_u32()
+    }
+}
+
+impl TryFrom<u32> for u24 {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        Self::try_from_u32(value).ok_or(())
+    }
+}
+
+impl PartialEq for u24 {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_u32() == other.to_u32()
+    }
+}
+
+impl Eq for u24 {}
+
+impl PartialOrd for u24 {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for u24 {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.to_u32().cmp(&other.to_u32())
     }
 }
 
@@ -45,12 +90,24 @@ const STRING_MEMORY_SLAB_SIZE: usize = 100_000;
 // size_of::<(ShiftedHash, SmolRange)> * HASH_BUCKET_COUNT * HASH_BUCKET_SIZE ~= 6 MB
 // HASH_BUCKET_COUNT CANNOT CHANGE
 const HASH_BUCKET_COUNT: usize = 0x100;
+// Default bucket size, use HashMemorySlab::with_capacity for larger mod sets
 const HASH_BUCKET_SIZE: usize = 0xC00;
 
 // 16 MB
 // This memory region is not to exceed 16MB so that we can use SmolRange for range definitions
 const INTERNED_PATH_SLAB_SIZE: usize = (4 * 1024 * 1024) / std::mem::size_of::<u24>();
 
+// Interned components nest once per parent folder, which is usually only a handful of levels.
+// Anything deeper than this spills onto the heap, see `ComponentIter`
+const COMPONENT_ITER_INLINE_DEPTH: usize = 8;
+
+// Version 0 has three lengths, version 1 adds the bucket size and neither are tagged.
+// Version 2 is tagged with the version and adds a CRC32 of the blob
+const META_VERSION: usize = 2;
+
+/// How many paths [`HashMemorySlab::intern_path_with_progress`] interns between progress callbacks
+pub const INTERN_PROGRESS_INTERVAL: usize = 1000;
+
 /// # Safety
 /// The returned memory from this function is **not** initialized, which means that the caller must
 /// be cautious not to use it to return references to uninitialized memory
@@ -86,10 +143,22 @@ pub unsafe fn allocate_uninit(size: NonZeroUsize, align: NonZeroUsize) -> Box<[u
 pub struct SmolRange(u32);
 
 impl SmolRange {
+    pub const MAX_LEN: usize = u8::MAX as usize;
+
     pub const fn new(len: u8, start: u24) -> Self {
         Self(((len as u32) << 24) | start.to_u32())
     }
 
+    /// Same as [`SmolRange::new`], but returns [`None`] instead of truncating lengths that don't
+    /// fit in a `u8`
+    pub const fn try_new(len: usize, start: u24) -> Option<Self> {
+        if len > Self::MAX_LEN {
+            None
+        } else {
+            Some(Self::new(len as u8, start))
+        }
+    }
+
     const fn len(self) -> u8 {
         ((self.0 & 0xFF000000) >> 24) as u8
     }
@@ -121,6 +190,11 @@ pub struct InternerCache {
     component_index: Hash40Map<u24>,
     cached_paths: Hash40Map<u24>,
     previous_bucket_lengths: Vec<usize>,
+    /// The parent of the most recently interned path, along with its index in the hash table
+    last_parent: String,
+    last_parent_index: Option<u24>,
+    /// The number of paths interned through [`HashMemorySlab::intern_path_with_progress`]
+    interned_count: usize,
 }
 
 impl InternerCache {
@@ -129,6 +203,9 @@ impl InternerCache {
             component_index: Hash40Map::default(),
             cached_paths: Hash40Map::default(),
             previous_bucket_lengths: vec![],
+            last_parent: String::new(),
+            last_parent_index: None,
+            interned_count: 0,
         }
     }
 }
@@ -147,6 +224,7 @@ pub struct HashMemorySlab {
 
     hashes: *mut [HashLookupKey],
     bucket_lengths: *mut [u32],
+    bucket_size: usize,
 
     was_finalized: bool,
 }
@@ -157,9 +235,46 @@ pub struct MemoryUsageFraction {
     pub denom: usize,
 }
 
+impl MemoryUsageFraction {
+    const BAR_WIDTH: usize = 20;
+
+    fn ratio(&self) -> f32 {
+        if self.denom == 0 {
+            0.0
+        } else {
+            self.numer as f32 / self.denom as f32
+        }
+    }
+}
+
+/// Formats a byte count with the largest unit that keeps it above 1
+struct ByteCount(usize);
+
+impl Display for ByteCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * KB;
+        match self.0 {
+            n if n >= MB => write!(f, "{:.1}MB", n as f32 / MB as f32),
+            n if n >= KB => write!(f, "{:.1}KB", n as f32 / KB as f32),
+            n => write!(f, "{n}B"),
+        }
+    }
+}
+
 impl Display for MemoryUsageFraction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} / {}", self.numer, self.denom)
+        let ratio = self.ratio();
+        let filled = ((ratio * Self::BAR_WIDTH as f32).round() as usize).min(Self::BAR_WIDTH);
+        write!(
+            f,
+            "[{}{}] {:.1}% ({} / {})",
+            "#".repeat(filled),
+            ".".repeat(Self::BAR_WIDTH - filled),
+            ratio * 100.0,
+            ByteCount(self.numer),
+            ByteCount(self.denom)
+        )
     }
 }
 
@@ -174,24 +289,136 @@ pub struct MemoryUsageReport {
 impl Display for MemoryUsageReport {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Memory Usage Report:")?;
-        writeln!(f, "\tTOTAL SIZE:      {}b", self.total_blob_size)?;
+        writeln!(f, "\tTOTAL SIZE:      {}", ByteCount(self.total_blob_size))?;
         writeln!(f, "\tTEXT:            {}", self.bytes)?;
         writeln!(f, "\tSTRING SLICES:   {}", self.strings)?;
         writeln!(f, "\tHASH COMPONENTS: {}", self.components)?;
         writeln!(f, "\tHASH SLICES:     {}", self.hashes)?;
 
-        let total_numer =
-            self.bytes.numer + self.strings.numer + self.components.numer + self.hashes.numer;
-        let total_denom =
-            self.bytes.denom + self.strings.denom + self.components.denom + self.hashes.denom;
+        let total = MemoryUsageFraction {
+            numer: self.bytes.numer
+                + self.strings.numer
+                + self.components.numer
+                + self.hashes.numer,
+            denom: self.bytes.denom
+                + self.strings.denom
+                + self.components.denom
+                + self.hashes.denom,
+        };
 
-        writeln!(
-            f,
-            "\tTOTAL CONSUMED:  {} / {} ({:.2}%)",
-            total_numer,
-            total_denom,
-            (total_numer as f32 / total_denom as f32) * 100.0
-        )
+        writeln!(f, "\tTOTAL CONSUMED:  {total}")
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct BucketUsageReport {
+    pub max_occupancy: usize,
+    pub mean_occupancy: f32,
+    pub buckets_above_90_pct: usize,
+}
+
+impl Display for BucketUsageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Bucket Usage Report:")?;
+        writeln!(f, "\tMAX OCCUPANCY:   {}", self.max_occupancy)?;
+        writeln!(f, "\tMEAN OCCUPANCY:  {:.2}", self.mean_occupancy)?;
+        writeln!(f, "\tABOVE 90%:       {}", self.buckets_above_90_pct)
+    }
+}
+
+/// Converts an index into one of the slabs into a [`u24`], failing instead of panicking so that
+/// interning can report it as an error
+fn slab_index(index: usize) -> Result<u24, InternPathError> {
+    u32::try_from(index)
+        .ok()
+        .and_then(|index| u24::try_from(index).ok())
+        .ok_or(InternPathError::IndexOverflow { index })
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum InternPathError {
+    BucketOverflow { bucket: usize, capacity: usize },
+    PathTooDeep { components: usize },
+    ComponentTooLong { len: usize },
+    IndexOverflow { index: usize },
+}
+
+impl Display for InternPathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BucketOverflow { bucket, capacity } => write!(
+                f,
+                "hash bucket {bucket:#x} is full ({capacity} entries), increase the bucket size"
+            ),
+            Self::PathTooDeep { components } => write!(
+                f,
+                "path has {components} components, the maximum is {}",
+                SmolRange::MAX_LEN
+            ),
+            Self::ComponentTooLong { len } => write!(
+                f,
+                "path component is {len} bytes long, the maximum is {}",
+                SmolRange::MAX_LEN
+            ),
+            Self::IndexOverflow { index } => {
+                write!(f, "interned index {index:#x} does not fit in 24 bits")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum BlobError {
+    InvalidMeta,
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+impl Display for BlobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMeta => f.write_str("meta file is not in a known format"),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "blob checksum {actual:#010x} does not match expected {expected:#010x}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum SlabValidationError {
+    BucketOverfilled { bucket: usize, len: usize },
+    BucketNotSorted { bucket: usize },
+    HashRangeOutOfBounds { hash_index: usize },
+    ComponentOutOfBounds { component: usize },
+    StringOutOfBounds { string: usize },
+}
+
+impl Display for SlabValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BucketOverfilled { bucket, len } => {
+                write!(
+                    f,
+                    "hash bucket {bucket:#x} has {len} entries, which is over capacity"
+                )
+            }
+            Self::BucketNotSorted { bucket } => {
+                write!(f, "hash bucket {bucket:#x} is not sorted or has duplicates")
+            }
+            Self::HashRangeOutOfBounds { hash_index } => {
+                write!(
+                    f,
+                    "hash {hash_index:#x} points outside of the component table"
+                )
+            }
+            Self::ComponentOutOfBounds { component } => {
+                write!(f, "component {component:#x} points outside of its table")
+            }
+            Self::StringOutOfBounds { string } => {
+                write!(f, "string {string:#x} points outside of the byte table")
+            }
+        }
     }
 }
 
@@ -202,7 +429,10 @@ pub struct InternPathResult {
 }
 
 impl HashMemorySlab {
-    fn init(get_memory: impl FnOnce(NonZeroUsize, NonZeroUsize) -> Box<[u8]>) -> Self {
+    fn init(
+        bucket_size: usize,
+        get_memory: impl FnOnce(NonZeroUsize, NonZeroUsize) -> Box<[u8]>,
+    ) -> Self {
         const fn align_up(value: usize, align: usize) -> usize {
             (value + (align - 1)) & !(align - 1)
         }
@@ -219,6 +449,10 @@ impl HashMemorySlab {
         .max()
         .unwrap();
         assert!(align.is_power_of_two());
+        // Hash indices are stored as u24 with IS_INTERNED_COMPONENT as a flag
+        assert!(
+            bucket_size > 0 && HASH_BUCKET_COUNT * bucket_size < IS_INTERNED_COMPONENT as usize
+        );
 
         let bytes_offset = 0;
         let string_offset = bytes_offset + align_up(HASH_MEMORY_SLAB_SIZE, align);
@@ -228,7 +462,7 @@ impl HashMemorySlab {
             component_offset + align_up(INTERNED_PATH_SLAB_SIZE * size_of::<u24>(), align);
         let bucket_length_offset = lookup_offset
             + align_up(
-                HASH_BUCKET_COUNT * HASH_BUCKET_SIZE * size_of::<HashLookupKey>(),
+                HASH_BUCKET_COUNT * bucket_size * size_of::<HashLookupKey>(),
                 align,
             );
         let size = bucket_length_offset + align_up(HASH_BUCKET_COUNT * size_of::<u32>(), align);
@@ -264,7 +498,7 @@ impl HashMemorySlab {
         let lookup = unsafe {
             std::ptr::slice_from_raw_parts_mut(
                 blob.as_mut_ptr().add(lookup_offset).cast::<HashLookupKey>(),
-                HASH_BUCKET_COUNT * HASH_BUCKET_SIZE,
+                HASH_BUCKET_COUNT * bucket_size,
             )
         };
         // SAFETY: See above
@@ -288,12 +522,23 @@ impl HashMemorySlab {
             component_len: 0,
             hashes: lookup,
             bucket_lengths,
+            bucket_size,
             was_finalized: false,
         }
     }
 
     pub fn new() -> Self {
-        let this = Self::init(|size, align| unsafe { allocate_uninit(size, align) });
+        Self::with_capacity(HASH_BUCKET_SIZE)
+    }
+
+    /// Creates a new slab where each of the hash buckets can hold `bucket_size` hashes.
+    ///
+    /// The default bucket size is enough for the base game hashes plus a typical mod set, users with
+    /// unusually large mod sets can use this to avoid bucket overflows.
+    pub fn with_capacity(bucket_size: usize) -> Self {
+        let this = Self::init(bucket_size, |size, align| unsafe {
+            allocate_uninit(size, align)
+        });
         unsafe {
             (*this.bucket_lengths).fill(0u32);
         }
@@ -310,7 +555,6 @@ impl HashMemorySlab {
         cache
     }
 
-    #[allow(dead_code)]
     pub fn report(&self) -> MemoryUsageReport {
         MemoryUsageReport {
             total_blob_size: self.total_blob_size,
@@ -339,8 +583,126 @@ impl HashMemorySlab {
         }
     }
 
-    pub fn from_blob(blob: Box<[u8]>, meta: Box<[u8]>) -> Self {
-        let mut this = Self::init(|size, align| {
+    /// Checks that every hash, component and string in the slab points to valid memory
+    ///
+    /// This is meant to be used on a finalized slab, since buckets are only sorted once finalized
+    #[allow(dead_code)]
+    pub fn validate(&self) -> Result<(), Vec<SlabValidationError>> {
+        let mut errors = vec![];
+
+        // SAFETY: All of these slices are set up in the constructor and only ever indexed up to their lengths
+        let (hashes, lengths, components, strings) = unsafe {
+            (
+                &*self.hashes,
+                &*self.bucket_lengths,
+                &(&*self.components)[..self.component_len],
+                &(&*self.strings)[..self.string_len],
+            )
+        };
+
+        for (bucket, len) in lengths.iter().enumerate() {
+            let len = *len as usize;
+            if len > self.bucket_size {
+                errors.push(SlabValidationError::BucketOverfilled { bucket, len });
+                continue;
+            }
+
+            let start = bucket * self.bucket_size;
+            let keys = &hashes[start..start + len];
+            if keys
+                .windows(2)
+                .any(|pair| pair[0].shifted_hash >= pair[1].shifted_hash)
+            {
+                errors.push(SlabValidationError::BucketNotSorted { bucket });
+            }
+
+            for (offset, key) in keys.iter().enumerate() {
+                let range = key.range.range();
+                if range.end as usize > components.len() {
+                    errors.push(SlabValidationError::HashRangeOutOfBounds {
+                        hash_index: start + offset,
+                    });
+                }
+            }
+        }
+
+        for (component, index) in components.iter().enumerate() {
+            let index = index.to_u32();
+            let in_bounds = if index & IS_INTERNED_COMPONENT != 0 {
+                ((index & !IS_INTERNED_COMPONENT) as usize) < hashes.len()
+            } else {
+                (index as usize) < strings.len()
+            };
+
+            if !in_bounds {
+                errors.push(SlabValidationError::ComponentOutOfBounds { component });
+            }
+        }
+
+        for (string, range) in strings.iter().enumerate() {
+            if range.range().end as usize > self.byte_len {
+                errors.push(SlabValidationError::StringOutOfBounds { string });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn bucket_usage(&self) -> BucketUsageReport {
+        let lengths = unsafe { &*self.bucket_lengths };
+        let max_occupancy = lengths.iter().map(|len| *len as usize).max().unwrap_or(0);
+        let total = lengths.iter().map(|len| *len as usize).sum::<usize>();
+        let buckets_above_90_pct = lengths
+            .iter()
+            .filter(|len| **len as usize * 10 > self.bucket_size * 9)
+            .count();
+
+        BucketUsageReport {
+            max_occupancy,
+            mean_occupancy: total as f32 / HASH_BUCKET_COUNT as f32,
+            buckets_above_90_pct,
+        }
+    }
+
+    /// Loads a slab from the output of [`Self::dump_blob`] and [`Self::dump_meta`]
+    ///
+    /// Meta files from before the checksum was added are accepted without verification
+    pub fn from_blob(blob: Box<[u8]>, meta: Box<[u8]>) -> Result<Self, BlobError> {
+        if meta.len() % size_of::<usize>() != 0 {
+            return Err(BlobError::InvalidMeta);
+        }
+
+        let fields = meta
+            .chunks_exact(size_of::<usize>())
+            .map(|chunk| usize::from_le_bytes(chunk.try_into().unwrap()))
+            .collect::<Vec<_>>();
+
+        let (lengths, bucket_size) = match fields.as_slice() {
+            [META_VERSION, byte_len, string_len, component_len, bucket_size, checksum] => {
+                let expected = *checksum as u32;
+                let actual = crc32fast::hash(&blob);
+                if expected != actual {
+                    return Err(BlobError::ChecksumMismatch { expected, actual });
+                }
+                ([*byte_len, *string_len, *component_len], *bucket_size)
+            }
+            [byte_len, string_len, component_len, bucket_size] => {
+                log::warn!("[stratus::hashes] Hash blob meta has no checksum, loading unverified");
+                ([*byte_len, *string_len, *component_len], *bucket_size)
+            }
+            [byte_len, string_len, component_len] => {
+                log::warn!("[stratus::hashes] Hash blob meta has no checksum, loading unverified");
+                ([*byte_len, *string_len, *component_len], HASH_BUCKET_SIZE)
+            }
+            _ => return Err(BlobError::InvalidMeta),
+        };
+
+        let mut this = Self::init(bucket_size, |size, align| {
             assert!(blob.len() == size.get());
             if blob.as_ptr() as usize % align != 0 {
                 let mut region = unsafe { allocate_uninit(size, align) };
@@ -352,13 +714,12 @@ impl HashMemorySlab {
             }
         });
 
-        assert!(meta.len() == size_of::<usize>() * 3);
-        let slice = bytemuck::cast_slice::<u8, usize>(&meta);
-        this.byte_len = usize::from_le(slice[0]);
-        this.string_len = usize::from_le(slice[1]);
-        this.component_len = usize::from_le(slice[2]);
+        let [byte_len, string_len, component_len] = lengths;
+        this.byte_len = byte_len;
+        this.string_len = string_len;
+        this.component_len = component_len;
         this.was_finalized = true;
-        this
+        Ok(this)
     }
 
     fn try_cache_or_finalized_self(
@@ -370,7 +731,7 @@ impl HashMemorySlab {
             if this.was_finalized {
                 let bucket_idx = hash.crc32() as usize % HASH_BUCKET_COUNT;
                 let len = cache.previous_bucket_lengths[bucket_idx];
-                let start_idx = bucket_idx * HASH_BUCKET_SIZE;
+                let start_idx = bucket_idx * this.bucket_size;
                 let bucket = unsafe { &(&*this.hashes)[start_idx..start_idx + len] };
 
                 let shifted_hash = (hash.raw() >> 8) as u32;
@@ -385,38 +746,159 @@ impl HashMemorySlab {
         })
     }
 
-    pub fn intern_path(&mut self, cache: &mut InternerCache, path: &Utf8Path) -> InternPathResult {
-        let range_start = u24::from_u32(self.component_len as u32);
-        let mut len = 0u8;
+    /// Same as [`Self::intern_path`], but calls `progress` with the total number of paths interned
+    /// with this cache every [`INTERN_PROGRESS_INTERVAL`] paths
+    pub fn intern_path_with_progress(
+        &mut self,
+        cache: &mut InternerCache,
+        path: &Utf8Path,
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<InternPathResult, InternPathError> {
+        let result = self.intern_path(cache, path);
+        cache.interned_count += 1;
+        if cache.interned_count % INTERN_PROGRESS_INTERVAL == 0 {
+            progress(cache.interned_count);
+        }
+        result
+    }
+
+    /// Checks that there is room for every component of `path` below `interned_parent`, which
+    /// each get a new hash and possibly a new string
+    ///
+    /// Components that repeat within `path` are counted as new strings every time, so this can
+    /// reject a path that would have just barely fit.
+    fn check_capacity(
+        &self,
+        cache: &InternerCache,
+        interned_parent: &Utf8Path,
+        path: &Utf8Path,
+    ) -> Result<(), InternPathError> {
+        let mut new_strings = 0;
+        let mut new_bytes = 0;
+        let mut new_hashes: Vec<(usize, usize)> = vec![];
+
+        let mut parent_hash = Hash40::const_new(interned_parent.as_str());
+        for component in path.strip_prefix(interned_parent).unwrap().components() {
+            let component = component.as_str();
+            if !cache
+                .component_index
+                .contains_key(&Hash40::const_new(component))
+            {
+                new_strings += 1;
+                new_bytes += component.len();
+            }
+
+            if parent_hash != Hash40::const_new("") {
+                parent_hash = parent_hash.const_with("/");
+            }
+            parent_hash = parent_hash.const_with(component);
+
+            let bucket_idx = parent_hash.crc32() as usize % HASH_BUCKET_COUNT;
+            match new_hashes
+                .iter_mut()
+                .find(|(bucket, _)| *bucket == bucket_idx)
+            {
+                Some((_, count)) => *count += 1,
+                None => new_hashes.push((bucket_idx, 1)),
+            }
+        }
+
+        for (bucket_idx, count) in new_hashes {
+            let bucket_len = unsafe { (*self.bucket_lengths)[bucket_idx] as usize };
+            if bucket_len + count > self.bucket_size {
+                return Err(InternPathError::BucketOverflow {
+                    bucket: bucket_idx,
+                    capacity: self.bucket_size,
+                });
+            }
+            slab_index(bucket_idx * self.bucket_size + bucket_len + count - 1)?;
+        }
+
+        if new_strings > 0 {
+            slab_index(self.string_len + new_strings - 1)?;
+            slab_index(self.byte_len + new_bytes - 1)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn intern_path(
+        &mut self,
+        cache: &mut InternerCache,
+        path: &Utf8Path,
+    ) -> Result<InternPathResult, InternPathError> {
+        let range_start = slab_index(self.component_len)?;
+        let mut len = 0usize;
 
         let full_hash = Hash40::const_new(path.as_str());
 
         if let Some(cached) = Self::try_cache_or_finalized_self(self, cache, full_hash) {
             unsafe {
-                return InternPathResult {
+                return Ok(InternPathResult {
                     range: (*self.hashes)[cached.to_u32() as usize].range,
                     is_new: false,
-                };
+                });
             }
         }
 
+        // Each component takes up at most one entry in the range, so the range of every prefix
+        // fits if the range of the full path does
+        let component_count = path.components().count();
+        if SmolRange::try_new(component_count, range_start).is_none() {
+            return Err(InternPathError::PathTooDeep {
+                components: component_count,
+            });
+        }
+
+        if let Some(component) = path
+            .components()
+            .find(|component| component.as_str().len() > SmolRange::MAX_LEN)
+        {
+            return Err(InternPathError::ComponentTooLong {
+                len: component.as_str().len(),
+            });
+        }
+
         let mut current = path;
-        while let Some(parent) = current.parent() {
+        let mut last_index = None;
+
+        // Files in the same folder are usually interned back to back, so if this path shares its parent
+        // with the previous one we can reference it directly instead of hashing every ancestor
+        let shared_parent = path.parent().filter(|parent| {
+            cache.last_parent_index.is_some() && parent.as_str() == cache.last_parent
+        });
+
+        if let Some(parent) = shared_parent {
             current = parent;
+            last_index = cache.last_parent_index;
+        } else {
+            while let Some(parent) = current.parent() {
+                current = parent;
 
-            let parent_hash = Hash40::const_new(current.as_str());
-            if let Some(cached) = Self::try_cache_or_finalized_self(self, cache, parent_hash) {
-                assert_eq!(cached.to_u32() & IS_INTERNED_COMPONENT, 0x0);
-                unsafe {
-                    (*self.components)[self.component_len] =
-                        u24::from_u32(IS_INTERNED_COMPONENT | cached.to_u32());
-                    self.component_len += 1;
-                    len += 1;
+                let parent_hash = Hash40::const_new(current.as_str());
+                if let Some(cached) = Self::try_cache_or_finalized_self(self, cache, parent_hash) {
+                    assert_eq!(cached.to_u32() & IS_INTERNED_COMPONENT, 0x0);
+                    last_index = Some(cached);
                     break;
                 }
             }
         }
 
+        // Nothing has been written yet, so failing here never leaves a partially interned path
+        // behind
+        self.check_capacity(cache, current, path)?;
+
+        if let Some(cached) = last_index {
+            unsafe {
+                (*self.components)[self.component_len] =
+                    u24::from_u32(IS_INTERNED_COMPONENT | cached.to_u32());
+            }
+            self.component_len += 1;
+            len += 1;
+        }
+
+        let mut parent_index = None;
+
         let mut parent_hash = Hash40::const_new(current.as_str());
         for component in path.strip_prefix(current).unwrap().components() {
             len += 1;
@@ -426,12 +908,13 @@ impl HashMemorySlab {
             } else {
                 let bytes = component.as_str().as_bytes();
                 let new_len = self.byte_len + bytes.len();
+                let byte_start = slab_index(self.byte_len)?;
+                let component_index = slab_index(self.string_len)?;
                 unsafe {
                     (&mut (*self.bytes))[self.byte_len..new_len].copy_from_slice(bytes);
                     (&mut (*self.strings))[self.string_len] =
-                        SmolRange::new(bytes.len() as u8, u24::from_u32(self.byte_len as u32));
+                        SmolRange::new(bytes.len() as u8, byte_start);
                 }
-                let component_index = u24::from_u32(self.string_len as u32);
                 cache.component_index.insert(hash, component_index);
                 self.string_len += 1;
                 self.byte_len = new_len;
@@ -447,28 +930,50 @@ impl HashMemorySlab {
             }
             parent_hash = parent_hash.const_with(component.as_str());
 
-            let range = SmolRange::new(len, range_start);
+            let range = SmolRange::try_new(len, range_start)
+                .ok_or(InternPathError::PathTooDeep { components: len })?;
 
             let bucket_idx = parent_hash.crc32() as usize % HASH_BUCKET_COUNT;
             let bucket_len = unsafe { &mut (*self.bucket_lengths)[bucket_idx] };
-            assert!((*bucket_len as usize) < HASH_BUCKET_SIZE);
-            let hash_idx = (bucket_idx * HASH_BUCKET_SIZE) + *bucket_len as usize;
+            if *bucket_len as usize >= self.bucket_size {
+                return Err(InternPathError::BucketOverflow {
+                    bucket: bucket_idx,
+                    capacity: self.bucket_size,
+                });
+            }
+            let hash_idx = (bucket_idx * self.bucket_size) + *bucket_len as usize;
+            let hash_index = slab_index(hash_idx)?;
             unsafe {
                 (*self.hashes)[hash_idx] = HashLookupKey {
                     shifted_hash: (parent_hash.raw() >> 8) as u32,
                     range,
                 }
             };
-            cache
-                .cached_paths
-                .insert(parent_hash, u24::from_u32(hash_idx as u32));
+            cache.cached_paths.insert(parent_hash, hash_index);
             *bucket_len += 1;
+
+            parent_index = last_index;
+            last_index = Some(hash_index);
         }
 
-        InternPathResult {
-            range: SmolRange::new(len, range_start),
-            is_new: true,
+        // Bare file names and extensions are interned in between full paths, so paths without a
+        // parent leave the cache alone instead of evicting the folder the next path is likely in
+        if shared_parent.is_none() {
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_str().is_empty()) {
+                cache.last_parent.clear();
+                cache.last_parent_index = None;
+                if let Some(index) = parent_index {
+                    cache.last_parent.push_str(parent.as_str());
+                    cache.last_parent_index = Some(index);
+                }
+            }
         }
+
+        Ok(InternPathResult {
+            range: SmolRange::try_new(len, range_start)
+                .ok_or(InternPathError::PathTooDeep { components: len })?,
+            is_new: true,
+        })
     }
 
     pub fn finalize(&mut self, _cache: InternerCache) {
@@ -480,7 +985,7 @@ impl HashMemorySlab {
                     let idx = component.to_u32();
                     if idx & IS_INTERNED_COMPONENT != 0 {
                         let idx = (idx & !IS_INTERNED_COMPONENT) as usize;
-                        let bucket = idx / HASH_BUCKET_SIZE;
+                        let bucket = idx / self.bucket_size;
                         let hash = Hash40::from_raw(
                             (((*self.hashes)[idx].shifted_hash as u64) << 8) | bucket as u64,
                         );
@@ -494,7 +999,7 @@ impl HashMemorySlab {
 
         for bucket_idx in 0..HASH_BUCKET_COUNT {
             let len = unsafe { (*self.bucket_lengths)[bucket_idx] as usize };
-            let start_idx = bucket_idx * HASH_BUCKET_SIZE;
+            let start_idx = bucket_idx * self.bucket_size;
             unsafe {
                 (&mut (*self.hashes))[start_idx..start_idx + len]
                     .sort_unstable_by(|a, b| a.shifted_hash.cmp(&b.shifted_hash));
@@ -519,7 +1024,7 @@ impl HashMemorySlab {
                 // SAFETY: Within this function, we index into slices that we have properly set up in the constructor
                 let bucket_idx = hash.crc32() as usize % HASH_BUCKET_COUNT;
                 let len = unsafe { (*self.bucket_lengths)[bucket_idx] };
-                let start_idx = bucket_idx * HASH_BUCKET_SIZE;
+                let start_idx = bucket_idx * self.bucket_size;
                 let bucket = unsafe { &(&(*self.hashes))[start_idx..start_idx + len as usize] };
                 let local_idx = bucket
                     .binary_search_by_key(&shifted_hash, |a| a.shifted_hash)
@@ -538,20 +1043,34 @@ impl HashMemorySlab {
         self.was_finalized = true;
     }
 
+    fn full_blob(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts((*self.bytes).as_ptr(), self.total_blob_size) }
+    }
+
+    #[allow(dead_code)]
     pub fn dump_blob(&self) -> Vec<u8> {
-        let full_blob =
-            unsafe { std::slice::from_raw_parts((*self.bytes).as_ptr(), self.total_blob_size) };
+        self.full_blob().to_vec()
+    }
 
-        let mut out = Vec::with_capacity(full_blob.len());
-        out.extend_from_slice(full_blob);
-        out
+    /// Writes the blob straight from the slab's memory, without copying it into a [`Vec`] first
+    pub fn dump_blob_into(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        writer.write_all(self.full_blob())
+    }
+
+    pub fn dump_meta_into(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        writer.write_all(&self.dump_meta())
     }
 
     pub fn dump_meta(&self) -> Vec<u8> {
-        let mut meta = Vec::with_capacity(size_of::<usize>() * 3);
+        let checksum = crc32fast::hash(self.full_blob()) as usize;
+
+        let mut meta = Vec::with_capacity(size_of::<usize>() * 6);
+        meta.extend_from_slice(&META_VERSION.to_le_bytes());
         meta.extend_from_slice(&self.byte_len.to_le_bytes());
         meta.extend_from_slice(&self.string_len.to_le_bytes());
         meta.extend_from_slice(&self.component_len.to_le_bytes());
+        meta.extend_from_slice(&self.bucket_size.to_le_bytes());
+        meta.extend_from_slice(&checksum.to_le_bytes());
         meta
     }
 
@@ -593,7 +1112,7 @@ impl HashMemorySlab {
         // SAFETY: Within this function, we index into slices that we have properly set up in the constructor
         let bucket_idx = hash.crc32() as usize % HASH_BUCKET_COUNT;
         let len = unsafe { (*self.bucket_lengths)[bucket_idx] };
-        let start_idx = bucket_idx * HASH_BUCKET_SIZE;
+        let start_idx = bucket_idx * self.bucket_size;
         let bucket = unsafe { &(&*self.hashes)[start_idx..start_idx + len as usize] };
 
         let shifted_hash = (hash.raw() >> 8) as u32;
@@ -644,7 +1163,7 @@ impl HashMemorySlab {
         // SAFETY: Within this function, we index into slices that we have properly set up in the constructor
         let bucket_idx = hash.crc32() as usize % HASH_BUCKET_COUNT;
         let len = unsafe { (*self.bucket_lengths)[bucket_idx] };
-        let start_idx = bucket_idx * HASH_BUCKET_SIZE;
+        let start_idx = bucket_idx * self.bucket_size;
         let bucket = unsafe { &(&*self.hashes)[start_idx..start_idx + len as usize] };
 
         let shifted_hash = (hash.raw() >> 8) as u32;
@@ -654,6 +1173,132 @@ impl HashMemorySlab {
             Err(_) => None,
         }
     }
+
+    /// Checks if this hash has been interned, without walking its components
+    #[allow(dead_code)]
+    pub fn contains(&self, hash: Hash40) -> bool {
+        // SAFETY: Within this function, we index into slices that we have properly set up in the constructor
+        let bucket_idx = hash.crc32() as usize % HASH_BUCKET_COUNT;
+        let len = unsafe { (*self.bucket_lengths)[bucket_idx] };
+        let start_idx = bucket_idx * self.bucket_size;
+        let bucket = unsafe { &(&*self.hashes)[start_idx..start_idx + len as usize] };
+
+        let shifted_hash = (hash.raw() >> 8) as u32;
+
+        bucket
+            .binary_search_by(|a| a.shifted_hash.cmp(&shifted_hash))
+            .is_ok()
+    }
+
+    /// Returns an iterator over the string components of this hash, if known.
+    ///
+    /// Nested interned components are walked with an inline stack, so this only allocates for paths
+    /// nested more than 8 interned components deep.
+    #[allow(dead_code)]
+    pub fn components_for(&self, hash: Hash40) -> Option<ComponentIter<'_>> {
+        // SAFETY: Within this function, we index into slices that we have properly set up in the constructor
+        let bucket_idx = hash.crc32() as usize % HASH_BUCKET_COUNT;
+        let len = unsafe { (*self.bucket_lengths)[bucket_idx] };
+        let start_idx = bucket_idx * self.bucket_size;
+        let bucket = unsafe { &(&*self.hashes)[start_idx..start_idx + len as usize] };
+
+        let shifted_hash = (hash.raw() >> 8) as u32;
+
+        let idx = bucket
+            .binary_search_by(|a| a.shifted_hash.cmp(&shifted_hash))
+            .ok()?;
+
+        let mut iter = ComponentIter {
+            slab: self,
+            stack: [ComponentIterFrame::EMPTY; COMPONENT_ITER_INLINE_DEPTH],
+            depth: 1,
+            spilled: Vec::new(),
+        };
+        iter.stack[0].range = bucket[idx].range;
+        Some(iter)
+    }
+}
+
+#[derive(Copy, Clone)]
+struct ComponentIterFrame {
+    range: SmolRange,
+    current: u8,
+}
+
+impl ComponentIterFrame {
+    const EMPTY: Self = Self {
+        range: SmolRange::new(0, u24::from_u32(0)),
+        current: 0,
+    };
+}
+
+pub struct ComponentIter<'a> {
+    slab: &'a HashMemorySlab,
+    stack: [ComponentIterFrame; COMPONENT_ITER_INLINE_DEPTH],
+    depth: usize,
+    /// Frames past the inline stack, which sit on top of it
+    spilled: Vec<ComponentIterFrame>,
+}
+
+impl ComponentIter<'_> {
+    fn top_mut(&mut self) -> Option<&mut ComponentIterFrame> {
+        if let Some(frame) = self.spilled.last_mut() {
+            return Some(frame);
+        }
+
+        self.depth.checked_sub(1).map(|top| &mut self.stack[top])
+    }
+
+    fn push(&mut self, frame: ComponentIterFrame) {
+        if self.depth < COMPONENT_ITER_INLINE_DEPTH {
+            self.stack[self.depth] = frame;
+            self.depth += 1;
+        } else {
+            self.spilled.push(frame);
+        }
+    }
+
+    fn pop(&mut self) {
+        if self.spilled.pop().is_none() {
+            self.depth -= 1;
+        }
+    }
+}
+
+impl<'a> Iterator for ComponentIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slab = self.slab;
+        while let Some(frame) = self.top_mut() {
+            if frame.current >= frame.range.len() {
+                self.pop();
+                continue;
+            }
+
+            let comp_idx = frame.range.start().to_u32() as usize + frame.current as usize;
+            frame.current += 1;
+
+            // SAFETY: The ranges we walk were all created by intern_path and point into the slab
+            let string_idx = unsafe { (*slab.components)[comp_idx].to_u32() };
+            if string_idx & IS_INTERNED_COMPONENT != 0 {
+                let index = (string_idx & !IS_INTERNED_COMPONENT) as usize;
+                self.push(ComponentIterFrame {
+                    range: unsafe { (*slab.hashes)[index].range },
+                    current: 0,
+                });
+            } else {
+                let string = unsafe { (*slab.strings)[string_idx as usize] };
+                let byte_start = string.start().to_u32() as usize;
+                let bytes =
+                    unsafe { &(&(*slab.bytes))[byte_start..byte_start + string.len() as usize] };
+                // SAFETY: We take the bytes from a &str to write into this buffer
+                return Some(unsafe { std::str::from_utf8_unchecked(bytes) });
+            }
+        }
+
+        None
+    }
 }
 
 impl Drop for HashMemorySlab {
@@ -677,7 +1322,7 @@ impl Debug for DisplayHash<'_> {
 
 impl Display for DisplayHash<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // Don't use component iter here since this can be done recursively where iter requires allocing memory
+        // Don't use component iter here since writing recursively lets us insert separators in place
         write_hash(self.hash, self.slab, f)
     }
 }
@@ -718,7 +1363,7 @@ pub fn write_hash(
     // SAFETY: Within this function, we index into slices that we have properly set up in the constructor
     let bucket_idx = hash.crc32() as usize % HASH_BUCKET_COUNT;
     let len = unsafe { (*slab.bucket_lengths)[bucket_idx] };
-    let start_idx = bucket_idx * HASH_BUCKET_SIZE;
+    let start_idx = bucket_idx * slab.bucket_size;
     let bucket = unsafe { &(&*slab.hashes)[start_idx..start_idx + len as usize] };
 
     let shifted_hash = (hash.raw() >> 8) as u32;
@@ -734,3 +1379,126 @@ pub fn write_hash(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interns `path` the same way that discovery and the hash file loader do, with the file name
+    /// and extension first
+    fn intern_like_discovery(slab: &mut HashMemorySlab, cache: &mut InternerCache, path: &str) {
+        let path = Utf8Path::new(path);
+        if let Some(extension) = path.extension() {
+            slab.intern_path(cache, Utf8Path::new(extension)).unwrap();
+        }
+        if let Some(file_name) = path.file_name() {
+            slab.intern_path(cache, Utf8Path::new(file_name)).unwrap();
+        }
+        slab.intern_path(cache, path).unwrap();
+    }
+
+    /// 50,000 paths under a common 4 component prefix, in folders of 1,000 files each
+    fn synthetic_paths() -> Vec<String> {
+        (0..50_000)
+            .map(|index| {
+                format!(
+                    "fighter/mario/model/body/c{:02}/texture_{index}.nutexb",
+                    index / 1000
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bucket_overflow_leaves_nothing_behind() {
+        let mut slab = HashMemorySlab::with_capacity(1);
+        let mut cache = slab.create_cache();
+
+        for index in 0.. {
+            let lengths = (slab.component_len, slab.string_len, slab.byte_len);
+            let folder = format!("folder_{index}");
+            let path = format!("{folder}/file_{index}");
+            match slab.intern_path(&mut cache, Utf8Path::new(&path)) {
+                Ok(_) => continue,
+                Err(InternPathError::BucketOverflow { .. }) => {
+                    assert_eq!(
+                        (slab.component_len, slab.string_len, slab.byte_len),
+                        lengths
+                    );
+                    assert!(!cache.cached_paths.contains_key(&Hash40::const_new(&folder)));
+                    break;
+                }
+                Err(error) => panic!("{error}"),
+            }
+        }
+    }
+
+    #[test]
+    fn parentless_paths_keep_parent_cache() {
+        let mut slab = HashMemorySlab::new();
+        let mut cache = slab.create_cache();
+
+        intern_like_discovery(
+            &mut slab,
+            &mut cache,
+            "fighter/mario/model/body/c00/a.nutexb",
+        );
+        let parent_index = cache.last_parent_index;
+        assert!(parent_index.is_some());
+        assert_eq!(cache.last_parent, "fighter/mario/model/body/c00");
+
+        slab.intern_path(&mut cache, Utf8Path::new("b.nutexb"))
+            .unwrap();
+        assert_eq!(cache.last_parent_index, parent_index);
+        assert_eq!(cache.last_parent, "fighter/mario/model/body/c00");
+
+        let result = slab
+            .intern_path(
+                &mut cache,
+                Utf8Path::new("fighter/mario/model/body/c00/b.nutexb"),
+            )
+            .unwrap();
+        assert!(result.is_new);
+
+        let hash = Hash40::const_new("fighter/mario/model/body/c00/b.nutexb");
+        let components = slab.components_for(hash).unwrap().collect::<Vec<_>>();
+        assert_eq!(
+            components,
+            ["fighter", "mario", "model", "body", "c00", "b.nutexb"]
+        );
+    }
+
+    /// Compares interning with the parent cache against interning with it cleared before every
+    /// path, which has to look up the ancestors of each path
+    ///
+    /// Timing based, so run it in release with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn bench_shared_prefix_interning() {
+        let paths = synthetic_paths();
+
+        let mut slab = HashMemorySlab::with_capacity(0x1000);
+        let mut cache = slab.create_cache();
+        let now = std::time::Instant::now();
+        for path in paths.iter() {
+            cache.last_parent.clear();
+            cache.last_parent_index = None;
+            intern_like_discovery(&mut slab, &mut cache, path);
+        }
+        let baseline = now.elapsed();
+
+        let mut slab = HashMemorySlab::with_capacity(0x1000);
+        let mut cache = slab.create_cache();
+        let now = std::time::Instant::now();
+        for path in paths.iter() {
+            intern_like_discovery(&mut slab, &mut cache, path);
+        }
+        let cached = now.elapsed();
+
+        println!("baseline: {baseline:?}, with parent cache: {cached:?}");
+        assert!(
+            cached.as_secs_f64() <= baseline.as_secs_f64() * 0.7,
+            "expected at least a 30% speedup, baseline: {baseline:?}, cached: {cached:?}"
+        );
+    }
+}
@@ -1,35 +1,39 @@
 use std::{
-    collections::{HashMap, HashSet},
+    cell::{Cell, RefCell},
+    collections::{BTreeSet, HashMap, HashSet},
     ffi::CStr,
     io::Read,
     ops::Deref,
     ptr::NonNull,
     sync::{
         atomic::{AtomicBool, Ordering},
-        OnceLock,
+        Mutex, OnceLock,
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
 
-use camino::Utf8Path;
-use log::LevelFilter;
+use camino::{Utf8Path, Utf8PathBuf};
 use skyline::hooks::InlineCtx;
 use smash_hash::{Hash40, Hash40Map, Hash40Set};
 
+#[cfg(feature = "verbose_decompression")]
+use crate::archive::{decompress_stream, ZstdBuffer};
 use crate::{
-    archive::{decompress_stream, Archive, ZstdBuffer},
+    archive::Archive,
+    config::StratusConfig,
     data::{
         FileData, FileDescriptor, FileEntity, FileGroup, FileInfo, FileInfoFlags, FileLoadMethod,
-        FilePackage, FilePackageChild, FilePath, IntoHash, Locale, Region, SearchFolder,
-        SearchPath, TryFilePathResult,
+        FilePath, IntoHash, Locale, Region, SearchFolder, SearchPath, TryFilePathResult,
     },
     filesystem::{Discovery, FileSystem},
     hash_interner::{DisplayHash, HashMemorySlab},
     logger::NxKernelLogger,
     mount_save::Language,
+    state::{ModOrder, StratusState},
 };
 
 mod archive;
+mod config;
 mod filesystem;
 mod fixes;
 
@@ -48,12 +52,20 @@ mod menu;
 mod mount_save;
 mod nvn;
 mod packages;
+mod plugins;
+mod state;
 
 const SKIP_CACHE: bool = true;
 const STRATUS_FOLDER: &str = "sd:/ultimate/stratus/";
+const MODS_FOLDER: &str = "sd:/ultimate/mods/";
+
+/// The folder that stratus keeps its caches, state, and logs in, see [`StratusConfig`]
+fn stratus_folder() -> &'static Utf8Path {
+    StratusConfig::get().stratus_folder()
+}
 
 fn init_folder() {
-    let path = Utf8Path::new(STRATUS_FOLDER);
+    let path = stratus_folder();
     if path.exists() {
         if path.is_file() {
             panic!("stratus folder is a file and not a folder");
@@ -61,7 +73,7 @@ fn init_folder() {
         return;
     }
 
-    let _ = std::fs::create_dir_all(STRATUS_FOLDER);
+    let _ = std::fs::create_dir_all(path);
 
     assert!(
         path.exists(),
@@ -83,8 +95,9 @@ impl ReadOnlyFileSystem {
         }
         #[cfg(not(any(debug_assertions, feature = "sanity_checks")))]
         {
-            // SAFETY: This is one of the first things we init, in release mode let's just declare the cold
-            //  path impossible to reach
+            // SAFETY: `main` calls `init_hashes` before the `install_hooks!` that installs every hook
+            //  reading from the file system, and the menu thread only uses `try_file_system`. In release
+            //  mode let's just declare the cold path impossible to reach
             unsafe { &FILE_SYSTEM.get().unwrap_unchecked().hashes }
         }
     }
@@ -97,11 +110,17 @@ impl ReadOnlyFileSystem {
         }
         #[cfg(not(any(debug_assertions, feature = "sanity_checks")))]
         {
-            // SAFETY: This is one of the first things we init, in release mode let's just declare the cold
-            //  path impossible to reach
+            // SAFETY: `main` calls `init_hashes` before the `install_hooks!` that installs every hook
+            //  reading from the file system, and the menu thread only uses `try_file_system`. In release
+            //  mode let's just declare the cold path impossible to reach
             unsafe { &FILE_SYSTEM.get().unwrap_unchecked().file_system }
         }
     }
+
+    /// Fetches the file system if it has been initialized, for code that can run before discovery
+    fn try_file_system() -> Option<&'static FileSystem> {
+        FILE_SYSTEM.get().map(|fs| &fs.file_system)
+    }
 }
 
 unsafe impl Send for ReadOnlyFileSystem {}
@@ -131,62 +150,94 @@ fn init_hashes() {
             Missing,
         }
 
-        let blob_path: &'static Utf8Path = Utf8Path::new("sd:/ultimate/stratus/hashes.blob");
-        let meta_path: &'static Utf8Path = Utf8Path::new("sd:/ultimate/stratus/hashes.meta");
-        let hashes_src: &'static Utf8Path = Utf8Path::new("sd:/ultimate/stratus/Hashes_FullPath");
-        let cached_blob_path: &'static Utf8Path =
-            Utf8Path::new("sd:/ultimate/stratus/hashes_cached.blob");
-        let cached_meta_path: &'static Utf8Path =
-            Utf8Path::new("sd:/ultimate/stratus/hashes_cached.meta");
-        let cached_fs_blob_path: &'static Utf8Path =
-            Utf8Path::new("sd:/ultimate/stratus/fs_cached.blob");
+        let config = StratusConfig::get();
+        let blob_path = config.hash_blob_path();
+        let meta_path = config.hash_meta_path();
+        let hashes_src = stratus_folder().join("Hashes_FullPath");
+        let cached_blob_path = stratus_folder().join("hashes_cached.blob");
+        let cached_meta_path = stratus_folder().join("hashes_cached.meta");
+        let cached_fs_blob_path = stratus_folder().join("filesystem.cache");
 
         if cached_blob_path.exists()
             && cached_meta_path.exists()
             && cached_fs_blob_path.exists()
             && !SKIP_CACHE
         {
-            let slab = std::fs::read(cached_blob_path).unwrap();
-            let meta = std::fs::read(cached_meta_path).unwrap();
-            let fs_blob = std::fs::read(cached_fs_blob_path).unwrap();
-
-            return ReadOnlyFileSystem {
-                hashes: HashMemorySlab::from_blob(slab.into_boxed_slice(), meta.into_boxed_slice()),
-                file_system: FileSystem::from_bytes(fs_blob.into_boxed_slice()),
-            };
+            let slab = std::fs::read(&cached_blob_path).unwrap();
+            let meta = std::fs::read(&cached_meta_path).unwrap();
+
+            match HashMemorySlab::from_blob(slab.into_boxed_slice(), meta.into_boxed_slice()) {
+                Ok(hashes) => {
+                    let fs_blob = std::fs::read(&cached_fs_blob_path).unwrap();
+                    match FileSystem::from_bytes(fs_blob.into_boxed_slice()) {
+                        Ok(file_system) => {
+                            return ReadOnlyFileSystem {
+                                hashes,
+                                file_system,
+                            }
+                        }
+                        Err(e) => {
+                            log::warn!("[stratus::hashes] Discarding cached file system: {e}");
+                            let _ = std::fs::remove_file(&cached_fs_blob_path);
+                        }
+                    }
+                }
+                Err(e) => log::warn!("[stratus::hashes] Discarding cached hash blob: {e}"),
+            }
         }
 
         let now = Instant::now();
         let load_method: LoadMethod;
 
-        let mut slab = if blob_path.exists() && meta_path.exists() {
-            let blob = std::fs::read(blob_path).unwrap();
-            let meta = std::fs::read(meta_path).unwrap();
-            load_method = LoadMethod::Blob;
+        let blob_slab = if blob_path.exists() && meta_path.exists() {
+            let blob = std::fs::read(&blob_path).unwrap();
+            let meta = std::fs::read(&meta_path).unwrap();
 
             HashMemorySlab::from_blob(blob.into_boxed_slice(), meta.into_boxed_slice())
+                .inspect_err(|e| {
+                    log::warn!("[stratus::hashes] Regenerating hash blob, failed to load: {e}")
+                })
+                .ok()
+        } else {
+            None
+        };
+
+        let mut slab = if let Some(slab) = blob_slab {
+            load_method = LoadMethod::Blob;
+            slab
         } else {
             let mut slab = HashMemorySlab::new();
             let mut cache = slab.create_cache();
 
-            if let Ok(file) = std::fs::read_to_string(hashes_src) {
+            if let Ok(file) = std::fs::read_to_string(&hashes_src) {
+                let mut progress = |count: usize| {
+                    println!("[stratus::hashes] ... {count} paths interned");
+                };
                 for line in file.lines() {
                     let path = Utf8Path::new(line);
                     if let Some(extension) = path.extension() {
-                        slab.intern_path(&mut cache, Utf8Path::new(extension));
+                        let _ = slab.intern_path(&mut cache, Utf8Path::new(extension));
                     }
                     if let Some(file_name) = path.file_name() {
-                        slab.intern_path(&mut cache, Utf8Path::new(file_name));
+                        let _ = slab.intern_path(&mut cache, Utf8Path::new(file_name));
+                    }
+                    if let Err(e) = slab.intern_path_with_progress(
+                        &mut cache,
+                        Utf8Path::new(line),
+                        &mut progress,
+                    ) {
+                        log::warn!("[stratus::hashes] Failed to intern '{line}': {e}");
                     }
-                    slab.intern_path(&mut cache, Utf8Path::new(line));
                 }
 
+                println!("[stratus::hashes] {}", slab.bucket_usage());
+
                 slab.finalize(cache);
 
-                let blob = slab.dump_blob();
-                let meta = slab.dump_meta();
-                std::fs::write(blob_path, blob).unwrap();
-                std::fs::write(meta_path, meta).unwrap();
+                let mut file = std::fs::File::create(&blob_path).unwrap();
+                slab.dump_blob_into(&mut file).unwrap();
+                let mut file = std::fs::File::create(&meta_path).unwrap();
+                slab.dump_meta_into(&mut file).unwrap();
                 load_method = LoadMethod::HashFile;
             } else {
                 load_method = LoadMethod::Missing;
@@ -206,7 +257,17 @@ fn init_hashes() {
 
         let mut cache = slab.create_cache();
         let now = std::time::Instant::now();
-        let discovery = Discovery::new_in_root("sd:/ultimate/mods".as_ref(), &mut slab, &mut cache);
+        let discovery = {
+            let state = StratusState::load();
+            Discovery::new_in_root_with_progress(
+                MODS_FOLDER.as_ref(),
+                &mut slab,
+                &mut cache,
+                |name| StratusState::is_mod_enabled(state.as_ref(), name),
+                &ModOrder::load(),
+                &mut |count| println!("[stratus::hashes] ... {count} mod paths interned"),
+            )
+        };
         println!(
             "[stratus::hashes] Discovered mod files in {:.3}s",
             now.elapsed().as_secs_f32()
@@ -216,17 +277,54 @@ fn init_hashes() {
             use std::fmt::Write;
             c0x_buffer.clear();
             let _ = write!(&mut c0x_buffer, "c{id:02}");
-            slab.intern_path(&mut cache, Utf8Path::new(&c0x_buffer));
+            let _ = slab.intern_path(&mut cache, Utf8Path::new(&c0x_buffer));
+        }
+        for name in ["tonelabel", "nus3bank", "nus3audio"] {
+            if let Err(e) = slab.intern_path(&mut cache, Utf8Path::new(name)) {
+                log::warn!("[stratus::hashes] Failed to intern '{name}': {e}");
+            }
         }
-        slab.intern_path(&mut cache, Utf8Path::new("tonelabel"));
-        slab.intern_path(&mut cache, Utf8Path::new("nus3bank"));
-        slab.intern_path(&mut cache, Utf8Path::new("nus3audio"));
         slab.finalize(cache);
-
-        let file_system = FileSystem::from_bytes(discovery.as_slab());
-        std::fs::write(cached_blob_path, slab.dump_blob()).unwrap();
-        std::fs::write(cached_meta_path, slab.dump_meta()).unwrap();
-        std::fs::write(cached_fs_blob_path, file_system.raw()).unwrap();
+        println!("[stratus::hashes] {}", slab.report());
+
+        // If the mod set hasn't changed since the last boot, the cached file system is identical to
+        // what we would serialize here
+        let cached_fs_blob = std::fs::read(&cached_fs_blob_path)
+            .ok()
+            .filter(|blob| FileSystem::read_checksum(blob) == Some(discovery.checksum()));
+        let cached_file_system =
+            match cached_fs_blob.map(|blob| FileSystem::from_bytes(blob.into_boxed_slice())) {
+                Some(Ok(file_system)) => {
+                    println!("[stratus::hashes] Mod files are unchanged, using cached file system");
+                    Some(file_system)
+                }
+                Some(Err(e)) => {
+                    log::warn!("[stratus::hashes] Discarding cached file system: {e}");
+                    let _ = std::fs::remove_file(&cached_fs_blob_path);
+                    None
+                }
+                None => None,
+            };
+        let is_fs_cached = cached_file_system.is_some();
+        let file_system = cached_file_system
+            .unwrap_or_else(|| {
+                FileSystem::from_bytes(discovery.as_slab())
+                    .expect("Freshly serialized file system should be valid")
+            })
+            .with_root_priorities(discovery.root_priorities().to_vec())
+            .with_conflicts(discovery.into_conflicts());
+        println!(
+            "[stratus::hashes] Discovered {} mod files across {} roots",
+            file_system.file_count(),
+            file_system.root_count()
+        );
+        let mut file = std::fs::File::create(&cached_blob_path).unwrap();
+        slab.dump_blob_into(&mut file).unwrap();
+        let mut file = std::fs::File::create(&cached_meta_path).unwrap();
+        slab.dump_meta_into(&mut file).unwrap();
+        if !is_fs_cached {
+            std::fs::write(&cached_fs_blob_path, file_system.raw()).unwrap();
+        }
 
         ReadOnlyFileSystem {
             hashes: slab,
@@ -285,6 +383,11 @@ impl LocalePreferences {
             unsafe { LOCALE.get().unwrap_unchecked() }
         }
     }
+
+    /// Builds the preferences from the language in the console's system settings
+    pub fn detect_from_system() -> Self {
+        mount_save::get_locale_from_system()
+    }
 }
 
 static LOCALE: OnceLock<LocalePreferences> = OnceLock::new();
@@ -302,6 +405,13 @@ extern "C" {
     fn wait_event(ptr: u64);
 }
 
+/// A single file load should never need anywhere near this many IO swaps, if we hit this then the swap
+/// is never going to complete and we would rather crash with a message than hang the game
+const MAX_IO_SWAP_ITERATIONS: usize = 1000;
+
+/// Number of IO swaps after which we start warning that something might be wrong
+const WARN_IO_SWAP_ITERATIONS: usize = 100;
+
 fn handle_inflate_io_swaps(ctx: &mut InlineCtx) {
     let res_service = ctx.registers[19].x() as *mut u8;
     let offset_into_read = unsafe { *res_service.add(0x220).cast::<u64>() };
@@ -316,7 +426,25 @@ fn handle_inflate_io_swaps(ctx: &mut InlineCtx) {
         let mut threshold = offset_into_read - x20;
         let mut x22 = ctx.registers[24].x();
 
+        let mut iterations = 0;
         while threshold < x21 {
+            log::debug!(
+                "[handle_inflate_io_swaps] Swap {iterations}: threshold={threshold:#x} x20={x20:#x} x21={x21:#x}"
+            );
+
+            iterations += 1;
+            if iterations == WARN_IO_SWAP_ITERATIONS {
+                log::warn!(
+                    "[handle_inflate_io_swaps] IO swap has taken {iterations} iterations (threshold={threshold:#x} x20={x20:#x} x21={x21:#x})"
+                );
+            }
+
+            if iterations > MAX_IO_SWAP_ITERATIONS {
+                panic!(
+                    "IO swap did not complete after {MAX_IO_SWAP_ITERATIONS} iterations (threshold={threshold:#x} x20={x20:#x} x21={x21:#x})"
+                );
+            }
+
             unsafe {
                 release_semaphore(*(**res_service.add(0x30).cast::<*const *const u64>()).add(0x1));
                 release_semaphore(*(**res_service.add(0x28).cast::<*const *const u64>()).add(0x1));
@@ -348,11 +476,31 @@ fn handle_inflate_io_swaps(ctx: &mut InlineCtx) {
     }
 }
 
+thread_local! {
+    // The file that the current thread is replacing, so that the panic hook can tell the user
+    // which file (and by extension which mod) caused the crash
+    static CURRENT_LOADING_FILE: Cell<Option<Hash40>> = const { Cell::new(None) };
+}
+
+/// Marks a file as being loaded on this thread until it is dropped
+struct LoadingFileGuard;
+
+impl LoadingFileGuard {
+    fn new(path: Hash40) -> Self {
+        CURRENT_LOADING_FILE.with(|current| current.set(Some(path)));
+        Self
+    }
+}
+
+impl Drop for LoadingFileGuard {
+    fn drop(&mut self) {
+        CURRENT_LOADING_FILE.with(|current| current.set(None));
+    }
+}
+
 // 13.0.1 35442e8
-#[allow(static_mut_refs)]
 #[skyline::hook(offset = 0x3544D18, inline)]
 fn jemalloc_hook(ctx: &mut InlineCtx) {
-    static mut BUFFER: String = String::new();
     let res_service = ctx.registers[19].x() as *const u8;
     let current_index = ctx.registers[27].w();
     let absolute_index = unsafe { *res_service.add(0x230).cast::<u32>() } + current_index;
@@ -362,6 +510,14 @@ fn jemalloc_hook(ctx: &mut InlineCtx) {
         panic!("ResInflateThread handed invalid info index");
     };
 
+    let load_method = info.desc().load_method();
+    if load_method.is_unrecognized() {
+        log::warn!(
+            "[jemalloc_hook] File info {absolute_index:#x} has unrecognized load method kind {:#04x}",
+            load_method.raw_kind()
+        );
+    }
+
     let path = match info.try_file_path() {
         TryFilePathResult::FilePath(path) => {
             // log::info!(
@@ -393,6 +549,8 @@ fn jemalloc_hook(ctx: &mut InlineCtx) {
         TryFilePathResult::Missing => panic!("File info is not pointing to a real file path"),
     };
 
+    let _loading = LoadingFileGuard::new(path);
+
     if cfg!(feature = "verbose_logging") {
         let offset_into_read = unsafe { *res_service.add(0x220).cast::<u64>() };
         let x20 = ctx.registers[20].x();
@@ -410,12 +568,10 @@ fn jemalloc_hook(ctx: &mut InlineCtx) {
         );
     }
 
-    // SAFETY: This path is only going to be called from the ResInflateThread, so this being a "static" variable is effectively a TLS variable
-    if let Some(file) =
-        ReadOnlyFileSystem::file_system().lookup_file(path, *LocalePreferences::get())
+    if let Some((file, tier)) =
+        ReadOnlyFileSystem::file_system().lookup_file_with_fallback(path, *LocalePreferences::get())
     {
-        // SAFETY: See above
-        log::info!("[jemalloc_hook] Replacing {}", path.display());
+        log::info!("[jemalloc_hook] Replacing {} ({tier:?})", path.display());
 
         // We need to create the same alignment on our buffer the game is expecting.
         // This alignment is going to be 0x1000 (page alignment) for graphics archives (BNTX and NUTEXB)
@@ -432,16 +588,38 @@ fn jemalloc_hook(ctx: &mut InlineCtx) {
                     alignment as usize,
                 )
                 .as_ptr();
+            let size = ReadOnlyFileSystem::file_system().get_decompressed_size(file);
+            let buffer = unsafe { std::slice::from_raw_parts_mut(ptr, size as usize) };
+            plugins::transform_file(path, buffer);
+            plugins::notify_file_load(path, buffer);
         } else {
-            ptr = ReadOnlyFileSystem::file_system()
-                .read_file(
-                    path,
-                    file,
-                    unsafe { &mut BUFFER },
-                    false,
-                    alignment as usize,
-                )
-                .as_ptr();
+            // Read straight into the buffer the game would have allocated, instead of having the
+            // file system allocate one for us
+            let size = ReadOnlyFileSystem::file_system().get_decompressed_size(file);
+            ptr = unsafe { jemalloc(alignment, size as u64) };
+            let buffer = unsafe { std::slice::from_raw_parts_mut(ptr, size as usize) };
+            match plugins::query_file(path) {
+                Some(data) if data.len() == buffer.len() => buffer.copy_from_slice(&data),
+                plugin_data => {
+                    if let Some(data) = plugin_data {
+                        log::warn!(
+                            "[jemalloc_hook] Plugin provided {:#x} bytes for {} but {:#x} were expected, loading from SD instead",
+                            data.len(),
+                            path.display(),
+                            buffer.len()
+                        );
+                    }
+
+                    if let Err(e) =
+                        ReadOnlyFileSystem::file_system().read_file_into_slice(path, file, buffer)
+                    {
+                        panic!("Failed to read {}: {e}", path.display());
+                    }
+                }
+            }
+
+            plugins::transform_file(path, buffer);
+            plugins::notify_file_load(path, buffer);
 
             // We need to manually handle the IO swap mechanism here. The game will "correct" the IO swaps on the next file but either
             // I'm misunderstanding something (likely) or that codepath is actually bugged for what it's supposed to do. So instead
@@ -463,6 +641,35 @@ fn jemalloc_hook(ctx: &mut InlineCtx) {
     ctx.registers[0].set_x(ptr as u64);
 }
 
+/// Value of x3 after `skip_load_hook` when the file is a "large load" that the game has to handle
+const HOOK_FLAG_LARGE_LOAD: u64 = 0;
+/// Value of x3 after `skip_load_hook` when the file was not loaded by us and fits in the buffer
+const HOOK_FLAG_VANILLA: u64 = 1;
+/// Value of x3 after `skip_load_hook` when the file was loaded off of the SD card by us
+const HOOK_FLAG_SD_LOADED: u64 = 2;
+
+/// The state passed from `skip_load_hook` to `skip_load_hook_p2` through x3
+///
+/// x3 is unused by the game at this point, and both hooks are followed by a `cbnz x3` patched in by
+/// `patch_res_threads`, so the value is also what the game branches on. `skip_load_hook_p2`
+/// decrements it, which means [`Self::SD_LOADED`] is the only state that is still non-zero there.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct HookState(u64);
+
+impl HookState {
+    const LARGE_LOAD: Self = Self(HOOK_FLAG_LARGE_LOAD);
+    const VANILLA: Self = Self(HOOK_FLAG_VANILLA);
+    const SD_LOADED: Self = Self(HOOK_FLAG_SD_LOADED);
+
+    fn from_register(x: u64) -> Self {
+        Self(x)
+    }
+
+    fn to_register(self) -> u64 {
+        self.0
+    }
+}
+
 /* This hook replaces a conditional branch that originally checks if the file is a "large load" (meaning that the data is split across
  *  an IO swap boundary) or a "small" load (data is contained completely within the buffer available to this thread).
  *
@@ -485,13 +692,14 @@ fn jemalloc_hook(ctx: &mut InlineCtx) {
  */
 #[skyline::hook(offset = 0x3544D68, inline)]
 fn skip_load_hook(ctx: &mut InlineCtx) {
-    if DID_LOAD.swap(false, Ordering::Relaxed) {
-        ctx.registers[3].set_x(2);
+    let state = if DID_LOAD.swap(false, Ordering::Relaxed) {
+        HookState::SD_LOADED
     } else if ctx.registers[23].x() <= ctx.registers[8].x() {
-        ctx.registers[3].set_x(1);
+        HookState::VANILLA
     } else {
-        ctx.registers[3].set_x(0);
-    }
+        HookState::LARGE_LOAD
+    };
+    ctx.registers[3].set_x(state.to_register());
 }
 
 /* This hook is intended to skip a memcpy that we don't need to do
@@ -506,28 +714,30 @@ fn skip_load_hook(ctx: &mut InlineCtx) {
  *  an unused register (x3) in jemalloc_hook and update it in skip_load_hook that tells us if we need to fallback
  *  to the game's "large load" memcpy loop (for files who's bytes to be read are larger than the available buffer).
  *
- *  Coming into this hook, this value can be one of 2:
- *  - 1: This indicates that the file is *not* a "large load" and that we should do the normal memcpy
- *  - 2: This indicates that the file was loaded by us, and that we should break out of the switch-case loop, which we accomplish
- *      via the cbnz instruction replacement (see patch_res_threads)
+ *  Coming into this hook, this value can be one of 2 (see `HookState`):
+ *  - HOOK_FLAG_VANILLA: This indicates that the file is *not* a "large load" and that we should do the normal memcpy
+ *  - HOOK_FLAG_SD_LOADED: This indicates that the file was loaded by us, and that we should break out of the switch-case loop,
+ *      which we accomplish via the cbnz instruction replacement (see patch_res_threads)
  *
- *  Notably, even though skip_load_hook can set the register value to 0, the instruction which runs after skip_load_hook
+ *  Notably, even though skip_load_hook can set the register value to HOOK_FLAG_LARGE_LOAD, the instruction which runs after skip_load_hook
  *  will branch to other code (that manages the vanilla "large load") if the file was not loaded by us and is a large load
  */
 #[skyline::hook(offset = 0x3545188, inline)]
 fn skip_load_hook_p2(ctx: &mut InlineCtx) {
-    let x = ctx.registers[3].x();
-    ctx.registers[3].set_x(x - 1);
-    if x - 1 == 0 {
+    let state = HookState::from_register(ctx.registers[3].x());
+    // Decrementing leaves x3 non-zero only for files that we loaded, which is what the cbnz after this hook checks
+    ctx.registers[3].set_x(state.to_register() - 1);
+    if state == HookState::VANILLA {
         let mem_size = unsafe { *(ctx.sp.x() as *const u8).add(0x28).cast::<usize>() };
         ctx.registers[2].set_x(mem_size as u64);
     } else {
         // This assertion is a sanity check, it shouldn't trigger as long as jemalloc_hook is implemented correctly
-        // The expalanation is that we set only three values from skip_load_hook. We can only either see 0x1 or 0x2 coming into this function.
-        // We handle 0x1 in the above block, so this block must represent 0x2. If it is not that value then there is a bug.
+        // The expalanation is that we set only three values from skip_load_hook. We can only either see HOOK_FLAG_VANILLA or HOOK_FLAG_SD_LOADED
+        // coming into this function. We handle HOOK_FLAG_VANILLA in the above block, so this block must represent HOOK_FLAG_SD_LOADED. If it
+        // is not that value then there is a bug.
         #[cfg(any(debug_assertions, feature = "sanity_checks"))]
         {
-            assert_eq!(x - 1, 0x1);
+            assert_eq!(state, HookState::SD_LOADED);
         }
 
         // The replaced instruction simulates breaking from the switch-case block that this code is inside of. If you look at the decompilation in ghidra,
@@ -537,31 +747,38 @@ fn skip_load_hook_p2(ctx: &mut InlineCtx) {
     }
 }
 
-static mut LOADING_THREAD_PATCHED_POINTER: Option<*mut u8> = None;
+thread_local! {
+    // Set by ResLoadingThread when it loads a single file request itself, and consumed a little later on
+    // in the same thread when the pointer gets handed over to ResInflateThread
+    static LOADING_THREAD_PATCHED_POINTER: Cell<Option<*mut u8>> = const { Cell::new(None) };
+}
 
-#[allow(static_mut_refs)]
 #[skyline::hook(offset = 0x3543994, inline)]
 fn process_single_patched_file_request(ctx: &mut InlineCtx) {
-    static mut BUFFER: String = String::new();
-
-    // This address is to point right after the instruction we hook.
-    // This is a little sketchy because skyline technically can replace 5 instructions for a very long
-    // instead the single instruction that we are depending on. The idea here is that we replace the instruction
-    // we hook with br x3, and if we need to take the vanilla codepath we are going to jump to the instruction after this one.
-    // If we don't want to take the vanilla codepath, we are going to jump to two instructions after this one,
-    // and fake the return value
-    static mut OFFSET_ABSOLUTE_ADDRESS: u64 = 0x0;
-
-    // SAFETY: Referencing OFFSET_ABSOLUTE_ADDRESS is effectively a function local variable, it never gets referenced outside this thread
-    if unsafe { OFFSET_ABSOLUTE_ADDRESS } == 0 {
-        // SAFETY: See above, and our plugin cannot exist outside of the skyline runtime, so calling that function is safe
-        unsafe {
-            OFFSET_ABSOLUTE_ADDRESS = skyline::hooks::getRegionAddress(skyline::hooks::Region::Text)
-                .cast::<u8>()
-                .add(0x3543998) as u64;
-        }
+    thread_local! {
+        static BUFFER: RefCell<String> = const { RefCell::new(String::new()) };
+
+        // This address is to point right after the instruction we hook.
+        // This is a little sketchy because skyline technically can replace 5 instructions for a very long
+        // instead the single instruction that we are depending on. The idea here is that we replace the instruction
+        // we hook with br x3, and if we need to take the vanilla codepath we are going to jump to the instruction after this one.
+        // If we don't want to take the vanilla codepath, we are going to jump to two instructions after this one,
+        // and fake the return value
+        static OFFSET_ABSOLUTE_ADDRESS: Cell<u64> = const { Cell::new(0x0) };
     }
 
+    let offset_absolute_address = OFFSET_ABSOLUTE_ADDRESS.with(|address| {
+        if address.get() == 0 {
+            // SAFETY: Our plugin cannot exist outside of the skyline runtime, so calling that function is safe
+            address.set(unsafe {
+                skyline::hooks::getRegionAddress(skyline::hooks::Region::Text)
+                    .cast::<u8>()
+                    .add(0x3543998) as u64
+            });
+        }
+        address.get()
+    });
+
     let file_info_idx = ctx.registers[20].w();
     let archive = ReadOnlyArchive::get();
     let Some(info) = archive.get_file_info(file_info_idx) else {
@@ -569,6 +786,14 @@ fn process_single_patched_file_request(ctx: &mut InlineCtx) {
         panic!("Invalid file info index provided to ResLoadingThread");
     };
 
+    let load_method = info.desc().load_method();
+    if load_method.is_unrecognized() {
+        log::warn!(
+            "[process_single_patched_file_request] File info {file_info_idx:#x} has unrecognized load method kind {:#04x}",
+            load_method.raw_kind()
+        );
+    }
+
     let path = match info.try_file_path() {
         TryFilePathResult::FilePath(path) => {
             log::info!(
@@ -594,17 +819,15 @@ fn process_single_patched_file_request(ctx: &mut InlineCtx) {
             // See bottom of function for output variables/registers
             ctx.registers[2].set_x(ctx.registers[21].x());
 
-            // SAFETY: See above on static mut variables
-            ctx.registers[3].set_x(unsafe { OFFSET_ABSOLUTE_ADDRESS });
+            ctx.registers[3].set_x(offset_absolute_address);
             return;
         }
         TryFilePathResult::Missing => panic!("File info is missing file path"),
     };
 
     let path = path.path_and_entity.hash40();
+    let _loading = LoadingFileGuard::new(path);
 
-    // SAFETY: Referencing BUFFER here is safe since this is an inline hook only ever called from within
-    // ResLoadingThread. It effectively becomes a function local variable
     if let Some(file) =
         ReadOnlyFileSystem::file_system().lookup_file(path, *LocalePreferences::get())
     {
@@ -625,15 +848,24 @@ fn process_single_patched_file_request(ctx: &mut InlineCtx) {
         //      the lower 15 bits are used for buffer alignment
         let buffer_alignment = info.flags().bits() & 0x7FFF;
 
-        let ptr = ReadOnlyFileSystem::file_system().read_file(
-            path,
-            file,
-            unsafe { &mut BUFFER },
-            true,
-            buffer_alignment as usize,
-        );
+        if load_method.is_owned() && info.flags().contains(FileInfoFlags::IS_UNSHARED) {
+            debug_assert_eq!(
+                info.desc().data().compressed_size(),
+                0,
+                "Unshared FileData must have compressed_size=0"
+            );
+        }
+
+        let ptr = BUFFER.with(|buffer| {
+            ReadOnlyFileSystem::file_system().read_file(
+                path,
+                file,
+                &mut buffer.borrow_mut(),
+                true,
+                buffer_alignment as usize,
+            )
+        });
 
-        // SAFETY: See above
         // let mut file = std::fs::File::open(unsafe { &BUFFER }).unwrap();
         // let amount_read = file.read(slice).unwrap();
 
@@ -649,36 +881,27 @@ fn process_single_patched_file_request(ctx: &mut InlineCtx) {
         //    to the cached address
         ctx.registers[21].set_x(size as u64);
         ctx.registers[0].set_x(size as u64);
-        // SAFETY: See above on static mut variables
-        ctx.registers[3].set_x(unsafe { OFFSET_ABSOLUTE_ADDRESS + 4 });
+        ctx.registers[3].set_x(offset_absolute_address + 4);
 
-        // SAFETY: We need to track this pointer down a little bit when we hand it over to ResInflateThread. Storing it in a static mut is fine
-        // as long as we only reference it from code that runs inside of this loading thread.
-        unsafe {
-            assert!(LOADING_THREAD_PATCHED_POINTER
-                .replace(ptr.as_ptr())
-                .is_none())
-        };
+        // We need to track this pointer down a little bit when we hand it over to ResInflateThread
+        LOADING_THREAD_PATCHED_POINTER
+            .with(|pointer| assert!(pointer.replace(Some(ptr.as_ptr())).is_none()));
     } else {
         // OUT VARIABLES: This is the vanilla codepath, so we need to set the pointer to the instruction we want to jump to
         //  as well as simulate the instruction that we replaced (mov x2, x21)
 
         ctx.registers[2].set_x(ctx.registers[21].x());
 
-        // SAFETY: See above on static mut variables
-        ctx.registers[3].set_x(unsafe { OFFSET_ABSOLUTE_ADDRESS });
+        ctx.registers[3].set_x(offset_absolute_address);
     }
 }
 
 /* This replaces ResLoadingThread's assigment of res_service->data_ptr to the data we loaded above
  *  if we loaded it. This codepath will only fire for single file loads.
  */
-#[allow(static_mut_refs)]
 #[skyline::hook(offset = 0x35439F4, inline)]
 fn loading_thread_assign_patched_pointer(ctx: &mut InlineCtx) {
-    // SAFETY: We are only accessing this static mut inside of the ResLoadingThread, so it's not worth the overhead
-    // of a mutex lock here
-    if let Some(pointer) = unsafe { LOADING_THREAD_PATCHED_POINTER.take() } {
+    if let Some(pointer) = LOADING_THREAD_PATCHED_POINTER.with(Cell::take) {
         log::info!("[loading_thread_assigned_patch_pointer] Setting patched file pointer");
         ctx.registers[8].set_x(pointer as u64);
     }
@@ -690,28 +913,40 @@ fn loading_thread_assign_patched_pointer(ctx: &mut InlineCtx) {
  */
 #[skyline::hook(offset = 0x3751cac, inline)]
 fn skip_load_resource_tables(ctx: &mut InlineCtx) {
-    ctx.registers[0].set_x(ReadOnlyArchive::get().resource_data_ptr() as u64);
+    let archive = ReadOnlyArchive::get();
+    // The game's own load is NOP'd, so there is nothing to fall back to if our tables are broken
+    if let Err(e) = Archive::check_resource_blob(archive.resource_blob()) {
+        panic!("Patched resource tables are invalid: {e}");
+    }
+    ctx.registers[0].set_x(archive.resource_data_ptr() as u64);
 }
 
 #[skyline::hook(offset = 0x3751CC4, inline)]
 fn skip_load_search_tables(ctx: &mut InlineCtx) {
-    ctx.registers[0].set_x(ReadOnlyArchive::get().search_data_ptr() as u64);
+    let archive = ReadOnlyArchive::get();
+    if let Err(e) = Archive::check_search_blob(archive.search_blob()) {
+        panic!("Patched search tables are invalid: {e}");
+    }
+    ctx.registers[0].set_x(archive.search_data_ptr() as u64);
 }
 
+// The call to the decompressor is NOP'd when this hook is installed, so this hook is the only
+// place the decompression happens. Without the feature the game calls the decompressor itself
+#[cfg(feature = "verbose_decompression")]
 #[skyline::hook(offset = 0x3545234, inline)]
 fn observe_decompression(ctx: &mut InlineCtx) {
     let compressor = ctx.registers[0].x();
     let buffer_out = ctx.registers[1].x() as *mut ZstdBuffer;
     let buffer_in = ctx.registers[2].x() as *mut ZstdBuffer;
-    //
-    // let before = format!(
-    //     "[observe_decompression] Decompressing: IN: {:?}, OUT: {:?}",
-    //     unsafe { &*buffer_in },
-    //     unsafe { &*buffer_out }
-    // );
+
+    let before = format!(
+        "[observe_decompression] Decompressing: IN: {:?}, OUT: {:?}",
+        unsafe { &*buffer_in },
+        unsafe { &*buffer_out }
+    );
     let result = unsafe { decompress_stream(compressor as _, &mut *buffer_out, &mut *buffer_in) };
     ctx.registers[0].set_x(result as _);
-    // log::info!("{before}, RESULT: {:#x}", result);
+    log::info!("{before}, RESULT: {:#x}", result);
 }
 
 fn patch_res_threads() {
@@ -736,6 +971,7 @@ fn patch_res_threads() {
     Patch::in_text(0x3751CC4).nop().unwrap();
 
     // observe_decompression
+    #[cfg(feature = "verbose_decompression")]
     Patch::in_text(0x3545234).nop().unwrap();
 
     // patches samusd bunshin model path
@@ -803,18 +1039,67 @@ pub extern "C" fn arcrop_require_api_version(_major: u32, _minor: u32) {}
 #[global_allocator]
 static ALLOC: &stats_alloc::StatsAlloc<std::alloc::System> = &stats_alloc::INSTRUMENTED_SYSTEM;
 
+/// New files in `fighter/<fighter>/<folder>/<part>/<slot>` go into that costume slot's package
+fn fighter_new_file_package(components: &[&str]) -> Option<Hash40> {
+    let fighter_name = components.get(1)?;
+    let slot = components.get(4)?;
+    if !slot.starts_with('c') {
+        return None;
+    }
+
+    Some(
+        Hash40::const_new("fighter")
+            .const_with("/")
+            .const_with(fighter_name)
+            .const_with("/")
+            .const_with(slot),
+    )
+}
+
+/// Parses the fighter name and costume slot out of a path like
+/// `fighter/<fighter>/<folder>/<folder>/cXX/...`
+fn fighter_costume_slot<'a>(components: &[&'a str]) -> Option<(&'a str, u8)> {
+    let ["fighter", fighter_name, _, _, slot, ..] = components else {
+        return None;
+    };
+
+    let slot = slot.strip_prefix('c')?.parse::<u8>().ok()?;
+    Some((*fighter_name, slot))
+}
+
+/// New files go into the package named after the folder they are in
+fn parent_folder_new_file_package(components: &[&str]) -> Option<Hash40> {
+    let (_, folders) = components.split_last()?;
+    let mut parent = Hash40::const_new("");
+    for component in folders {
+        parent = parent.const_with(component).const_with("/");
+    }
+    Some(parent.const_trim_trailing("/"))
+}
+
+/// The key that the cached archive in packaged.bin/search.bin is stored with
+///
+/// The patched archive depends on which regional files were picked, so the locale preferences
+/// are stored next to the file system checksum and a change to either rebuilds the cache.
+fn archive_cache_key() -> [u8; 8] {
+    let preferences = LocalePreferences::get();
+    let mut key = [0u8; 8];
+    key[..4].copy_from_slice(&ReadOnlyFileSystem::file_system().checksum().to_le_bytes());
+    key[4] = preferences.region as u8;
+    key[5] = preferences.locale as u8;
+    key[6] = preferences.language as u8;
+    key
+}
+
 fn initial_loading_impl() {
     ARCHIVE.get_or_init(|| {
         let now = std::time::Instant::now();
 
-        let cache_crc_path = Utf8Path::new(STRATUS_FOLDER).join("fschecksum.bin");
+        let cache_crc_path = stratus_folder().join("fschecksum.bin");
         if cache_crc_path.exists() && !SKIP_CACHE {
-            let mut crc32 = [0u8; 4];
-            let mut file = std::fs::File::open(&cache_crc_path).unwrap();
-            file.read_exact(&mut crc32).unwrap();
-            if u32::from_le_bytes(crc32) == ReadOnlyFileSystem::file_system().checksum() {
-                let packaged_path = Utf8Path::new(STRATUS_FOLDER).join("packaged.bin");
-                let search_path = Utf8Path::new(STRATUS_FOLDER).join("search.bin");
+            if std::fs::read(&cache_crc_path).unwrap() == archive_cache_key() {
+                let packaged_path = stratus_folder().join("packaged.bin");
+                let search_path = stratus_folder().join("search.bin");
                 if packaged_path.exists() && search_path.exists() {
                     let packaged_len = std::fs::metadata(&packaged_path).unwrap().len() as usize;
                     let search_len = std::fs::metadata(&search_path).unwrap().len() as usize;
@@ -822,12 +1107,20 @@ fn initial_loading_impl() {
                         let ptr = std::alloc::alloc(
                             std::alloc::Layout::from_size_align(packaged_len, 0x10).unwrap(),
                         );
+                        assert!(
+                            !ptr.is_null(),
+                            "Failed to allocate {packaged_len} bytes for {packaged_path}"
+                        );
                         Box::from_raw(std::slice::from_raw_parts_mut(ptr, packaged_len))
                     };
                     let mut search_buf = unsafe {
                         let ptr = std::alloc::alloc(
                             std::alloc::Layout::from_size_align(search_len, 0x10).unwrap(),
                         );
+                        assert!(
+                            !ptr.is_null(),
+                            "Failed to allocate {search_len} bytes for {search_path}"
+                        );
                         Box::from_raw(std::slice::from_raw_parts_mut(ptr, search_len))
                     };
 
@@ -835,9 +1128,12 @@ fn initial_loading_impl() {
                     packaged.read_exact(&mut packaged_buf).unwrap();
                     let mut search = std::fs::File::open(&search_path).unwrap();
                     search.read_exact(&mut search_buf).unwrap();
-                    return ReadOnlyArchive(unsafe {
-                        archive::Archive::from_blobs(packaged_buf, search_buf)
-                    });
+                    match archive::Archive::try_from_blobs(packaged_buf, search_buf) {
+                        Ok(archive) => return ReadOnlyArchive(archive),
+                        Err(e) => {
+                            log::warn!("[stratus::patching] Discarding cached archive: {e}");
+                        }
+                    }
                 }
             }
         }
@@ -849,6 +1145,12 @@ fn initial_loading_impl() {
             now.elapsed().as_secs_f32()
         );
 
+        if let Err(errors) = archive.validate() {
+            for error in errors {
+                log::warn!("[stratus::patching] Archive validation failed: {error}");
+            }
+        }
+
         struct UnsharedFileInfo {
             real_infos: Vec<(u32, u32)>,
             group_offset: u32,
@@ -866,20 +1168,19 @@ fn initial_loading_impl() {
 
         let mut duplicated_fighter_packages: Hash40Map<Hash40Set> = Hash40Map::default();
         let mut new_packages_by_parent: Hash40Map<Hash40Set> = Hash40Map::default();
+        // Costume slots that have no package yet, sorted so that each new slot can mirror the
+        // new slots below it
+        let mut new_fighter_packages: Hash40Map<BTreeSet<u8>> = Hash40Map::default();
         let mut new_files_by_package: Hash40Map<Vec<(FilePath, u32)>> = Hash40Map::default();
 
         let mut component_buffer = [""; 16];
         let hashes = ReadOnlyFileSystem::hashes();
-        for (file, size) in
-            ReadOnlyFileSystem::file_system().iter_file_sizes(*LocalePreferences::get())
+        // Files that are already in the archive are handled by the unsharing loop below
+        for (file, size) in ReadOnlyFileSystem::file_system()
+            .iter_file_sizes_filtered(*LocalePreferences::get(), |file| {
+                archive.lookup_file_path(file).is_none()
+            })
         {
-            // Just means that the user didn't have a hashes file
-            if archive.lookup_file_path(file).is_some() {
-                continue;
-            }
-
-            let package;
-
             let component_count = hashes
                 .buffer_str_components_for(file, &mut component_buffer)
                 .unwrap();
@@ -899,42 +1200,23 @@ fn initial_loading_impl() {
             };
             let extension = Hash40::const_new(extension);
 
-            match component_buffer[0] {
-                "fighter" => {
-                    if component_count < 4 {
-                        continue;
-                    }
-
-                    let fighter_name = component_buffer[1];
-
-                    let slot = component_buffer[4];
-                    if slot.starts_with('c') {
-                        if !matches!(
-                            slot,
-                            "c00" | "c01" | "c02" | "c03" | "c04" | "c05" | "c06" | "c07"
-                        ) {
-                            duplicated_fighter_packages
-                                .entry(Hash40::const_new(fighter_name))
-                                .or_default()
-                                .insert(Hash40::const_new(slot));
-                        }
+            let components = &component_buffer[..component_count];
+            let Some(package) = plugins::new_file_package(components) else {
+                continue;
+            };
 
-                        package = Hash40::const_new("fighter")
-                            .const_with("/")
-                            .const_with(fighter_name)
-                            .const_with("/")
-                            .const_with(slot);
-                    } else {
-                        continue;
-                    }
-                }
-                "stage" => {
-                    package = parent.const_trim_trailing("/");
-                }
-                "ui" => {
-                    package = parent.const_trim_trailing("/");
+            if let ["fighter", fighter_name, _, _, slot, ..] = components {
+                if slot.starts_with('c')
+                    && !matches!(
+                        *slot,
+                        "c00" | "c01" | "c02" | "c03" | "c04" | "c05" | "c06" | "c07"
+                    )
+                {
+                    duplicated_fighter_packages
+                        .entry(Hash40::const_new(fighter_name))
+                        .or_default()
+                        .insert(Hash40::const_new(slot));
                 }
-                _ => continue,
             }
 
             if archive.lookup_file_package(package).is_none() {
@@ -953,6 +1235,13 @@ fn initial_loading_impl() {
                         .entry(parent_hash)
                         .or_default()
                         .insert(Hash40::const_new(component_buffer[4]));
+                } else if let Some((fighter_name, slot)) = fighter_costume_slot(components) {
+                    // Slots that can be duplicated from c00 get their package in the duplication
+                    // pass below, the rest are created empty before the new files are added
+                    new_fighter_packages
+                        .entry(Hash40::const_new(fighter_name))
+                        .or_default()
+                        .insert(slot);
                 } else {
                     continue;
                 }
@@ -971,6 +1260,15 @@ fn initial_loading_impl() {
                 continue;
             }
             for new_costume in new_costumes {
+                let base_costume = Hash40::const_new("fighter/")
+                    .const_with_hash(fighter_name)
+                    .const_with("/c00");
+                if archive.lookup_file_package(base_costume).is_none() {
+                    // There is no costume to duplicate from, so an empty package gets created
+                    // for the new files instead, see `new_fighter_packages`
+                    continue;
+                }
+
                 packages::duplicate_fighter_costume_package(
                     &mut archive,
                     fighter_name,
@@ -1132,11 +1430,19 @@ fn initial_loading_impl() {
             "c15".into_hash(),
         );
 
+        // Files in data groups get renamed below, which changes the same infos that the
+        // unsharing checks read, so this pass only collects them
+        let mut managed_groups = HashSet::new();
+        let mut files_to_rename = vec![];
         for package_idx in 0..archive.num_file_package() {
             let package = archive.get_file_package(package_idx as u32).unwrap();
-            let infos = package.infos();
-            for idx in 0..infos.len() {
-                let info = infos.get_local(idx).unwrap();
+            if let Some(group) = package.resolve_group() {
+                if managed_groups.insert(group.index()) {
+                    files_to_rename.extend(group.file_info_slice().range());
+                }
+            }
+
+            for info in package.infos() {
                 let shared_info = info.entity().info();
 
                 if info.index() != shared_info.index() {
@@ -1189,47 +1495,32 @@ fn initial_loading_impl() {
         );
 
         let mut renamed = HashMap::new();
-        let mut managed_groups = HashSet::new();
-        for package_idx in 0..archive.num_file_package() as u32 {
-            let package = archive.get_file_package(package_idx).unwrap();
-            if !package.has_file_group() {
-                continue;
-            }
+        for info_idx in files_to_rename {
+            let mut info = archive.get_file_info_mut(info_idx).unwrap();
+            let path_idx = info.path_ref().index();
+            let new_fp_idx = if let Some(new_idx) = renamed.get(&path_idx) {
+                *new_idx
+            } else {
+                let file_path = *info.path_ref();
+
+                let (new_idx, _) =
+                    info.archive_mut()
+                        .insert_or_get_file_path(FilePath::from_parts(
+                            file_path.path().const_with(".reshared"),
+                            file_path.parent(),
+                            file_path.file_name(),
+                            file_path.extension(),
+                            file_path.path_and_entity.data(),
+                        ));
 
-            let Some(group) = package.file_group() else {
-                continue;
+                renamed.insert(path_idx, new_idx);
+                new_idx
             };
 
-            if !managed_groups.insert(group.index()) {
-                continue;
-            }
-
-            let info_range = group.file_info_slice().range();
-            for info_idx in info_range {
-                let mut info = archive.get_file_info_mut(info_idx).unwrap();
-                let path_idx = info.path_ref().index();
-                let new_fp_idx = if let Some(new_idx) = renamed.get(&path_idx) {
-                    *new_idx
-                } else {
-                    let file_path = *info.path_ref();
-
-                    let new_idx = info.archive_mut().insert_file_path(FilePath::from_parts(
-                        file_path.path().const_with(".reshared"),
-                        file_path.parent(),
-                        file_path.file_name(),
-                        file_path.extension(),
-                        file_path.path_and_entity.data(),
-                    ));
-
-                    renamed.insert(path_idx, new_idx);
-                    new_idx
-                };
-
-                info.set_path(new_fp_idx);
-                info.set_as_reshared();
-                info.desc()
-                    .set_load_method(FileLoadMethod::PackageSkip(info_idx));
-            }
+            info.set_path(new_fp_idx);
+            info.set_as_reshared();
+            info.desc()
+                .set_load_method(FileLoadMethod::PackageSkip(info_idx));
         }
 
         let now = std::time::Instant::now();
@@ -1268,10 +1559,8 @@ fn initial_loading_impl() {
                         );
                     }
 
-                    let new_data_idx = archive.push_file_data(FileData::new_for_unsharing(
-                        size,
-                        unshare_info.group_offset,
-                    ));
+                    let new_data_idx = archive
+                        .push_file_data(FileData::new_sd_backed(size, unshare_info.group_offset));
 
                     let data_group_idx = archive
                         .get_file_package(unshare_info.package_index)
@@ -1295,7 +1584,7 @@ fn initial_loading_impl() {
                     // will skip loading that data group if this is the case. Instead, we politely tell it that there
                     // is actually data to read. This allows the streaming decompressor to work on our files.
                     if group.compressed_size() == 0 {
-                        group.set_compressed_size(0x10);
+                        group.set_compressed_size(FileGroup::DEFAULT_NEW_PACKAGE_SIZE);
                     }
 
                     let mut first_info = archive
@@ -1304,10 +1593,10 @@ fn initial_loading_impl() {
                     let mut flags = first_info.flags();
                     flags.set(FileInfoFlags::IS_SHARED, false);
                     flags.set(FileInfoFlags::IS_UNKNOWN_FLAG, false);
+                    flags.set(FileInfoFlags::IS_UNSHARED, true);
                     first_info.set_flags(flags);
-                    first_info.set_non_localized();
                     first_info.set_entity(new_entity_idx);
-                    first_info.set_desc(new_desc_idx);
+                    first_info.force_set_desc_and_clear_locale_flags(new_desc_idx);
                     first_info.path_mut().set_entity(new_entity_idx);
 
                     for (_, info) in unshare_info.real_infos.iter().skip(1) {
@@ -1350,8 +1639,7 @@ fn initial_loading_impl() {
                         let new_data_idx = archive.push_file_data(data);
                         desc.set_data(new_data_idx);
                         let new_desc_idx = archive.push_file_desc(desc);
-                        info.set_non_localized();
-                        info.set_desc(new_desc_idx);
+                        info.force_set_desc_and_clear_locale_flags(new_desc_idx);
                         info.set_entity(archive.num_file_entity() as u32);
                         info.set_as_reshared();
                         let new_info_idx = archive.push_file_info(info);
@@ -1449,42 +1737,30 @@ fn initial_loading_impl() {
             now.elapsed().as_secs_f32()
         );
 
-        for (package_parent, package_names) in new_packages_by_parent {
-            let Some(parent) = archive.lookup_file_package(package_parent) else {
+        for (fighter_name, slots) in new_fighter_packages {
+            if fighter_name == Hash40::const_new("common") {
                 continue;
-            };
-
-            let child_package_range = parent.child_packages().range();
-
-            let new_range_start = archive.num_file_package_child() as u32;
-            let new_range_len = (child_package_range.end - child_package_range.start)
-                + (package_names.len() as u32);
-
-            for child_package_idx in child_package_range {
-                let child_package = *archive.get_file_package_child(child_package_idx).unwrap();
-                archive.push_file_package_child(child_package);
             }
 
-            for package_name in package_names {
-                let new_group = archive.push_file_group(FileGroup::new_for_new_package());
-
-                let path = package_parent.const_with("/").const_with_hash(package_name);
-                let package = FilePackage::new(path, package_name, package_parent, new_group);
+            let slots = slots.into_iter().collect::<Vec<_>>();
+            for new_index in packages::create_fighter_packages(&mut archive, fighter_name, &slots) {
+                println!(
+                    "Adding file package {new_index:#x} to fighter/{}",
+                    fighter_name.display()
+                );
+            }
+        }
 
-                let new_index = archive.insert_file_package(package);
-                let child = FilePackageChild::new(path, new_index);
+        for (package_parent, package_names) in new_packages_by_parent {
+            let package_names = package_names.into_iter().collect::<Vec<_>>();
+            for new_index in
+                packages::create_stage_packages(&mut archive, package_parent, &package_names)
+            {
                 println!(
-                    "Adding file package child: {:?} to {}",
-                    child,
+                    "Adding file package {new_index:#x} to {}",
                     package_parent.display()
                 );
-                archive.push_file_package_child(child);
             }
-
-            archive
-                .lookup_file_package_mut(package_parent)
-                .unwrap()
-                .set_child_package_range(new_range_start, new_range_len);
         }
 
         let now = std::time::Instant::now();
@@ -1499,7 +1775,7 @@ fn initial_loading_impl() {
 
             let should_log = package_hash == Hash40::const_new("fighter/samus/c00");
 
-            let file_info_range = package.infos().range();
+            let file_info_range = package.info_range();
 
             if should_log {
                 println!(
@@ -1553,12 +1829,7 @@ fn initial_loading_impl() {
                         parent.set_folder_count(count + 1);
 
                         if parent.has_first_child() {
-                            let mut child = parent.first_child();
-                            while !child.is_end() {
-                                child = child.next();
-                            }
-
-                            child.set_next_index(new_index);
+                            parent.first_child().into_last().set_next_index(new_index);
                         } else {
                             parent.set_first_child_index(new_index);
                         }
@@ -1583,12 +1854,7 @@ fn initial_loading_impl() {
                 let file_count = parent.file_count();
                 parent.set_file_count(file_count + 1);
                 if parent.has_first_child() {
-                    let mut child = parent.first_child();
-                    while !child.is_end() {
-                        child = child.next();
-                    }
-
-                    child.set_next_index(new_index);
+                    parent.first_child().into_last().set_next_index(new_index);
                 } else {
                     parent.set_first_child_index(new_index);
                 }
@@ -1598,7 +1864,7 @@ fn initial_loading_impl() {
                 let mut filepath = file;
                 filepath.path_and_entity.set_data(new_entity_idx);
                 let new_file_path = archive.insert_file_path(filepath);
-                let new_data = archive.push_file_data(FileData::new_for_unsharing(size, 0));
+                let new_data = archive.push_file_data(FileData::new_owned_from_sd(size));
                 let new_desc = archive.push_file_desc(FileDescriptor::new(
                     data_group,
                     new_data,
@@ -1608,7 +1874,7 @@ fn initial_loading_impl() {
                     new_file_path,
                     new_entity_idx,
                     new_desc,
-                    FileInfoFlags::IS_GRAPHICS_ARCHIVE,
+                    FileInfoFlags::IS_GRAPHICS_ARCHIVE | FileInfoFlags::IS_CUSTOM_SLOT,
                 ));
                 archive
                     .get_file_entity_mut(new_entity_idx)
@@ -1627,36 +1893,230 @@ fn initial_loading_impl() {
             now.elapsed().as_secs_f32()
         );
 
-        let now = std::time::Instant::now();
-        archive.reserialize();
-        println!(
-            "[stratus::patching] Rebuilt archive tables in {:.3}s",
-            now.elapsed().as_secs_f32()
-        );
+        // Taken before reserializing, which folds the dynamic entries into the fixed region
+        let stats = archive.stats();
 
+        if archive.is_dirty() {
+            let now = std::time::Instant::now();
+            let budget = archive.default_reserialize_budget();
+            if let Err(e) = archive.reserialize_with_budget(budget) {
+                log::error!("[stratus::patching] {e}, reserializing without a budget");
+                archive.reserialize();
+            }
+            println!(
+                "[stratus::patching] Rebuilt archive tables in {:.3}s",
+                now.elapsed().as_secs_f32()
+            );
+        } else {
+            println!("[stratus::patching] Archive is unmodified, skipping rebuild");
+        }
+        println!("[stratus::patching] {stats}");
+
+        if let Err(errors) = archive.validate() {
+            for error in errors {
+                log::warn!("[stratus::patching] Patched archive validation failed: {error}");
+            }
+        }
+
+        std::fs::write(stratus_folder().join("fschecksum.bin"), archive_cache_key()).unwrap();
         std::fs::write(
-            Utf8Path::new(STRATUS_FOLDER).join("fschecksum.bin"),
-            ReadOnlyFileSystem::file_system().checksum().to_le_bytes(),
-        )
-        .unwrap();
-        std::fs::write(
-            Utf8Path::new(STRATUS_FOLDER).join("packaged.bin"),
+            stratus_folder().join("packaged.bin"),
             archive.resource_blob(),
         )
         .unwrap();
-        std::fs::write(
-            Utf8Path::new(STRATUS_FOLDER).join("search.bin"),
-            archive.search_blob(),
-        )
-        .unwrap();
+        std::fs::write(stratus_folder().join("search.bin"), archive.search_blob()).unwrap();
 
         ReadOnlyArchive(archive)
     });
 }
 
+/// Runs every validation pass once the archive is loaded and writes the results to the SD card,
+/// so that release builds can catch corruption without paying for the per-call checks
+#[cfg(feature = "sanity_checks_on_startup")]
+fn write_startup_report() {
+    use std::fmt::Write;
+
+    let now = std::time::Instant::now();
+    let mut report = String::new();
+
+    let hashes = ReadOnlyFileSystem::hashes();
+    let _ = writeln!(
+        report,
+        "[hashes]\n{}\n{}",
+        hashes.report(),
+        hashes.bucket_usage()
+    );
+    match hashes.validate() {
+        Ok(()) => {
+            let _ = writeln!(report, "No hash slab errors");
+        }
+        Err(errors) => {
+            for error in errors {
+                let _ = writeln!(report, "error: {error}");
+            }
+        }
+    }
+
+    let archive = ReadOnlyArchive::get();
+    let _ = writeln!(
+        report,
+        "\n[archive]\nFile path lookup load factor: {:.2}",
+        archive.file_path_load_factor()
+    );
+    match archive.validate() {
+        Ok(()) => {
+            let _ = writeln!(report, "No archive errors");
+        }
+        Err(errors) => {
+            for error in errors {
+                let _ = writeln!(report, "error: {error}");
+            }
+        }
+    }
+
+    let path = stratus_folder().join("startup_report.txt");
+    if let Err(e) = std::fs::write(&path, report) {
+        log::warn!("[stratus::patching] Failed to write {path}: {e}");
+    }
+
+    println!(
+        "[stratus::patching] Ran startup sanity checks in {:.3}s",
+        now.elapsed().as_secs_f32()
+    );
+}
+
+/// How long each step of booting took, written to the diagnostic dump
+static BOOT_TIMINGS: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(vec![]);
+
+fn record_boot_timing(step: &'static str, duration: Duration) {
+    BOOT_TIMINGS.lock().unwrap().push((step, duration));
+}
+
+/// Checks if ZL and ZR are both held on any controller, which requests a diagnostic dump instead of
+/// booting the game
+fn is_diagnostic_mode_requested() -> bool {
+    let mut controllers = [
+        ninput::Controller::new(0x20),
+        ninput::Controller::new(0x10),
+        ninput::Controller::new(0),
+        ninput::Controller::new(1),
+        ninput::Controller::new(2),
+        ninput::Controller::new(3),
+        ninput::Controller::new(4),
+        ninput::Controller::new(5),
+        ninput::Controller::new(6),
+        ninput::Controller::new(7),
+    ];
+
+    controllers.iter_mut().any(|controller| {
+        controller.update();
+        controller
+            .buttons
+            .contains(ninput::Buttons::ZL | ninput::Buttons::ZR)
+    })
+}
+
+/// Writes everything that is useful when looking into a bug report to the SD card
+fn write_diagnostic_dump() -> std::io::Result<Utf8PathBuf> {
+    use std::fmt::Write;
+
+    let mut report = String::new();
+    let _ = writeln!(report, "stratus {}", env!("CARGO_PKG_VERSION"));
+
+    let _ = writeln!(report, "\n[boot timings]");
+    for (step, duration) in BOOT_TIMINGS.lock().unwrap().iter() {
+        let _ = writeln!(report, "{step}: {:.3}s", duration.as_secs_f32());
+    }
+
+    let hashes = ReadOnlyFileSystem::hashes();
+    let _ = writeln!(
+        report,
+        "\n[hashes]\n{}\n{}",
+        hashes.report(),
+        hashes.bucket_usage()
+    );
+
+    let archive = ReadOnlyArchive::get();
+    let _ = writeln!(report, "\n[archive tables]");
+    for (name, count) in [
+        ("file_path", archive.num_file_path()),
+        ("file_entity", archive.num_file_entity()),
+        ("file_info", archive.num_file_info()),
+        ("file_desc", archive.num_file_desc()),
+        ("file_data", archive.num_file_data()),
+        ("file_package", archive.num_file_package()),
+        ("file_package_child", archive.num_file_package_child()),
+        ("file_group", archive.num_file_group()),
+        ("stream_folder", archive.num_stream_folder()),
+        ("stream_path", archive.num_stream_path()),
+        ("stream_entity", archive.num_stream_entity()),
+        ("stream_data", archive.num_stream_data()),
+    ] {
+        let _ = writeln!(report, "{name}: {count}");
+    }
+
+    let file_system = ReadOnlyFileSystem::file_system();
+    let _ = writeln!(
+        report,
+        "\n[patched files] ({} across {} mods)",
+        file_system.file_count(),
+        file_system.root_count()
+    );
+    for path in file_system.iter_file_paths(*LocalePreferences::get()) {
+        let _ = writeln!(report, "{}", path.display());
+    }
+
+    let _ = writeln!(report, "\n[conflicts]");
+    if file_system.conflicts().is_empty() {
+        let _ = writeln!(
+            report,
+            "No conflicts, or the file system was loaded from the cache"
+        );
+    }
+    for conflict in file_system.conflicts() {
+        let _ = writeln!(
+            report,
+            "{} from {} overrides {:?}",
+            conflict.path.display(),
+            conflict.winning_mod,
+            conflict.losing_mods
+        );
+    }
+
+    let _ = writeln!(report, "\n[archive]");
+    report.push_str(&archive.text_report());
+
+    let path = stratus_folder().join("diagnostic_dump.txt");
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
+
 #[skyline::hook(offset = 0x3751c0c, inline)]
 fn initial_loading(_ctx: &InlineCtx) {
+    debug_assert!(
+        ReadOnlyFileSystem::try_file_system().is_some(),
+        "initial_loading ran before init_hashes"
+    );
+    let now = std::time::Instant::now();
     initial_loading_impl();
+    record_boot_timing("initial_loading", now.elapsed());
+
+    #[cfg(feature = "sanity_checks_on_startup")]
+    write_startup_report();
+
+    if is_diagnostic_mode_requested() {
+        println!("[stratus::patching] ZL+ZR held, writing diagnostic dump");
+        let message = match write_diagnostic_dump() {
+            Ok(path) => format!("The diagnostic dump was written to {path}\0"),
+            Err(e) => format!("Failed to write the diagnostic dump: {e}\0"),
+        };
+        skyline::error::show_error(
+            70,
+            "Stratus diagnostic mode, the game will close after this message.\0",
+            message.as_str(),
+        );
+        unsafe { skyline::nn::oe::ExitApplication() };
+    }
 }
 
 #[skyline::hook(offset = 0x3543bb8, inline)]
@@ -1747,14 +2207,25 @@ pub fn main() {
             },
         };
 
-        let err_msg = format!("smashline has panicked: '{}', {}\0", msg, location);
+        let err_msg = match CURRENT_LOADING_FILE.with(Cell::get) {
+            Some(path) => format!(
+                "stratus has panicked while loading {}: '{}', {}\0",
+                path.display(),
+                msg,
+                location
+            ),
+            None => format!("stratus has panicked: '{}', {}\0", msg, location),
+        };
         skyline::error::show_error(
             69,
-            "Smashline has panicked! Please open Details and post an issue at https://github.com/HDR-Development/smashline.\0",
+            "Stratus has panicked! Please open Details and post an issue at https://github.com/blu-dev/stratus.\0",
             err_msg.as_str(),
         );
     }));
 
+    // Loaded before anything else, since it changes where everything else reads and writes
+    let config = StratusConfig::get();
+
     ninput::init();
     menu::init_menu();
     // logger::install_hooks();
@@ -1771,22 +2242,42 @@ pub fn main() {
 
     unsafe {
         set_overclock_enabled(true);
-        // set_cpu_boost_mode(1);
+        if config.cpu_boost_on_init {
+            set_cpu_boost_mode(1);
+        }
     }
 
     init_folder();
+
+    // Installed before the hashes so that everything from startup ends up in the log file
+    let mut logger = NxKernelLogger::new().with_max_level(config.log_level);
+    if config.enable_verbose_file_logging {
+        logger = logger.with_sd_file_sink(&stratus_folder().join("stratus.log"));
+    }
+    let _ = log::set_logger(Box::leak(Box::new(logger)));
+    unsafe { log::set_max_level_racy(config.log_level) };
+
+    plugins::register_new_file_root_handler("fighter", fighter_new_file_package);
+    plugins::register_new_file_root_handler("stage", parent_folder_new_file_package);
+    plugins::register_new_file_root_handler("ui", parent_folder_new_file_package);
+
+    let locale = LOCALE.get_or_init(LocalePreferences::detect_from_system);
+    println!(
+        "[stratus::locale] Using {:?} ({:?})",
+        locale.locale, locale.region
+    );
+
+    let now = std::time::Instant::now();
     init_hashes();
+    record_boot_timing("init_hashes", now.elapsed());
     patch_res_threads();
     kirby_copy::install();
 
-    LOCALE.get_or_init(mount_save::get_locale_from_user_save);
-
-    let _ = log::set_logger(Box::leak(Box::new(NxKernelLogger::new())));
-    unsafe { log::set_max_level_racy(LevelFilter::Info) };
-
-    // unsafe {
-    //     set_cpu_boost_mode(0);
-    // }
+    if config.cpu_boost_on_init {
+        unsafe {
+            set_cpu_boost_mode(0);
+        }
+    }
 
     skyline::install_hooks!(
         skip_load_resource_tables,
@@ -1795,7 +2286,6 @@ pub fn main() {
         jemalloc_hook,
         skip_load_hook,
         skip_load_hook_p2,
-        observe_decompression,
         observe_res_service_inflate,
         process_single_patched_file_request,
         loading_thread_assign_patched_pointer,
@@ -1803,4 +2293,7 @@ pub fn main() {
         observe_load_package,
         set_samusd_bunshin_string,
     );
+
+    #[cfg(feature = "verbose_decompression")]
+    skyline::install_hook!(observe_decompression);
 }
@@ -1,6 +1,10 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fs::File, io::Write, sync::Mutex};
 
-use log::Level;
+use camino::Utf8Path;
+use log::{Level, LevelFilter};
+
+/// Log files larger than this get rotated out when the logger is created
+const MAX_LOG_FILE_SIZE: u64 = 4 * 1024 * 1024;
 
 unsafe extern "C" {
     #[link_name = "_ZN2nn4diag6detail16PrintDebugStringEPKcm"]
@@ -24,13 +28,46 @@ pub fn install_hooks() {
 
 pub struct NxKernelLogger {
     by_module: HashMap<&'static str, Level>,
+    max_level: LevelFilter,
+    // Not buffered, since the lines right before a crash are the ones that matter the most
+    file: Option<Mutex<File>>,
 }
 
 impl NxKernelLogger {
     pub fn new() -> Self {
         Self {
             by_module: HashMap::new(),
+            max_level: LevelFilter::Info,
+            file: None,
+        }
+    }
+
+    /// Also writes every log line to the file at `path`
+    ///
+    /// If the existing file is over 4 MB it is moved to `<path>.1` and a fresh one is started
+    pub fn with_sd_file_sink(mut self, path: &Utf8Path) -> Self {
+        if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() > MAX_LOG_FILE_SIZE) {
+            let rotated = format!("{path}.1");
+            let _ = std::fs::remove_file(&rotated);
+            let _ = std::fs::rename(path, &rotated);
         }
+
+        match File::options().create(true).append(true).open(path) {
+            Ok(file) => self.file = Some(Mutex::new(file)),
+            Err(e) => {
+                let message = format!("[WARN ]  Failed to open log file {path}: {e}");
+                unsafe { print_debug_string(message.as_ptr().cast(), message.len()) };
+            }
+        }
+
+        self
+    }
+
+    /// The level that is logged for modules without their own filter, defaults to
+    /// [`LevelFilter::Info`]
+    pub fn with_max_level(mut self, level: LevelFilter) -> Self {
+        self.max_level = level;
+        self
     }
 
     #[allow(dead_code)]
@@ -54,12 +91,30 @@ impl log::Log for NxKernelLogger {
                         .get(module.trim_start_matches(concat!(env!("CARGO_CRATE_NAME"), "::")))
                 })
                 .map(|filter| record.level() <= *filter)
-                .unwrap_or_else(|| record.level() <= Level::Info)
+                .unwrap_or_else(|| record.level() <= self.max_level)
         {
             let message = format!("[{: <5}]  {}", record.level(), record.args());
             unsafe { print_debug_string(message.as_ptr().cast(), message.len()) };
+
+            if let Some(file) = self.file.as_ref() {
+                if let Ok(mut file) = file.lock() {
+                    let _ = writeln!(
+                        file,
+                        "[{}] [{}] {}",
+                        record.level(),
+                        record.module_path().unwrap_or("unknown"),
+                        record.args()
+                    );
+                }
+            }
         }
     }
 
-    fn flush(&self) {}
+    fn flush(&self) {
+        if let Some(file) = self.file.as_ref() {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
 }
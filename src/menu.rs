@@ -1,15 +1,26 @@
-use std::{alloc::Layout, cell::RefCell, collections::VecDeque, rc::Rc};
+use std::{
+    alloc::Layout,
+    cell::{Ref, RefCell},
+    collections::{HashSet, VecDeque},
+    rc::Rc,
+};
 
-use ::envy::{LayoutTree, NodeDisjointAccessor, NodeUpdateCallback, NodeVisibility, SublayoutNode};
+use ::envy::{
+    LayoutTree, NodeDisjointAccessor, NodeUpdateCallback, NodeVisibility, SublayoutNode, TextNode,
+};
+use camino::Utf8Path;
 use ninput::Buttons;
 
 use crate::{
+    filesystem::{self, ModMetadata, TAR_GZ_EXTENSION},
     menu::envy::NvnBackend,
     nvn::{
         self,
         abstraction::{ManagedCommandBuffer, ManagedMemoryPool, SwapChain},
         align_up, DisplayHandle, LayerHandle, WindowHandle, PAGE_ALIGNMENT,
     },
+    state::{ModOrder, StratusState},
+    ReadOnlyFileSystem, MODS_FOLDER,
 };
 
 mod envy;
@@ -188,7 +199,52 @@ impl NodeUpdateCallback<NvnBackend> for MainMenuButton {
     }
 }
 
-struct VirtualController(Box<[ninput::Controller]>);
+/// How far a stick needs to be tilted before it counts as a direction press
+const STICK_DEADZONE: f32 = 0.35;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+struct StickDirections {
+    up: bool,
+    down: bool,
+    left: bool,
+    right: bool,
+}
+
+impl StickDirections {
+    fn from_axes(x: f32, y: f32) -> Self {
+        Self {
+            up: y > STICK_DEADZONE,
+            down: y < -STICK_DEADZONE,
+            left: x < -STICK_DEADZONE,
+            right: x > STICK_DEADZONE,
+        }
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            up: self.up || other.up,
+            down: self.down || other.down,
+            left: self.left || other.left,
+            right: self.right || other.right,
+        }
+    }
+
+    // Sticks report a held state, so only count directions that weren't held last frame as pressed
+    fn newly_pressed(self, previous: Self) -> Self {
+        Self {
+            up: self.up && !previous.up,
+            down: self.down && !previous.down,
+            left: self.left && !previous.left,
+            right: self.right && !previous.right,
+        }
+    }
+}
+
+struct VirtualController {
+    controllers: Box<[ninput::Controller]>,
+    sticks_held: StickDirections,
+    sticks_pressed: StickDirections,
+}
 
 impl VirtualController {
     fn new() -> Self {
@@ -205,69 +261,106 @@ impl VirtualController {
             ninput::Controller::new(7),
         ];
 
-        Self(Box::new(controllers))
+        Self {
+            controllers: Box::new(controllers),
+            sticks_held: StickDirections::default(),
+            sticks_pressed: StickDirections::default(),
+        }
     }
 
     fn update(&mut self) {
-        self.0.iter_mut().for_each(|controller| controller.update());
+        self.controllers
+            .iter_mut()
+            .for_each(|controller| controller.update());
+
+        let held = self
+            .controllers
+            .iter()
+            .fold(StickDirections::default(), |held, controller| {
+                held.union(StickDirections::from_axes(
+                    controller.left_stick.x,
+                    controller.left_stick.y,
+                ))
+                .union(StickDirections::from_axes(
+                    controller.right_stick.x,
+                    controller.right_stick.y,
+                ))
+            });
+
+        self.sticks_pressed = held.newly_pressed(self.sticks_held);
+        self.sticks_held = held;
     }
 
     fn up(&self) -> bool {
-        self.0
-            .iter()
-            .any(|controller| controller.pressed_buttons.intersects(Buttons::up()))
+        self.sticks_pressed.up
+            || self
+                .controllers
+                .iter()
+                .any(|controller| controller.pressed_buttons.intersects(Buttons::up()))
     }
 
     fn down(&self) -> bool {
-        self.0
-            .iter()
-            .any(|controller| controller.pressed_buttons.intersects(Buttons::down()))
+        self.sticks_pressed.down
+            || self
+                .controllers
+                .iter()
+                .any(|controller| controller.pressed_buttons.intersects(Buttons::down()))
     }
 
     fn right(&self) -> bool {
-        self.0
-            .iter()
-            .any(|controller| controller.pressed_buttons.intersects(Buttons::right()))
+        self.sticks_pressed.right
+            || self
+                .controllers
+                .iter()
+                .any(|controller| controller.pressed_buttons.intersects(Buttons::right()))
     }
 
     fn left(&self) -> bool {
-        self.0
-            .iter()
-            .any(|controller| controller.pressed_buttons.intersects(Buttons::left()))
+        self.sticks_pressed.left
+            || self
+                .controllers
+                .iter()
+                .any(|controller| controller.pressed_buttons.intersects(Buttons::left()))
     }
 
     fn select(&self) -> bool {
-        self.0
+        self.controllers
             .iter()
             .any(|controller| controller.pressed_buttons.intersects(Buttons::A))
     }
 
     fn cancel(&self) -> bool {
-        self.0
+        self.controllers
             .iter()
             .any(|controller| controller.pressed_buttons.intersects(Buttons::B))
     }
 
     fn shoulder_r(&self) -> bool {
-        self.0
+        self.controllers
             .iter()
             .any(|controller| controller.pressed_buttons.intersects(Buttons::R))
     }
 
     fn shoulder_l(&self) -> bool {
-        self.0
+        self.controllers
             .iter()
             .any(|controller| controller.pressed_buttons.intersects(Buttons::L))
     }
 
+    fn plus(&self) -> bool {
+        self.controllers
+            .iter()
+            .any(|controller| controller.pressed_buttons.intersects(Buttons::PLUS))
+    }
+
     fn shoulder_r_down(&self) -> bool {
-        self.0
+        self.controllers
             .iter()
             .any(|controller| controller.buttons.intersects(Buttons::R))
     }
 
     fn shoulder_l_down(&self) -> bool {
-        self.0
+        self.controllers
             .iter()
             .any(|controller| controller.buttons.intersects(Buttons::L))
     }
@@ -279,6 +372,47 @@ enum MenuScene {
     Mods,
     Settings,
     Update,
+
+    // Fading between two scenes, nothing should respond to input
+    Transitioning,
+}
+
+/// Number of frames it takes to fade out of the current scene and into the next one
+const TRANSITION_FRAMES: f32 = 15.0;
+
+struct SceneTransition {
+    to: MenuScene,
+    progress: f32,
+    has_switched: bool,
+}
+
+impl SceneTransition {
+    fn to(scene: MenuScene) -> Self {
+        Self {
+            to: scene,
+            progress: 0.0,
+            has_switched: false,
+        }
+    }
+}
+
+fn set_scene_visibility(root: &mut LayoutTree<NvnBackend>, scene: MenuScene) {
+    let scene_nodes = [
+        ("Stratus/Main Menu", MenuScene::MainMenu),
+        ("Stratus/Mods", MenuScene::Mods),
+        ("Stratus/Settings", MenuScene::Settings),
+        ("Stratus/Update", MenuScene::Update),
+    ];
+
+    for (path, node_scene) in scene_nodes {
+        root.get_node_by_path_mut(path)
+            .unwrap()
+            .set_visibility(if node_scene == scene {
+                NodeVisibility::Inherited
+            } else {
+                NodeVisibility::Hidden
+            });
+    }
 }
 
 fn init_main_menu(
@@ -382,10 +516,36 @@ fn init_main_menu(
         });
 }
 
+/// How a mod is stored in the mods folder, the order of the variants is the order that
+/// [`ModSortOrder::Type`] sorts them in
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum ModKind {
+    Folder,
+    ZipFile,
+    TarGzFile,
+}
+
+impl ModKind {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Folder => "Mod Folder",
+            Self::ZipFile => "Compressed Zip File",
+            Self::TarGzFile => "Compressed Tarball",
+        }
+    }
+}
+
 struct ModListEntry {
     name: String,
+    /// The name from the mod's metadata file, only used for display since `name` is the key
+    /// for the enabled state
+    display_name: Option<String>,
+    discovery_index: usize,
     is_enabled: bool,
-    is_zip_file: bool,
+    kind: ModKind,
+    has_conflicts: bool,
+    /// The priority from the mod order, see [`ModOrder::priority`]
+    priority: usize,
     preview: (),
     authors: Vec<String>,
     version: Option<String>,
@@ -393,27 +553,95 @@ struct ModListEntry {
 }
 
 impl ModListEntry {
-    fn new(name: impl Into<String>) -> Self {
-        Self {
-            name: name.into(),
-            is_enabled: true,
-            is_zip_file: false,
-            preview: (),
-            authors: vec![],
-            version: None,
-            description: None,
+    fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Builds an entry for every mod folder, zip file and tarball in the mods folder, sorted by name
+    fn discover_from_stratus_folder() -> Vec<ModListEntry> {
+        let state = StratusState::load();
+        let mod_order = ModOrder::load();
+
+        let Ok(read_dir) = Utf8Path::new(MODS_FOLDER).read_dir_utf8() else {
+            log::warn!("[stratus::menu] Failed to read mods folder {MODS_FOLDER}");
+            return vec![];
+        };
+
+        let mut entries = vec![];
+        for entry in read_dir.flatten() {
+            if entry.file_name().starts_with(".") {
+                continue;
+            }
+
+            let Ok(ft) = entry.file_type() else {
+                continue;
+            };
+
+            let kind = if ft.is_dir() {
+                ModKind::Folder
+            } else if ft.is_file() && entry.file_name().ends_with(".zip") {
+                ModKind::ZipFile
+            } else if ft.is_file() && entry.file_name().ends_with(TAR_GZ_EXTENSION) {
+                ModKind::TarGzFile
+            } else {
+                continue;
+            };
+
+            let metadata = match kind {
+                ModKind::Folder => ModMetadata::read_from_folder(entry.path()),
+                ModKind::ZipFile => ModMetadata::read_from_zip(entry.path()),
+                // Only available once discovery has extracted it
+                ModKind::TarGzFile => ModMetadata::read_from_folder(
+                    &filesystem::tar_gz_extracted_path(Utf8Path::new(MODS_FOLDER), entry.path()),
+                ),
+            }
+            .unwrap_or_default();
+
+            entries.push(ModListEntry {
+                name: entry.file_name().to_string(),
+                display_name: metadata.name,
+                discovery_index: entries.len(),
+                is_enabled: StratusState::is_mod_enabled(state.as_ref(), entry.file_name()),
+                kind,
+                has_conflicts: false,
+                priority: mod_order.priority(entry.file_name()),
+                preview: (),
+                authors: metadata.authors,
+                version: metadata.version,
+                description: metadata.description,
+            });
+        }
+
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries
+    }
+}
+
+/// The order that mods are shown in the mods list, cycled with the + button
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ModSortOrder {
+    Name,
+    Type,
+    EnabledFirst,
+    DiscoveryOrder,
+}
+
+impl ModSortOrder {
+    fn next(self) -> Self {
+        match self {
+            Self::Name => Self::Type,
+            Self::Type => Self::EnabledFirst,
+            Self::EnabledFirst => Self::DiscoveryOrder,
+            Self::DiscoveryOrder => Self::Name,
         }
     }
 
-    fn zip(name: impl Into<String>) -> Self {
-        Self {
-            name: name.into(),
-            is_enabled: true,
-            is_zip_file: true,
-            preview: (),
-            authors: vec![],
-            version: None,
-            description: None,
+    fn label(self) -> &'static str {
+        match self {
+            Self::Name => "Sort: Name",
+            Self::Type => "Sort: Type",
+            Self::EnabledFirst => "Sort: Enabled",
+            Self::DiscoveryOrder => "Sort: Discovery",
         }
     }
 }
@@ -423,11 +651,53 @@ struct ModsList {
     scene: Rc<RefCell<MenuScene>>,
     root: LocalChannel<RootEvent>,
     entries: Vec<ModListEntry>,
+    sort_order: ModSortOrder,
     current_local: usize,
     current_page: usize,
     was_disabled_last: bool,
 }
 
+impl ModsList {
+    /// Sorts the entries by the provided order, falling back to the name for equal entries
+    fn sort_by(&mut self, order: ModSortOrder) {
+        self.sort_order = order;
+        self.entries.sort_by(|a, b| match order {
+            ModSortOrder::Name => a.name.cmp(&b.name),
+            // Folders first, then zip files, then tarballs
+            ModSortOrder::Type => a.kind.cmp(&b.kind).then_with(|| a.name.cmp(&b.name)),
+            ModSortOrder::EnabledFirst => b
+                .is_enabled
+                .cmp(&a.is_enabled)
+                .then_with(|| a.name.cmp(&b.name)),
+            ModSortOrder::DiscoveryOrder => a.discovery_index.cmp(&b.discovery_index),
+        });
+        self.current_local = 0;
+        self.current_page = 0;
+    }
+
+    /// Flags the entries that provide files that are also provided by another mod
+    ///
+    /// The menu can be opened before discovery has finished, in which case nothing is flagged
+    fn refresh_conflicts(&mut self) {
+        let Some(file_system) = ReadOnlyFileSystem::try_file_system() else {
+            return;
+        };
+
+        let conflicting = file_system
+            .conflicts()
+            .iter()
+            .flat_map(|conflict| {
+                std::iter::once(&conflict.winning_mod).chain(conflict.losing_mods.iter())
+            })
+            .filter_map(|path| path.file_name())
+            .collect::<HashSet<_>>();
+
+        for entry in self.entries.iter_mut() {
+            entry.has_conflicts = conflicting.contains(entry.name.as_str());
+        }
+    }
+}
+
 impl NodeUpdateCallback<NvnBackend> for ModsList {
     fn update(&mut self, node: NodeDisjointAccessor<'_, NvnBackend>) {
         if *self.scene.borrow() != MenuScene::Mods {
@@ -440,6 +710,12 @@ impl NodeUpdateCallback<NvnBackend> for ModsList {
             return;
         }
 
+        if self.controller.borrow().plus() {
+            self.sort_by(self.sort_order.next());
+            // Redraw the first page the same way as when the scene is opened
+            self.was_disabled_last = true;
+        }
+
         let child_names = [
             "mod_btn_01",
             "mod_btn_02",
@@ -452,6 +728,7 @@ impl NodeUpdateCallback<NvnBackend> for ModsList {
         let page_count = self.entries.len() / 6 + 1;
 
         if self.was_disabled_last {
+            self.refresh_conflicts();
             self.current_local = 0;
             self.current_page = 0;
             {
@@ -468,6 +745,15 @@ impl NodeUpdateCallback<NvnBackend> for ModsList {
                 sibling.mark_changed();
             }
 
+            // Layouts from before the sort indicator was added don't have it, sorting still works
+            // without it being shown
+            if let Some(mut sibling) = node.sibling_mut("mod_sort_indicator") {
+                if let Some(text) = sibling.downcast_mut::<TextNode<NvnBackend>>() {
+                    text.set_text(self.sort_order.label());
+                }
+                sibling.mark_changed();
+            }
+
             for (idx, name) in child_names.into_iter().enumerate() {
                 let mut child = node.child_mut(name).unwrap();
                 if idx >= self.entries.len() {
@@ -488,7 +774,14 @@ impl NodeUpdateCallback<NvnBackend> for ModsList {
                         .get_node_by_path_mut("mod_txt_name")
                         .unwrap()
                         .as_text_mut()
-                        .set_text(&self.entries[idx].name);
+                        .set_text(self.entries[idx].display_name());
+                    layout.get_node_by_path_mut("on").unwrap().set_visibility(
+                        if self.entries[idx].is_enabled {
+                            NodeVisibility::Inherited
+                        } else {
+                            NodeVisibility::Hidden
+                        },
+                    );
                     layout.play_animation_looping("select");
                 }
 
@@ -496,6 +789,12 @@ impl NodeUpdateCallback<NvnBackend> for ModsList {
             }
         }
 
+        // Nothing to navigate if there are no mods installed
+        if self.entries.is_empty() {
+            self.was_disabled_last = false;
+            return;
+        }
+
         let controller = self.controller.borrow();
 
         let mut new_page = self.current_page;
@@ -597,7 +896,7 @@ impl NodeUpdateCallback<NvnBackend> for ModsList {
                         .get_node_by_path_mut("mod_txt_name")
                         .unwrap()
                         .as_text_mut()
-                        .set_text(&self.entries[idx].name);
+                        .set_text(self.entries[idx].display_name());
                     layout.get_node_by_path_mut("on").unwrap().set_visibility(
                         if self.entries[idx].is_enabled {
                             NodeVisibility::Inherited
@@ -637,28 +936,34 @@ impl NodeUpdateCallback<NvnBackend> for ModsList {
                 .get_node_by_path_mut("Preview/mod_category_txt")
                 .unwrap()
                 .as_text_mut()
-                .set_text(if entry.is_zip_file {
-                    "Compressed Zip File"
-                } else {
-                    "Mod Folder"
-                });
+                .set_text(entry.kind.label());
             layout
                 .get_node_by_path_mut("Preview/mod_name_txt")
                 .unwrap()
                 .as_text_mut()
-                .set_text(&entry.name);
+                .set_text(entry.display_name());
             layout
                 .get_node_by_path_mut("txt_info/Info")
                 .unwrap()
                 .as_text_mut()
                 .set_text(format!(
-                    "Authors: {}\nVersion: {}",
+                    "Authors: {}\nVersion: {}\nPriority: {}{}",
                     if entry.authors.is_empty() {
                         "???".to_string()
                     } else {
                         entry.authors.join(", ")
                     },
-                    entry.version.as_deref().unwrap_or("???")
+                    entry.version.as_deref().unwrap_or("???"),
+                    if entry.priority == usize::MAX {
+                        "Default".to_string()
+                    } else {
+                        (entry.priority + 1).to_string()
+                    },
+                    if entry.has_conflicts {
+                        "\nConflicts with other mods"
+                    } else {
+                        ""
+                    }
                 ));
             layout
                 .get_node_by_path_mut("txt_info/Description")
@@ -671,6 +976,13 @@ impl NodeUpdateCallback<NvnBackend> for ModsList {
 
         if controller.select() {
             entry.is_enabled = !entry.is_enabled;
+            StratusState::from_disabled(
+                self.entries
+                    .iter()
+                    .filter(|entry| !entry.is_enabled)
+                    .map(|entry| entry.name.as_str()),
+            )
+            .save_in_background();
         }
 
         let mut showing = node.child_mut(child_names[self.current_local]).unwrap();
@@ -708,18 +1020,8 @@ fn init_mods(
             controller: controller.clone(),
             scene,
             root: channel,
-            entries: vec![
-                ModListEntry::zip("HDR-Skins"),
-                ModListEntry::zip("HDR-Stages"),
-                ModListEntry::zip("Ponytail Peach"),
-                ModListEntry::new("Thwomp Kirby (C08)"),
-                ModListEntry::new("Knuckles"),
-                ModListEntry::new("Colored Turnips"),
-                ModListEntry::zip("P5D Joker"),
-                ModListEntry::new("Octoling (C13)"),
-                ModListEntry::new("MP2 Dark Samus (C09)"),
-                ModListEntry::zip("Secret Sauce"),
-            ],
+            entries: ModListEntry::discover_from_stratus_folder(),
+            sort_order: ModSortOrder::Name,
             current_local: 0,
             current_page: 0,
             was_disabled_last: true,
@@ -798,6 +1100,18 @@ impl<T> LocalChannel<T> {
     fn recv(&self) -> Option<T> {
         self.0.borrow_mut().pop_front()
     }
+
+    /// Takes every pending event out of the channel
+    ///
+    /// The queue is emptied up front, so events sent while iterating are kept for the next drain
+    fn drain(&self) -> impl Iterator<Item = T> {
+        std::mem::take(&mut *self.0.borrow_mut()).into_iter()
+    }
+
+    #[allow(dead_code)]
+    fn peek(&self) -> Option<Ref<'_, T>> {
+        Ref::filter_map(self.0.borrow(), |queue| queue.front()).ok()
+    }
 }
 
 extern "C" fn menu_thread(_: *mut skyline::libc::c_void) {
@@ -904,6 +1218,7 @@ extern "C" fn menu_thread(_: *mut skyline::libc::c_void) {
         // layout.as_layout_mut().get_node_by_path_mut("Stratus/Background/bg_set").unwrap().as_sublayout_mut().as_layout_mut().play_animation("Idling");
 
         let scene = Rc::new(RefCell::new(MenuScene::MainMenu));
+        let mut transition: Option<SceneTransition> = None;
         let root_channel = LocalChannel::new();
         let controller =
             initialize_root(layout.as_layout_mut(), root_channel.clone(), scene.clone());
@@ -920,93 +1235,42 @@ extern "C" fn menu_thread(_: *mut skyline::libc::c_void) {
             layout.as_layout_mut().propagate();
             layout.prepare(&mut backend);
 
-            while let Some(event) = root_channel.recv() {
+            for event in root_channel.drain() {
                 match event {
                     RootEvent::Play => unsafe { SHOULD_SHUT_DOWN = true },
                     RootEvent::ShowMainMenu => {
-                        *scene.borrow_mut() = MenuScene::MainMenu;
-                        let layout = layout.as_layout_mut();
-                        layout
-                            .get_node_by_path_mut("Stratus/Main Menu")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Inherited);
-                        layout
-                            .get_node_by_path_mut("Stratus/Mods")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                        layout
-                            .get_node_by_path_mut("Stratus/Settings")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                        layout
-                            .get_node_by_path_mut("Stratus/Update")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                    }
-                    RootEvent::ShowMods => {
-                        *scene.borrow_mut() = MenuScene::Mods;
-                        let layout = layout.as_layout_mut();
-                        layout
-                            .get_node_by_path_mut("Stratus/Main Menu")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                        layout
-                            .get_node_by_path_mut("Stratus/Mods")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Inherited);
-                        layout
-                            .get_node_by_path_mut("Stratus/Settings")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                        layout
-                            .get_node_by_path_mut("Stratus/Update")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
+                        transition = Some(SceneTransition::to(MenuScene::MainMenu))
                     }
+                    RootEvent::ShowMods => transition = Some(SceneTransition::to(MenuScene::Mods)),
                     RootEvent::ShowSettings => {
-                        *scene.borrow_mut() = MenuScene::Settings;
-                        let layout = layout.as_layout_mut();
-                        layout
-                            .get_node_by_path_mut("Stratus/Main Menu")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                        layout
-                            .get_node_by_path_mut("Stratus/Mods")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                        layout
-                            .get_node_by_path_mut("Stratus/Settings")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Inherited);
-                        layout
-                            .get_node_by_path_mut("Stratus/Update")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
+                        transition = Some(SceneTransition::to(MenuScene::Settings))
                     }
                     RootEvent::ShowUpdate => {
-                        *scene.borrow_mut() = MenuScene::Update;
-                        let layout = layout.as_layout_mut();
-                        layout
-                            .get_node_by_path_mut("Stratus/Main Menu")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                        layout
-                            .get_node_by_path_mut("Stratus/Mods")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                        layout
-                            .get_node_by_path_mut("Stratus/Settings")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Hidden);
-                        layout
-                            .get_node_by_path_mut("Stratus/Update")
-                            .unwrap()
-                            .set_visibility(NodeVisibility::Inherited);
+                        transition = Some(SceneTransition::to(MenuScene::Update))
                     }
                     RootEvent::Quit => unsafe { skyline::nn::oe::ExitApplication() },
                 }
             }
 
+            if let Some(current) = transition.as_mut() {
+                *scene.borrow_mut() = MenuScene::Transitioning;
+                current.progress += 1.0 / TRANSITION_FRAMES;
+
+                // Swap which scene is visible once the outgoing one has fully faded out
+                if !current.has_switched && current.progress >= 0.5 {
+                    set_scene_visibility(layout.as_layout_mut(), current.to);
+                    current.has_switched = true;
+                }
+
+                if current.progress >= 1.0 {
+                    *scene.borrow_mut() = current.to;
+                    backend.set_opacity(1.0);
+                    transition = None;
+                } else {
+                    backend.set_opacity((current.progress * 2.0 - 1.0).abs());
+                }
+            }
+
             let stage = backend.stage();
 
             cmdbuf_sync.wait(u64::MAX);
@@ -52,6 +52,9 @@ pub struct NvnBackend {
     images: ManagedImages,
     envy_images: HashMap<nvn::TextureHandle, EnvyTextureInfo>,
     fonts: FontManager,
+    // Colors that envy last provided for each uniform, before opacity is applied
+    base_colors: Vec<Option<glam::Vec4>>,
+    opacity: f32,
 }
 
 impl NvnBackend {
@@ -76,10 +79,27 @@ impl NvnBackend {
             images: ManagedImages::new(device.clone(), 0x100),
             envy_images: HashMap::new(),
             fonts: FontManager::new(&device),
+            base_colors: vec![],
+            opacity: 1.0,
             device,
         }
     }
 
+    /// Sets the opacity that every draw is multiplied by, used for fading between menu scenes
+    pub fn set_opacity(&mut self, opacity: f32) {
+        if self.opacity == opacity {
+            return;
+        }
+
+        self.opacity = opacity;
+        for (idx, color) in self.base_colors.iter().enumerate() {
+            if let Some(color) = color {
+                self.draw_uniform[idx].base_color =
+                    *color * glam::Vec4::new(1.0, 1.0, 1.0, opacity);
+            }
+        }
+    }
+
     pub fn prepare_render(&self, cmdbuf: &mut nvn::CommandBuffer) {
         cmdbuf.set_texture_pool(self.images.texpool());
         cmdbuf.set_sampler_pool(self.images.sampool());
@@ -181,12 +201,20 @@ impl envy::EnvyBackend for NvnBackend {
 
     fn release_uniform(&mut self, handle: Self::UniformHandle) {
         self.draw_uniform_availability.set(handle.0, false);
+        if let Some(color) = self.base_colors.get_mut(handle.0) {
+            *color = None;
+        }
     }
 
     fn update_uniform(&mut self, handle: Self::UniformHandle, uniform: envy::DrawUniform) {
+        if self.base_colors.len() <= handle.0 {
+            self.base_colors.resize(handle.0 + 1, None);
+        }
+        self.base_colors[handle.0] = Some(uniform.color);
+
         self.draw_uniform[handle.0] = PerDrawCBuffer {
             world_matrix: uniform.model_matrix,
-            base_color: uniform.color,
+            base_color: uniform.color * glam::Vec4::new(1.0, 1.0, 1.0, self.opacity),
             world_inverse_matrix: uniform.model_i_matrix,
             padding: [0u8; 0x70],
         };
@@ -71,6 +71,53 @@ impl Language {
 #[skyline::from_offset(0x37404a0)]
 fn get_desired_language() -> u32;
 
+/// `nn::settings::LanguageCode`, a null padded IETF language tag like `en-US`
+#[repr(C)]
+struct LanguageCode([u8; 8]);
+
+unsafe extern "C" {
+    #[link_name = "_ZN2nn8settings15GetLanguageCodeEPNS0_12LanguageCodeE"]
+    unsafe fn nn_settings_get_language_code(out: &mut LanguageCode);
+}
+
+/// Reads the language set in the console's system settings, which is what the game uses unless
+/// the user has changed it in game
+pub fn get_locale_from_system() -> LocalePreferences {
+    let mut code = LanguageCode([0; 8]);
+    unsafe { nn_settings_get_language_code(&mut code) };
+    let len = code.0.iter().position(|b| *b == 0).unwrap_or(code.0.len());
+    let code = std::str::from_utf8(&code.0[..len]).unwrap_or("");
+
+    let (language, locale, region) = match code {
+        "ja" => (Language::Japanese, Locale::Japanese, Region::Japan),
+        "en-US" => (Language::English, Locale::UsEnglish, Region::NorthAmerica),
+        "en-GB" => (Language::English, Locale::EuEnglish, Region::Europe),
+        "fr-CA" => (Language::French, Locale::UsFrench, Region::NorthAmerica),
+        "fr" => (Language::French, Locale::EuFrench, Region::Europe),
+        "es-419" => (Language::Spanish, Locale::UsSpanish, Region::NorthAmerica),
+        "es" => (Language::Spanish, Locale::EuSpanish, Region::Europe),
+        "de" => (Language::German, Locale::German, Region::Europe),
+        "nl" => (Language::Dutch, Locale::Dutch, Region::Europe),
+        "it" => (Language::Italian, Locale::Italian, Region::Europe),
+        "ru" => (Language::Russian, Locale::Russian, Region::Europe),
+        "zh-CN" | "zh-Hans" => (Language::Chinese, Locale::Chinese, Region::China),
+        "zh-TW" | "zh-Hant" => (Language::Taiwanese, Locale::Taiwanese, Region::China),
+        "ko" => (Language::Korean, Locale::Korean, Region::China),
+        other => {
+            // Portuguese is the only system language the game doesn't support, it uses
+            // US English for it
+            log::warn!("[stratus::locale] Unsupported system language '{other}', using en-US");
+            (Language::English, Locale::UsEnglish, Region::NorthAmerica)
+        }
+    };
+
+    LocalePreferences {
+        region,
+        locale,
+        language,
+    }
+}
+
 #[allow(unreachable_code)]
 pub fn get_locale_from_user_save() -> LocalePreferences {
     const SAVE_REGION_OFFSET: usize = 0x3C6098;
@@ -82,6 +82,26 @@ impl ManagedMemoryPool {
     pub fn total_size(&self) -> usize {
         self.memory_layout.size()
     }
+
+    /// Maps a subrange of the memory backing this pool so that it can be rewritten in place,
+    /// instead of creating a new pool
+    ///
+    /// # Safety
+    /// The pool must have been created with CPU access (`CPU_CACHED` or `CPU_UNCACHED`), and the GPU
+    /// must not be reading from the range while it is being written. When the pool is `CPU_CACHED`,
+    /// the caller must flush the written range (e.g. with `nvn::Buffer::flush_mapped_range`) before
+    /// the GPU reads from it.
+    #[allow(dead_code)]
+    #[track_caller]
+    pub unsafe fn map_cpu_range_unchecked(&mut self, offset: usize, size: usize) -> &mut [u8] {
+        assert!(
+            offset + size <= self.total_size(),
+            "range {offset:#x}..{:#x} is outside of the pool ({:#x})",
+            offset + size,
+            self.total_size()
+        );
+        std::slice::from_raw_parts_mut(self.raw_ptr.as_ptr().add(offset), size)
+    }
 }
 
 impl Drop for ManagedMemoryPool {
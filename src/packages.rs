@@ -7,7 +7,7 @@ use crate::{
     containers::TableMut,
     data::{
         FileGroup, FileInfoFlags, FileLoadMethod, FilePackage, FilePackageChild, FilePackageFlags,
-        FilePath, IntoHash, SearchFolder, SearchPath,
+        FilePath, IntoHash, SearchFolder, SearchPath, SearchPathWalker,
     },
     HashDisplay, ReadOnlyFileSystem,
 };
@@ -52,10 +52,14 @@ fn duplicate_package_children_and_file_info(
         }
 
         let desc_idx = archive.push_file_desc(desc);
-        info.set_non_localized();
-        info.set_desc(desc_idx);
+        info.force_set_desc_and_clear_locale_flags(desc_idx);
         let flags = info.flags();
-        info.set_flags(flags | FileInfoFlags::IS_SHARED | FileInfoFlags::IS_UNKNOWN_FLAG);
+        info.set_flags(
+            flags
+                | FileInfoFlags::IS_SHARED
+                | FileInfoFlags::IS_UNKNOWN_FLAG
+                | FileInfoFlags::IS_CUSTOM_SLOT,
+        );
         archive.push_file_info(info);
     }
 
@@ -84,11 +88,10 @@ fn fetch_new_costume_folder_or_insert<'a>(
         let new_path_index = archive.insert_search_path(new_parent_path);
 
         let grandparent = archive.lookup_search_folder_mut(grandparent_path).unwrap();
-        let mut child = grandparent.first_child();
-        while !child.is_end() {
-            child = child.next();
-        }
-        child.set_next_index(new_path_index);
+        grandparent
+            .first_child()
+            .into_last()
+            .set_next_index(new_path_index);
         new_folder_index
     };
 
@@ -129,11 +132,10 @@ fn duplicate_fighter_costume_file_paths(
                 let new_search_path_idx =
                     new_folder.archive_mut().insert_search_path(new_search_path);
                 if new_folder.has_first_child() {
-                    let mut child = new_folder.first_child();
-                    while !child.is_end() {
-                        child = child.next();
-                    }
-                    child.set_next_index(new_search_path_idx);
+                    new_folder
+                        .first_child()
+                        .into_last()
+                        .set_next_index(new_search_path_idx);
                 } else {
                     new_folder.set_first_child_index(new_search_path_idx);
                 }
@@ -193,11 +195,10 @@ fn duplicate_fighter_costume_file_paths(
                 let parent = archive
                     .lookup_search_folder_mut(parent_path.path())
                     .unwrap();
-                let mut child = parent.first_child();
-                while !child.is_end() {
-                    child = child.next();
-                }
-                child.set_next_index(new_search_path_idx);
+                parent
+                    .first_child()
+                    .into_last()
+                    .set_next_index(new_search_path_idx);
                 new_search_path
             }
         } else {
@@ -253,7 +254,7 @@ fn duplicate_fighter_costume_child_package(
         0xFFFFFF,
     );
 
-    let mut new_data_group = FileGroup::new_for_new_package();
+    let mut new_data_group = FileGroup::new_for_new_package_default();
 
     let source_flags = source_package.flags();
     if source_flags.intersects(FilePackageFlags::HAS_SUB_PACKAGE) {
@@ -326,7 +327,7 @@ pub fn duplicate_fighter_costume_package(
         0xFFFFFF,
     );
 
-    let mut new_data_group = FileGroup::new_for_new_package();
+    let mut new_data_group = FileGroup::new_for_new_package_default();
 
     let source_flags = source_package.flags();
     if source_flags.intersects(FilePackageFlags::HAS_SUB_PACKAGE) {
@@ -384,6 +385,139 @@ pub fn duplicate_fighter_costume_package(
     archive.insert_file_package(new_package)
 }
 
+/// Creates a new, empty package at `<parent>/<name>`, without linking it to the parent package
+///
+/// The flags and search folder structure are mirrored from `template`, if there is one. The
+/// package must not already exist.
+fn create_empty_package(
+    archive: &mut Archive,
+    parent: Hash40,
+    name: Hash40,
+    template: Option<Hash40>,
+) -> u32 {
+    let new_path = parent.const_with("/").const_with_hash(name);
+    let mut new_package = FilePackage::new(new_path, name, parent, 0xFFFFFF);
+
+    if let Some(template) = template {
+        let template_flags = archive.lookup_file_package(template).unwrap().flags();
+        new_package.set_flags(
+            template_flags
+                & !(FilePackageFlags::IS_REGIONAL
+                    | FilePackageFlags::IS_LOCALIZED
+                    | FilePackageFlags::HAS_SUB_PACKAGE
+                    | FilePackageFlags::IS_SYM_LINK
+                    | FilePackageFlags::SYM_LINK_IS_REGIONAL),
+        );
+
+        if let Some(template_search_path) = archive.lookup_search_path(template).map(|path| *path) {
+            fetch_new_costume_folder_or_insert(archive, &template_search_path, name);
+        }
+    }
+
+    let (_, new_index) = archive.create_file_group_for_package(new_package);
+    new_index
+}
+
+/// Adds `children` to the end of the child package range of `parent`, if `parent` is a package
+///
+/// Child package ranges need to be contiguous, so the existing children have to be moved to the
+/// end of the table. This is done once for the whole batch, so that adding several packages to
+/// the same parent doesn't leave a stale copy of the range behind for each of them.
+fn append_child_packages(archive: &mut Archive, parent: Hash40, children: &[u32]) {
+    if children.is_empty() {
+        return;
+    }
+
+    let Some(root) = archive.lookup_file_package(parent) else {
+        return;
+    };
+
+    let child_range = root.child_packages().range();
+    let new_child_start = archive.num_file_package_child() as u32;
+    let new_child_count = child_range.len() as u32 + children.len() as u32;
+
+    for child in child_range {
+        let child = *archive.get_file_package_child(child).unwrap();
+        archive.push_file_package_child(child);
+    }
+
+    for child in children.iter().copied() {
+        let path = archive.get_file_package(child).unwrap().path();
+        archive.push_file_package_child(FilePackageChild::new(path, child));
+    }
+
+    archive
+        .lookup_file_package_mut(parent)
+        .unwrap()
+        .set_child_package_range(new_child_start, new_child_count);
+}
+
+/// Creates new, empty costume packages for a fighter at `fighter/<fighter>/cXX`, returning the
+/// index of each package in the same order as `slots`
+///
+/// Unlike [`duplicate_fighter_costume_package`], this does not copy any files from another costume.
+/// The flags and search folder structure of each package are mirrored from the highest existing
+/// costume slot below it, if there is one.
+pub fn create_fighter_packages(archive: &mut Archive, fighter: Hash40, slots: &[u8]) -> Vec<u32> {
+    let fighter_path = Hash40::const_new("fighter/").const_with_hash(fighter);
+
+    let mut new_children = vec![];
+    let indices = slots
+        .iter()
+        .map(|&slot| {
+            let new_costume = Hash40::const_new(&format!("c{slot:02}"));
+            let new_path = fighter_path.const_with("/").const_with_hash(new_costume);
+            if let Some(package) = archive.lookup_file_package(new_path) {
+                return package.index();
+            }
+
+            let source_path = (0..slot)
+                .rev()
+                .map(|slot| fighter_path.const_with(&format!("/c{slot:02}")))
+                .find(|path| archive.lookup_file_package(*path).is_some());
+
+            let new_index = create_empty_package(archive, fighter_path, new_costume, source_path);
+            new_children.push(new_index);
+            new_index
+        })
+        .collect();
+
+    append_child_packages(archive, fighter_path, &new_children);
+    indices
+}
+
+/// Creates new, empty stage packages at `<parent>/<name>`, returning the index of each package in
+/// the same order as `names`
+///
+/// Stage packages do not have slot-based variants, so the flags and search folder structure are
+/// mirrored from the first existing sibling package under the same parent, if there is one.
+pub fn create_stage_packages(archive: &mut Archive, parent: Hash40, names: &[Hash40]) -> Vec<u32> {
+    let sibling_path = archive.lookup_file_package(parent).and_then(|parent| {
+        parent
+            .child_packages()
+            .get_local(0)
+            .map(|child| child.path())
+    });
+
+    let mut new_children = vec![];
+    let indices = names
+        .iter()
+        .map(|&name| {
+            let new_path = parent.const_with("/").const_with_hash(name);
+            if let Some(package) = archive.lookup_file_package(new_path) {
+                return package.index();
+            }
+
+            let new_index = create_empty_package(archive, parent, name, sibling_path);
+            new_children.push(new_index);
+            new_index
+        })
+        .collect();
+
+    append_child_packages(archive, parent, &new_children);
+    indices
+}
+
 pub fn retarget_files(
     archive: &mut Archive,
     package: impl IntoHash,
@@ -393,24 +527,21 @@ pub fn retarget_files(
     let package = package.into_hash();
     let parent = parent.into_hash();
     let new_parent = new_parent.into_hash();
-    let parent_folder = archive.lookup_search_folder_mut(parent).unwrap();
+    let parent_folder = archive.lookup_search_folder(parent).unwrap();
 
-    let mut child = parent_folder.first_child();
-    while !child.is_end() {
-        let name = child.name();
-        let target_entity = child
-            .archive()
+    let children = SearchPathWalker::new(parent_folder.first_child())
+        .map(|child| (child.path(), child.name()))
+        .collect::<Vec<_>>();
+    for (path, name) in children {
+        let target_entity = archive
             .lookup_file_path(new_parent.const_with("/").const_with_hash(name))
             .unwrap()
             .entity()
             .index();
-        let path = child.path();
-        child
-            .archive_mut()
+        archive
             .lookup_file_path_mut(path)
             .unwrap()
             .set_entity(target_entity);
-        child = child.next();
     }
 
     let package = archive.lookup_file_package(package).unwrap();
@@ -436,3 +567,136 @@ pub fn retarget_files(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{FileEntity, FileInfo};
+
+    fn hash(path: &str) -> Hash40 {
+        Hash40::const_new(path)
+    }
+
+    fn insert_root_package(archive: &mut Archive, path: &str, name: &str, parent: &str) -> u32 {
+        let package = FilePackage::new(hash(path), hash(name), hash(parent), 0xFFFFFF);
+        archive.create_file_group_for_package(package).1
+    }
+
+    fn child_paths(archive: &Archive, parent: &str) -> Vec<Hash40> {
+        archive
+            .lookup_file_package(hash(parent))
+            .unwrap()
+            .child_packages()
+            .into_iter()
+            .map(|child| child.path())
+            .collect()
+    }
+
+    #[test]
+    fn create_fighter_package_above_c07() {
+        let mut archive = Archive::empty();
+        insert_root_package(&mut archive, "fighter/mario", "mario", "fighter");
+        archive.create_fighter_package(hash("mario"), 7);
+
+        let index = archive.create_fighter_package(hash("mario"), 8);
+
+        let package = archive.lookup_file_package("fighter/mario/c08").unwrap();
+        assert_eq!(index, package.index());
+        assert_eq!(package.name(), hash("c08"));
+        assert_eq!(package.parent(), hash("fighter/mario"));
+        assert!(package.info_range().is_empty());
+        assert_eq!(package.info_range().start as usize, archive.num_file_info());
+        assert!(package.infos().is_empty());
+
+        assert_eq!(
+            child_paths(&archive, "fighter/mario"),
+            [hash("fighter/mario/c07"), hash("fighter/mario/c08")]
+        );
+    }
+
+    #[test]
+    fn create_fighter_package_twice_returns_existing() {
+        let mut archive = Archive::empty();
+        insert_root_package(&mut archive, "fighter/mario", "mario", "fighter");
+
+        let first = create_fighter_packages(&mut archive, hash("mario"), &[8]);
+        let child_count = archive.num_file_package_child();
+        let second = create_fighter_packages(&mut archive, hash("mario"), &[8]);
+
+        assert_eq!(first, second);
+        assert_eq!(archive.num_file_package_child(), child_count);
+    }
+
+    #[test]
+    fn create_stage_packages_relinks_parent_once() {
+        let mut archive = Archive::empty();
+        insert_root_package(&mut archive, "stage/battlefield", "battlefield", "stage");
+        create_stage_packages(&mut archive, hash("stage/battlefield"), &[hash("normal")]);
+        let child_count = archive.num_file_package_child();
+
+        let names = [hash("battle"), hash("end"), hash("custom")];
+        let indices = create_stage_packages(&mut archive, hash("stage/battlefield"), &names);
+
+        assert_eq!(indices.len(), names.len());
+        // The one existing child is moved once, then the three new ones are added after it
+        assert_eq!(
+            archive.num_file_package_child(),
+            child_count + 1 + names.len()
+        );
+        assert_eq!(
+            child_paths(&archive, "stage/battlefield"),
+            [
+                hash("stage/battlefield/normal"),
+                hash("stage/battlefield/battle"),
+                hash("stage/battlefield/end"),
+                hash("stage/battlefield/custom"),
+            ]
+        );
+    }
+
+    #[test]
+    fn create_stage_package_then_reserialize() {
+        let mut archive = Archive::empty();
+        let [package_idx] =
+            create_stage_packages(&mut archive, hash("stage/battlefield"), &[hash("custom")])[..]
+        else {
+            panic!("expected one package");
+        };
+
+        let data_group = archive
+            .get_file_package(package_idx)
+            .unwrap()
+            .data_group()
+            .index();
+        let entity = archive.push_file_entity(FileEntity::new(data_group, 0xFFFFFF));
+        let path = archive.insert_file_path(FilePath::from_parts(
+            hash("stage/battlefield/custom/model.numdlb"),
+            hash("stage/battlefield/custom"),
+            hash("model.numdlb"),
+            hash("numdlb"),
+            entity,
+        ));
+        let info_start = archive.num_file_info() as u32;
+        archive.push_file_info(FileInfo::new(
+            path,
+            entity,
+            0,
+            FileInfoFlags::IS_CUSTOM_SLOT,
+        ));
+        archive
+            .get_file_package_mut(package_idx)
+            .unwrap()
+            .set_info_range(info_start, 1);
+
+        archive.reserialize();
+
+        let package = archive
+            .lookup_file_package("stage/battlefield/custom")
+            .unwrap();
+        assert_eq!(package.index(), package_idx);
+        assert_eq!(package.info_range(), info_start..info_start + 1);
+        assert!(archive
+            .lookup_file_path("stage/battlefield/custom/model.numdlb")
+            .is_some());
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    ffi::c_void,
+    sync::{Mutex, OnceLock},
+};
+
+use smash_hash::{Hash40, Hash40Map};
+
+use crate::HashDisplay;
+
+/// A third-party library that wants to observe or provide file data without hooking the
+/// same offsets as stratus
+pub trait StratusPlugin: Send + Sync {
+    /// Called after a replacement file has been read into the game's buffer, the buffer
+    /// can be modified in place but not resized
+    fn on_file_load(&self, path: Hash40, data: &mut [u8]);
+
+    /// Called before a replacement file is read from the SD card, returning [`Some`] will
+    /// use the returned data instead.
+    ///
+    /// The returned data must be the same size as the file on the SD card, since the game
+    /// has already been told how big the file is
+    fn on_file_query(&self, path: Hash40) -> Option<Box<[u8]>>;
+}
+
+static PLUGINS: OnceLock<Mutex<Vec<Box<dyn StratusPlugin>>>> = OnceLock::new();
+
+fn plugins() -> &'static Mutex<Vec<Box<dyn StratusPlugin>>> {
+    PLUGINS.get_or_init(|| Mutex::new(vec![]))
+}
+
+pub fn register_plugin(plugin: Box<dyn StratusPlugin>) {
+    plugins().lock().unwrap().push(plugin);
+}
+
+/// Asks each plugin, in registration order, if they want to provide the data for this file
+pub fn query_file(path: Hash40) -> Option<Box<[u8]>> {
+    // Avoid initializing the list from the loading threads if nobody has registered anything
+    let plugins = PLUGINS.get()?.lock().unwrap();
+    plugins.iter().find_map(|plugin| plugin.on_file_query(path))
+}
+
+pub fn notify_file_load(path: Hash40, data: &mut [u8]) {
+    let Some(plugins) = PLUGINS.get() else {
+        return;
+    };
+
+    for plugin in plugins.lock().unwrap().iter() {
+        plugin.on_file_load(path, data);
+    }
+}
+
+/// Transforms a file's data in place, returning the size of the transformed data
+///
+/// The size has to stay the same, see [`transform_file`]
+pub type TransformCallback = extern "C" fn(*mut u8, usize) -> usize;
+
+static TRANSFORMS: OnceLock<Mutex<Hash40Map<TransformCallback>>> = OnceLock::new();
+
+/// Registers a callback that can modify `hash`'s data after it has been read from the SD card but
+/// before it is handed to the game, returning `false` if the file already has a transform
+pub fn register_transform_callback(hash: Hash40, callback: TransformCallback) -> bool {
+    let mut transforms = TRANSFORMS
+        .get_or_init(|| Mutex::new(Hash40Map::default()))
+        .lock()
+        .unwrap();
+    if transforms.contains_key(&hash) {
+        return false;
+    }
+
+    transforms.insert(hash, callback);
+    true
+}
+
+/// Runs the transform registered for this file, if there is one
+///
+/// The game has already been told how big the file is, so transforms can't change the size of a
+/// file. The callback runs on a copy of `data`, and if it reports a different size than it was
+/// given the transform is rejected and `data` is left untouched.
+pub fn transform_file(path: Hash40, data: &mut [u8]) {
+    let Some(transforms) = TRANSFORMS.get() else {
+        return;
+    };
+
+    let Some(callback) = transforms.lock().unwrap().get(&path).copied() else {
+        return;
+    };
+
+    let mut transformed = data.to_vec();
+    let new_size = callback(transformed.as_mut_ptr(), transformed.len());
+    if new_size != data.len() {
+        log::warn!(
+            "[stratus::plugins] Rejecting transform for {}, it returned {new_size:#x} bytes but the file is {:#x} bytes",
+            path.display(),
+            data.len()
+        );
+        return;
+    }
+
+    data.copy_from_slice(&transformed);
+}
+
+/// Resolves the package that a new file belongs to from the components of its path, where the
+/// first component is the root the handler was registered for
+pub type NewFileRootHandler = fn(components: &[&str]) -> Option<Hash40>;
+
+static NEW_FILE_ROOT_HANDLERS: OnceLock<Mutex<Vec<(&'static str, NewFileRootHandler)>>> =
+    OnceLock::new();
+
+/// Registers a handler for new files under `root`, this has to be called before the game runs
+/// `initial_loading` for the handler to be used
+pub fn register_new_file_root_handler(root: &'static str, handler: NewFileRootHandler) {
+    NEW_FILE_ROOT_HANDLERS
+        .get_or_init(|| Mutex::new(vec![]))
+        .lock()
+        .unwrap()
+        .push((root, handler));
+}
+
+/// Asks each handler registered for the file's root, in registration order, which package the
+/// new file should be added to
+pub fn new_file_package(components: &[&str]) -> Option<Hash40> {
+    let root = *components.first()?;
+    let handlers = NEW_FILE_ROOT_HANDLERS.get()?.lock().unwrap();
+    handlers
+        .iter()
+        .filter(|(handler_root, _)| *handler_root == root)
+        .find_map(|(_, handler)| handler(components))
+}
+
+/// C compatible version of [`StratusPlugin`] for plugins that aren't written in Rust
+#[repr(C)]
+pub struct StratusPluginCallbacks {
+    pub user_data: *mut c_void,
+    pub on_file_load: Option<extern "C" fn(*mut c_void, u64, *mut u8, usize)>,
+    /// Returns a pointer to the replacement data and writes its length to the last argument,
+    /// or returns null to not replace the file
+    pub on_file_query: Option<extern "C" fn(*mut c_void, u64, *mut usize) -> *mut u8>,
+    /// Called with the buffer returned from `on_file_query` once it has been copied
+    pub free_buffer: Option<extern "C" fn(*mut c_void, *mut u8, usize)>,
+}
+
+// SAFETY: The plugin registering the callbacks is responsible for `user_data` being usable
+// from the loading threads
+unsafe impl Send for StratusPluginCallbacks {}
+unsafe impl Sync for StratusPluginCallbacks {}
+
+impl StratusPlugin for StratusPluginCallbacks {
+    fn on_file_load(&self, path: Hash40, data: &mut [u8]) {
+        if let Some(callback) = self.on_file_load {
+            callback(self.user_data, path.raw(), data.as_mut_ptr(), data.len());
+        }
+    }
+
+    fn on_file_query(&self, path: Hash40) -> Option<Box<[u8]>> {
+        let callback = self.on_file_query?;
+        let mut len = 0usize;
+        let ptr = callback(self.user_data, path.raw(), &mut len);
+        if ptr.is_null() {
+            return None;
+        }
+
+        let data = unsafe { std::slice::from_raw_parts(ptr, len) }.into();
+        if let Some(free) = self.free_buffer {
+            free(self.user_data, ptr, len);
+        }
+
+        Some(data)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn stratus_register_plugin(callbacks: StratusPluginCallbacks) {
+    register_plugin(Box::new(callbacks));
+}
+
+#[no_mangle]
+pub extern "C" fn stratus_register_transform_callback(
+    hash: u64,
+    callback: TransformCallback,
+) -> bool {
+    register_transform_callback(Hash40::from_raw(hash), callback)
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashSet,
+    sync::{mpsc::Sender, Mutex, OnceLock},
+};
+
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+
+use crate::stratus_folder;
+
+/// User state that persists between boots, stored as JSON so that it can be edited by hand
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StratusState {
+    /// Names of the mod folders/zip files (relative to the mods folder) that are disabled
+    ///
+    /// Disabled mods are stored instead of enabled ones so that newly added mods start enabled
+    #[serde(default)]
+    pub disabled_mods: HashSet<String>,
+}
+
+/// Sends states to the thread that writes them, see [`StratusState::save_in_background`]
+static SAVE_QUEUE: OnceLock<Mutex<Sender<StratusState>>> = OnceLock::new();
+
+impl StratusState {
+    pub fn path() -> Utf8PathBuf {
+        stratus_folder().join(".stratus_state.json")
+    }
+
+    fn temp_path() -> Utf8PathBuf {
+        stratus_folder().join(".stratus_state.json.tmp")
+    }
+
+    /// Loads the state file, returning [`None`] if it does not exist or cannot be parsed
+    ///
+    /// Falls back to the temporary file if a save was interrupted before it replaced the state file
+    pub fn load() -> Option<Self> {
+        let mut path = Self::path();
+        if !path.exists() {
+            path = Self::temp_path();
+        }
+
+        let contents = std::fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&contents) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                log::warn!("[stratus::state] Failed to parse {path}: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn from_disabled<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            disabled_mods: names.into_iter().map(str::to_string).collect(),
+        }
+    }
+
+    /// Writes the state to a temporary file before moving it over the state file, so that a
+    /// partial write never replaces a good state file
+    ///
+    /// The SD card can't rename over an existing file, so the old file is removed first. If we
+    /// stop in between, [`Self::load`] picks up the temporary file.
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        let temp_path = Self::temp_path();
+        std::fs::write(&temp_path, contents)?;
+
+        match std::fs::remove_file(Self::path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+
+        std::fs::rename(temp_path, Self::path())
+    }
+
+    /// Saves the state file on a separate thread so that the caller does not block on SD card IO
+    ///
+    /// Every save goes through the same thread, which only writes the newest state when several
+    /// are queued, so saves never race each other.
+    pub fn save_in_background(self) {
+        let queue = SAVE_QUEUE.get_or_init(|| {
+            let (sender, receiver) = std::sync::mpsc::channel::<Self>();
+            std::thread::spawn(move || {
+                while let Ok(state) = receiver.recv() {
+                    let state = receiver.try_iter().last().unwrap_or(state);
+                    if let Err(e) = state.save() {
+                        log::warn!("[stratus::state] Failed to save {}: {e}", Self::path());
+                    }
+                }
+            });
+            Mutex::new(sender)
+        });
+
+        let _ = queue.lock().unwrap().send(self);
+    }
+
+    /// Returns whether the mod is enabled, mods are enabled unless the state file disables them
+    pub fn is_mod_enabled(this: Option<&Self>, name: &str) -> bool {
+        match this {
+            Some(state) => !state.disabled_mods.contains(name),
+            None => true,
+        }
+    }
+}
+
+/// Load priority for mods that provide the same file, read from a text file with one mod
+/// folder/zip file name per line where earlier lines win. Blank lines and lines starting with `#`
+/// are ignored.
+#[derive(Debug, Default, Clone)]
+pub struct ModOrder {
+    names: Vec<String>,
+}
+
+impl ModOrder {
+    pub fn path() -> Utf8PathBuf {
+        stratus_folder().join("mod_order.txt")
+    }
+
+    /// Loads the mod order file, every mod is unlisted if it does not exist
+    pub fn load() -> Self {
+        let Ok(contents) = std::fs::read_to_string(Self::path()) else {
+            return Self::default();
+        };
+
+        Self {
+            names: contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// The priority of the mod, lower values win conflicts and unlisted mods are [`usize::MAX`]
+    pub fn priority(&self, name: &str) -> usize {
+        self.names
+            .iter()
+            .position(|listed| listed == name)
+            .unwrap_or(usize::MAX)
+    }
+}